@@ -0,0 +1,243 @@
+use chrono::Utc;
+use lambda_http::{http::header::AUTHORIZATION, request::RequestContext, Request};
+use nanoserde::DeJson;
+use ring::hmac;
+
+use crate::error::{ApiError, Result};
+
+/// The user a request is made on behalf of.
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub id: String,
+}
+
+/// Works out who is calling, either from what an API Gateway authorizer
+/// already verified or from an HS256 signed JWT in the `Authorization` header.
+pub struct Authenticator {
+    jwt_key: Option<hmac::Key>,
+}
+
+#[derive(DeJson)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(DeJson)]
+struct JwtClaims {
+    sub: String,
+    #[nserde(default_with = "Option::default")]
+    exp: Option<i64>,
+    #[nserde(default_with = "Option::default")]
+    nbf: Option<i64>,
+}
+
+impl Authenticator {
+    /// Bearer tokens are only accepted when a `jwt_secret` is configured.
+    pub fn new(jwt_secret: Option<&str>) -> Self {
+        let jwt_key = jwt_secret.map(|secret| hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()));
+        Self { jwt_key }
+    }
+
+    pub fn caller(&self, request: &Request) -> Result<Caller> {
+        if let Some(id) = authorizer_principal(request) {
+            return Ok(Caller { id });
+        }
+
+        let token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| unauthorized("Missing bearer token"))?;
+        let key = self
+            .jwt_key
+            .as_ref()
+            .ok_or_else(|| unauthorized("Bearer tokens are not accepted"))?;
+
+        let claims = verify(key, token.trim())?;
+        if claims.sub.is_empty() {
+            return Err(unauthorized("Token has no subject"));
+        }
+        Ok(Caller { id: claims.sub })
+    }
+}
+
+/// The `sub` claim of a Cognito user pool authorizer, or the `principalId`
+/// returned by a Lambda authorizer.
+fn authorizer_principal(request: &Request) -> Option<String> {
+    let Some(RequestContext::ApiGatewayV1(context)) = request.extensions().get::<RequestContext>()
+    else {
+        return None;
+    };
+    context
+        .authorizer
+        .get("claims")
+        .and_then(|claims| claims.get("sub"))
+        .or_else(|| context.authorizer.get("principalId"))
+        .and_then(|principal| principal.as_str())
+        .filter(|principal| !principal.is_empty())
+        .map(String::from)
+}
+
+fn verify(key: &hmac::Key, token: &str) -> Result<JwtClaims> {
+    let (signed, signature) = token
+        .rsplit_once('.')
+        .ok_or_else(|| unauthorized("Malformed token"))?;
+    let (header, claims) = signed
+        .split_once('.')
+        .filter(|(_, claims)| !claims.contains('.'))
+        .ok_or_else(|| unauthorized("Malformed token"))?;
+
+    let header: JwtHeader = decode_part(header)?;
+    if header.alg != "HS256" {
+        return Err(unauthorized("Unsupported token algorithm"));
+    }
+
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| unauthorized("Malformed token"))?;
+    hmac::verify(key, signed.as_bytes(), &signature)
+        .map_err(|_| unauthorized("Invalid token signature"))?;
+
+    let claims: JwtClaims = decode_part(claims)?;
+    let now = Utc::now().timestamp();
+    let exp = claims
+        .exp
+        .ok_or_else(|| unauthorized("Token has no expiry"))?;
+    if exp <= now {
+        return Err(unauthorized("Token has expired"));
+    }
+    if claims.nbf.is_some_and(|nbf| nbf > now) {
+        return Err(unauthorized("Token is not valid yet"));
+    }
+    Ok(claims)
+}
+
+fn decode_part<T: DeJson>(part: &str) -> Result<T> {
+    base64::decode_config(part, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|json| DeJson::deserialize_json(&json).ok())
+        .ok_or_else(|| unauthorized("Malformed token"))
+}
+
+fn unauthorized(message: &str) -> ApiError {
+    ApiError::Unauthorized(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use lambda_http::{aws_lambda_events::apigw::ApiGatewayProxyRequestContext, Body, RequestExt};
+
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    fn token(secret: &str, alg: &str, claims: &str) -> String {
+        let encode = |part: &str| base64::encode_config(part, base64::URL_SAFE_NO_PAD);
+        let signed = format!(
+            "{}.{}",
+            encode(&format!(r#"{{"alg": "{}", "typ": "JWT"}}"#, alg)),
+            encode(claims)
+        );
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let signature = hmac::sign(&key, signed.as_bytes());
+        format!(
+            "{}.{}",
+            signed,
+            base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    fn claims(exp: i64) -> String {
+        format!(
+            r#"{{"sub": "alice", "exp": {}}}"#,
+            Utc::now().timestamp() + exp
+        )
+    }
+
+    fn bearer(token: &str) -> Request {
+        lambda_http::http::Request::builder()
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::Empty)
+            .expect("request is valid")
+    }
+
+    fn caller(token: &str) -> Result<String> {
+        let authenticator = Authenticator::new(Some(SECRET));
+        authenticator.caller(&bearer(token)).map(|caller| caller.id)
+    }
+
+    fn rejection(token: &str) -> String {
+        match caller(token) {
+            Err(ApiError::Unauthorized(message)) => message,
+            other => panic!(
+                "expected a 401, got {:?}",
+                other.map_err(|err| err.to_string())
+            ),
+        }
+    }
+
+    #[test]
+    fn accepts_a_token_with_a_good_signature() {
+        assert_eq!(
+            caller(&token(SECRET, "HS256", &claims(60))).unwrap(),
+            "alice"
+        );
+    }
+
+    #[test]
+    fn rejects_a_token_with_a_bad_signature() {
+        let token = token("another secret", "HS256", &claims(60));
+        assert_eq!(rejection(&token), "Invalid token signature");
+    }
+
+    #[test]
+    fn rejects_an_algorithm_other_than_hs256() {
+        let token = token(SECRET, "none", &claims(60));
+        assert_eq!(rejection(&token), "Unsupported token algorithm");
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let token = token(SECRET, "HS256", &claims(-60));
+        assert_eq!(rejection(&token), "Token has expired");
+    }
+
+    #[test]
+    fn rejects_a_token_without_an_expiry() {
+        let token = token(SECRET, "HS256", r#"{"sub": "alice"}"#);
+        assert_eq!(rejection(&token), "Token has no expiry");
+    }
+
+    #[test]
+    fn rejects_a_token_that_is_not_valid_yet() {
+        let now = Utc::now().timestamp();
+        let claims = format!(
+            r#"{{"sub": "alice", "exp": {}, "nbf": {}}}"#,
+            now + 120,
+            now + 60
+        );
+        let token = token(SECRET, "HS256", &claims);
+        assert_eq!(rejection(&token), "Token is not valid yet");
+    }
+
+    #[test]
+    fn takes_the_caller_from_the_authorizer() {
+        let authenticator = Authenticator::new(None);
+        let request = |key: &str, value: serde_json::Value| {
+            let mut context = ApiGatewayProxyRequestContext::default();
+            context.authorizer.insert(key.to_string(), value);
+            bearer("ignored").with_request_context(RequestContext::ApiGatewayV1(context))
+        };
+
+        let cognito = request("claims", serde_json::json!({ "sub": "alice" }));
+        assert_eq!(authenticator.caller(&cognito).unwrap().id, "alice");
+        let lambda = request("principalId", serde_json::json!("bob"));
+        assert_eq!(authenticator.caller(&lambda).unwrap().id, "bob");
+        let empty = request("principalId", serde_json::json!(""));
+        assert!(matches!(
+            authenticator.caller(&empty),
+            Err(ApiError::Unauthorized(_))
+        ));
+    }
+}
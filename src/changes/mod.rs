@@ -0,0 +1,480 @@
+//! Decodes the notes table's stream records about documents, groups and
+//! notes and hands them to every `ChangeProcessor`.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use async_trait::async_trait;
+use aws_lambda_events::{
+    dynamodb::{attributes::AttributeValue as StreamValue, Event, EventRecord},
+    event::streams::{DynamoDbBatchItemFailure, DynamoDbEventResponse},
+};
+use aws_sdk_dynamodb::{model::AttributeValue, types::Blob};
+
+use crate::{
+    error::{ApiError, Result},
+    repositories::{document_key, entity_of, group_key, is_trashed, Item},
+    services::{get_s, id_of, Document, Group, Note, PK, SK},
+};
+
+pub mod processors;
+
+/// Who the stream names as the remover of items purged by the table's TTL.
+const TTL_PRINCIPAL: &str = "dynamodb.amazonaws.com";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Inserted,
+    Modified,
+    Removed,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            ChangeKind::Inserted => "inserted",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Removed => "removed",
+        };
+        f.write_str(kind)
+    }
+}
+
+#[derive(Clone)]
+pub enum Entity {
+    Document(Document),
+    Group(Group),
+    Note(Note),
+}
+
+impl Entity {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Entity::Document(_) => "document",
+            Entity::Group(_) => "group",
+            Entity::Note(_) => "note",
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        match self {
+            Entity::Document(document) => &document.id,
+            Entity::Group(group) => &group.id,
+            Entity::Note(note) => &note.id,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        match self {
+            Entity::Document(document) => document.version,
+            Entity::Group(group) => group.version,
+            Entity::Note(note) => note.version,
+        }
+    }
+
+    /// Decodes the image of the item stored under `sk`, which the stream
+    /// leaves empty for the side of a change that has none.
+    fn from_image(sk: &str, image: Item) -> Result<Option<Self>> {
+        if image.is_empty() {
+            return Ok(None);
+        }
+        let entity = match entity_of(sk) {
+            "Note" => Entity::Note(Note::try_from(&image)?),
+            "Group" => Entity::Group(Group::try_from(&image)?),
+            _ => Entity::Document(Document::try_from(image)?),
+        };
+        Ok(Some(entity))
+    }
+}
+
+/// A document, group or note as it was before and after a write. Groups and
+/// notes come with the ids leading to them, which their images lack.
+#[derive(Clone)]
+pub struct Change {
+    /// The table the stream belongs to, when the record names it.
+    pub table: Option<String>,
+    pub kind: ChangeKind,
+    pub document_id: String,
+    pub group_id: Option<String>,
+    pub old: Option<Entity>,
+    pub new: Option<Entity>,
+    /// Whether the item is in the trash after the write, so processors can
+    /// tell a delete from an edit.
+    pub trashed: bool,
+    /// Whether the table's TTL removed the item, purging it from the trash.
+    pub expired: bool,
+}
+
+impl Change {
+    /// Decodes a stream record, or returns `None` when it is not about a
+    /// document, group or note.
+    pub fn from_record(record: &EventRecord) -> Result<Option<Self>> {
+        let keys = item(&record.change.keys);
+        let (pk, sk) = (get_s(&keys, PK)?, get_s(&keys, SK)?);
+        if !pk.starts_with(&document_key(""))
+            || !(sk.starts_with(&document_key("")) || sk.starts_with(&group_key("")))
+        {
+            return Ok(None);
+        }
+
+        let kind = match record.event_name.as_str() {
+            "INSERT" => ChangeKind::Inserted,
+            "MODIFY" => ChangeKind::Modified,
+            "REMOVE" => ChangeKind::Removed,
+            other => {
+                return Err(ApiError::BadRequest(format!(
+                    "Unknown stream event '{}'",
+                    other
+                )))
+            }
+        };
+        let new_image = item(&record.change.new_image);
+        let expired = record.user_identity.as_ref().is_some_and(|identity| {
+            identity.type_ == "Service" && identity.principal_id == TTL_PRINCIPAL
+        });
+        Ok(Some(Change {
+            table: table_of(record),
+            kind,
+            document_id: id_of(&pk).to_string(),
+            group_id: sk
+                .starts_with(&group_key(""))
+                .then(|| sk.split('#').nth(1).map(String::from))
+                .flatten(),
+            old: Entity::from_image(&sk, item(&record.change.old_image))?,
+            trashed: is_trashed(&new_image),
+            new: Entity::from_image(&sk, new_image)?,
+            expired,
+        }))
+    }
+
+    /// The entity as it is after the change, or as it was before a remove.
+    pub fn entity(&self) -> Option<&Entity> {
+        self.new.as_ref().or(self.old.as_ref())
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(entity) = self.entity() else {
+            return write!(f, "{} item in document {}", self.kind, self.document_id);
+        };
+        write!(f, "{} {} {}", self.kind, entity.kind(), entity.id())?;
+        if !matches!(entity, Entity::Document(_)) {
+            write!(f, " in document {}", self.document_id)?;
+        }
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => {
+                write!(f, ", version {} -> {}", old.version(), new.version())?
+            }
+            (_, Some(new)) | (Some(new), None) => write!(f, ", version {}", new.version())?,
+            (None, None) => {}
+        }
+        if self.trashed {
+            f.write_str(", in the trash")?;
+        }
+        if self.expired {
+            f.write_str(", expired")?;
+        }
+        Ok(())
+    }
+}
+
+/// Something done with every change to the notes table. Batches are retried,
+/// so processors have to be idempotent.
+#[async_trait]
+pub trait ChangeProcessor: Send + Sync {
+    /// Names the processor in the logs.
+    fn name(&self) -> &'static str;
+
+    async fn process(&self, change: &Change) -> Result<()>;
+}
+
+/// Dispatches the records of a stream event to the processors, in order.
+pub struct StreamHandler {
+    processors: Vec<Box<dyn ChangeProcessor>>,
+}
+
+impl StreamHandler {
+    pub fn new(processors: Vec<Box<dyn ChangeProcessor>>) -> Self {
+        Self { processors }
+    }
+
+    /// Processes the records in order and reports the first one that fails, so
+    /// Lambda retries the batch from there.
+    pub async fn handle(&self, event: &Event) -> DynamoDbEventResponse {
+        for record in event.records.iter() {
+            let change = match Change::from_record(record) {
+                Ok(Some(change)) => change,
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::error!("cannot decode stream record {}: {}", record.event_id, err);
+                    return failed_at(record);
+                }
+            };
+
+            for processor in self.processors.iter() {
+                if let Err(err) = processor.process(&change).await {
+                    tracing::error!(
+                        "{} failed on stream record {}: {}",
+                        processor.name(),
+                        record.event_id,
+                        err
+                    );
+                    return failed_at(record);
+                }
+            }
+        }
+        DynamoDbEventResponse {
+            batch_item_failures: Vec::new(),
+        }
+    }
+}
+
+fn failed_at(record: &EventRecord) -> DynamoDbEventResponse {
+    DynamoDbEventResponse {
+        batch_item_failures: vec![DynamoDbBatchItemFailure {
+            item_identifier: record.change.sequence_number.clone(),
+        }],
+    }
+}
+
+/// Names the table of a record, which the stream's ARN carries as
+/// `...:table/<name>/stream/<label>`.
+fn table_of(record: &EventRecord) -> Option<String> {
+    if let Some(table) = &record.table_name {
+        return Some(table.clone());
+    }
+    let (_, path) = record.event_source_arn.as_deref()?.split_once(":table/")?;
+    path.split('/').next().map(String::from)
+}
+
+/// Converts an image from the stream's JSON into the item the SDK would have
+/// read, so the domain types decode it the usual way.
+fn item(image: &HashMap<String, StreamValue>) -> Item {
+    image
+        .iter()
+        .map(|(name, value)| (name.clone(), attribute(value)))
+        .collect()
+}
+
+fn attribute(value: &StreamValue) -> AttributeValue {
+    match value {
+        StreamValue::Null => AttributeValue::Null(true),
+        StreamValue::String(value) => AttributeValue::S(value.clone()),
+        StreamValue::Number(value) => AttributeValue::N(value.to_string()),
+        StreamValue::Boolean(value) => AttributeValue::Bool(*value),
+        StreamValue::Binary(value) => AttributeValue::B(Blob::new(value.clone())),
+        StreamValue::StringSet(values) => AttributeValue::Ss(values.clone()),
+        StreamValue::NumberSet(values) => {
+            AttributeValue::Ns(values.iter().map(|value| value.to_string()).collect())
+        }
+        StreamValue::BinarySet(values) => AttributeValue::Bs(
+            values
+                .iter()
+                .map(|value| Blob::new(value.clone()))
+                .collect(),
+        ),
+        StreamValue::AttributeList(values) => {
+            AttributeValue::L(values.iter().map(attribute).collect())
+        }
+        StreamValue::AttributeMap(values) => AttributeValue::M(item(values)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    const DOCUMENT_ID: &str = "01M56TT7190B8SZASTY0EGNVRD";
+    const GROUP_ID: &str = "01M56TT7198W6YEHH57KW5MZ53";
+    const NOTE_ID: &str = "01M56TT71ES6WPKVYX167G9GX5";
+
+    fn fixture(json: &str) -> Event {
+        serde_json::from_str(json).expect("fixture is a stream event")
+    }
+
+    fn insert_document() -> Event {
+        fixture(include_str!("../../fixtures/streams/insert_document.json"))
+    }
+
+    fn modify_note() -> Event {
+        fixture(include_str!("../../fixtures/streams/modify_note.json"))
+    }
+
+    fn trash_group() -> Event {
+        fixture(include_str!("../../fixtures/streams/trash_group.json"))
+    }
+
+    fn expire_group() -> Event {
+        fixture(include_str!("../../fixtures/streams/expire_group.json"))
+    }
+
+    fn changes(event: &Event) -> Vec<Option<Change>> {
+        event
+            .records
+            .iter()
+            .map(|record| Change::from_record(record).expect("record decodes"))
+            .collect()
+    }
+
+    /// Keeps every change it is handed, or fails on all of them.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        changes: Arc<Mutex<Vec<Change>>>,
+        fail: bool,
+    }
+
+    impl Recorder {
+        fn seen(&self) -> Vec<String> {
+            let changes = self.changes.lock().expect("recorder is not poisoned");
+            changes.iter().map(Change::to_string).collect()
+        }
+    }
+
+    #[async_trait]
+    impl ChangeProcessor for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        async fn process(&self, change: &Change) -> Result<()> {
+            if self.fail {
+                return Err(ApiError::BadRequest(String::from("failing on purpose")));
+            }
+            let mut changes = self.changes.lock().expect("recorder is not poisoned");
+            changes.push(change.clone());
+            Ok(())
+        }
+    }
+
+    fn failures(response: &DynamoDbEventResponse) -> Vec<Option<&str>> {
+        response
+            .batch_item_failures
+            .iter()
+            .map(|failure| failure.item_identifier.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_an_inserted_document_and_its_group() {
+        let changes = changes(&insert_document());
+        assert_eq!(changes.len(), 4);
+        assert!(changes[2..].iter().all(Option::is_none));
+
+        let document = changes[0].as_ref().expect("document is a change");
+        assert_eq!(document.table.as_deref(), Some("notes"));
+        assert_eq!(document.kind, ChangeKind::Inserted);
+        assert_eq!(document.document_id, DOCUMENT_ID);
+        assert_eq!(document.group_id, None);
+        assert!(document.old.is_none());
+        assert!(matches!(document.entity(), Some(Entity::Document(d)) if d.id == DOCUMENT_ID));
+
+        let group = changes[1].as_ref().expect("group is a change");
+        assert_eq!(group.kind, ChangeKind::Inserted);
+        assert_eq!(group.group_id.as_deref(), Some(GROUP_ID));
+        assert!(matches!(group.entity(), Some(Entity::Group(g)) if g.id == GROUP_ID));
+    }
+
+    #[test]
+    fn decodes_a_modified_note() {
+        let changes = changes(&modify_note());
+        assert!(changes[1].is_none());
+
+        let note = changes[0].as_ref().expect("note is a change");
+        assert_eq!(note.table.as_deref(), Some("notes"));
+        assert_eq!(note.kind, ChangeKind::Modified);
+        assert_eq!(note.group_id.as_deref(), Some(GROUP_ID));
+        assert_eq!(note.old.as_ref().map(Entity::version), Some(3));
+        assert_eq!(note.new.as_ref().map(Entity::version), Some(4));
+        assert!(matches!(note.entity(), Some(Entity::Note(n)) if n.id == NOTE_ID));
+        assert!(!note.trashed);
+    }
+
+    #[test]
+    fn tells_a_trashed_group_from_an_expired_one() {
+        let trashed = changes(&trash_group())
+            .remove(0)
+            .expect("group is a change");
+        assert_eq!(trashed.table.as_deref(), Some("notes"));
+        assert_eq!(trashed.kind, ChangeKind::Modified);
+        assert!(trashed.trashed);
+        assert!(!trashed.expired);
+
+        let expired = changes(&expire_group())
+            .remove(0)
+            .expect("group is a change");
+        assert_eq!(expired.table.as_deref(), Some("notes"));
+        assert_eq!(expired.kind, ChangeKind::Removed);
+        assert!(expired.new.is_none());
+        assert!(expired.expired);
+        assert!(matches!(expired.entity(), Some(Entity::Group(g)) if g.id == GROUP_ID));
+    }
+
+    #[tokio::test]
+    async fn hands_only_documents_groups_and_notes_to_the_processors() {
+        let recorder = Recorder::default();
+        let handler = StreamHandler::new(vec![Box::new(recorder.clone())]);
+
+        for event in [
+            insert_document(),
+            modify_note(),
+            trash_group(),
+            expire_group(),
+        ] {
+            let response = handler.handle(&event).await;
+            assert!(response.batch_item_failures.is_empty());
+        }
+        assert_eq!(
+            recorder.seen(),
+            [
+                format!("inserted document {}, version 1", DOCUMENT_ID),
+                format!(
+                    "inserted group {} in document {}, version 1",
+                    GROUP_ID, DOCUMENT_ID
+                ),
+                format!(
+                    "modified note {} in document {}, version 3 -> 4",
+                    NOTE_ID, DOCUMENT_ID
+                ),
+                format!(
+                    "modified group {} in document {}, version 1 -> 2, in the trash",
+                    GROUP_ID, DOCUMENT_ID
+                ),
+                format!(
+                    "removed group {} in document {}, version 2, expired",
+                    GROUP_ID, DOCUMENT_ID
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_the_record_a_processor_fails_on() {
+        let recorder = Recorder::default();
+        let failing = Recorder {
+            fail: true,
+            ..Recorder::default()
+        };
+        let handler = StreamHandler::new(vec![Box::new(failing), Box::new(recorder.clone())]);
+
+        let response = handler.handle(&insert_document()).await;
+        assert_eq!(failures(&response), [Some("4127000000000012345678901")]);
+        assert!(recorder.seen().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_a_record_that_does_not_decode() {
+        let mut event = insert_document();
+        event.records[1].event_name = String::from("UPDATE");
+        let recorder = Recorder::default();
+        let handler = StreamHandler::new(vec![Box::new(recorder.clone())]);
+
+        let response = handler.handle(&event).await;
+        assert_eq!(failures(&response), [Some("4127000000000012345678902")]);
+        assert_eq!(recorder.seen().len(), 1);
+    }
+}
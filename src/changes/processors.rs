@@ -0,0 +1,20 @@
+use async_trait::async_trait;
+
+use crate::error::Result;
+
+use super::{Change, ChangeProcessor};
+
+/// Logs a line per change.
+pub struct LogProcessor;
+
+#[async_trait]
+impl ChangeProcessor for LogProcessor {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn process(&self, change: &Change) -> Result<()> {
+        tracing::info!("{}", change);
+        Ok(())
+    }
+}
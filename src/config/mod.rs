@@ -1,12 +1,187 @@
+use std::{env, fmt, time::Duration};
+
 use aws_config::SdkConfig;
-use aws_sdk_dynamodb::Endpoint;
+use aws_sdk_dynamodb::{Endpoint, Region};
 use lambda_http::http::Uri;
+use tracing::Level;
+
+/// Settings read from the environment once, at cold start, so a missing or
+/// malformed variable stops the function before it serves any request.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub store: Store,
+    /// `RUST_LOG`, one of `trace`, `debug`, `info`, `warn` or `error`.
+    pub log_level: Level,
+    /// `LOG_REQUESTS=true` logs every incoming event.
+    pub log_requests: bool,
+    /// Verifies HS256 bearer tokens. Unset, such tokens are refused; empty is
+    /// rejected as a misconfiguration.
+    pub jwt_secret: Option<String>,
+    /// `TRASH_RETENTION_DAYS`, 30 unless set: how long deleted documents,
+    /// groups and notes can be restored before they are purged for good.
+    pub trash_retention: Duration,
+}
+
+/// Where notes are kept, picked with `NOTES_STORE`.
+#[derive(Debug, Clone)]
+pub enum Store {
+    /// `NOTES_STORE=dynamodb`, the default.
+    DynamoDb(DynamoDbConfig),
+    /// `NOTES_STORE=memory` keeps everything in this process, so the function
+    /// can be run offline without a database container.
+    Memory,
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamoDbConfig {
+    /// `DYNAMODB_ENDPOINT`, only set to reach something other than AWS, such
+    /// as DynamoDB Local on `http://172.17.0.1:8000`.
+    pub endpoint: Option<Uri>,
+    /// `REGION_NAME`, or the `AWS_REGION` Lambda sets when that is missing.
+    pub region: String,
+    /// `TABLE_NAME`
+    pub table_name: String,
+}
+
+pub enum ConfigError {
+    Missing(&'static str),
+    Invalid {
+        name: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing(name) => write!(f, "environment variable {} must be set", name),
+            ConfigError::Invalid {
+                name,
+                value,
+                expected,
+            } => write!(
+                f,
+                "environment variable {} is '{}', expected {}",
+                name, value, expected
+            ),
+        }
+    }
+}
+
+// `main` reports errors it returns with `Debug`, so keep that readable too.
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl AppConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let store = match var("NOTES_STORE").as_deref() {
+            None | Some("dynamodb") => Store::DynamoDb(DynamoDbConfig::from_env()?),
+            Some("memory") => Store::Memory,
+            Some(value) => return Err(invalid("NOTES_STORE", value, "dynamodb or memory")),
+        };
+
+        let log_level = match var("RUST_LOG") {
+            Some(value) => value.parse().map_err(|_| {
+                invalid(
+                    "RUST_LOG",
+                    &value,
+                    "one of trace, debug, info, warn or error",
+                )
+            })?,
+            None => Level::INFO,
+        };
+
+        let log_requests = match var("LOG_REQUESTS").as_deref() {
+            None | Some("false") => false,
+            Some("true") => true,
+            Some(value) => return Err(invalid("LOG_REQUESTS", value, "true or false")),
+        };
+
+        let jwt_secret = match env::var("JWT_SECRET") {
+            Ok(value) if value.is_empty() => {
+                return Err(invalid(
+                    "JWT_SECRET",
+                    &value,
+                    "a secret, or the variable left unset behind an authorizer",
+                ))
+            }
+            Ok(value) => Some(value),
+            Err(_) => None,
+        };
+
+        let retention_days = match var("TRASH_RETENTION_DAYS") {
+            Some(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|days| *days > 0)
+                .ok_or_else(|| invalid("TRASH_RETENTION_DAYS", &value, "a number of days"))?,
+            None => 30,
+        };
+
+        Ok(Self {
+            store,
+            log_level,
+            log_requests,
+            jwt_secret,
+            trash_retention: Duration::from_secs(retention_days * 24 * 60 * 60),
+        })
+    }
+}
+
+impl DynamoDbConfig {
+    fn from_env() -> Result<Self, ConfigError> {
+        let endpoint = match var("DYNAMODB_ENDPOINT") {
+            Some(value) => Some(
+                value
+                    .parse::<Uri>()
+                    .ok()
+                    .filter(|uri| uri.scheme().is_some() && uri.authority().is_some())
+                    .ok_or_else(|| {
+                        invalid(
+                            "DYNAMODB_ENDPOINT",
+                            &value,
+                            "an absolute URL like http://localhost:8000",
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+        let region = var("REGION_NAME")
+            .or_else(|| var("AWS_REGION"))
+            .ok_or(ConfigError::Missing("REGION_NAME"))?;
+        let table_name = var("TABLE_NAME").ok_or(ConfigError::Missing("TABLE_NAME"))?;
+
+        Ok(Self {
+            endpoint,
+            region,
+            table_name,
+        })
+    }
+
+    pub async fn load_sdk_config(&self) -> SdkConfig {
+        let mut loader = aws_config::from_env().region(Region::new(self.region.clone()));
+        if let Some(endpoint) = &self.endpoint {
+            loader = loader.endpoint_resolver(Endpoint::immutable(endpoint.clone()));
+        }
+        loader.load().await
+    }
+}
+
+/// Reads a variable, treating an empty value the same as an unset one.
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
 
-pub async fn load_config() -> SdkConfig {
-    aws_config::from_env()
-        .endpoint_resolver(Endpoint::immutable(Uri::from_static(
-            "http://172.17.0.1:8000",
-        )))
-        .load()
-        .await
+fn invalid(name: &'static str, value: &str, expected: &'static str) -> ConfigError {
+    ConfigError::Invalid {
+        name,
+        value: value.to_string(),
+        expected,
+    }
 }
@@ -1,33 +1,192 @@
-use lambda_http::{http::Result, Body, Response};
+use std::sync::Arc;
 
-use crate::{repositories::document_repository::DatabaseRepository, services::Documents};
+use crate::{
+    auth::Caller,
+    error::{ApiError, Result},
+    repositories::{document_key, search_index::DOCUMENT_ID, NotesStore, Page, TagChange},
+    services::{
+        change_tags, ensure_role, ensure_version, fetch_accessible_document, get_s, id_of,
+        share_of, shared_scopes, tagged_entries, DocumentPage, Documents, Role, Scope, Tags, SK,
+    },
+};
 
-use super::DocumentReq;
+use super::{decode_cursor, encode_cursor, DocumentListReq, DocumentReq, DocumentUpdateReq};
 
-pub struct DocumentController<'a> {
-    database_repository: &'a DatabaseRepository,
+pub struct DocumentController {
+    database_repository: Arc<dyn NotesStore>,
 }
 
-impl<'a> DocumentController<'a> {
-    pub fn new(database_repository: &'a DatabaseRepository) -> Self {
+impl DocumentController {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
         Self {
             database_repository,
         }
     }
 
-    pub(crate) async fn list_all(&self) -> Documents {
-        let items = self.database_repository.list_all().await;
-        Documents::from(items)
+    pub(crate) async fn list(
+        &self,
+        caller: &Caller,
+        req: &DocumentListReq,
+    ) -> Result<DocumentPage> {
+        let after = req.cursor.as_deref().map(decode_cursor).transpose()?;
+        let page = if let Some(tag) = &req.tag {
+            self.list_tagged(caller, tag, req, after.as_deref()).await?
+        } else if req.shared {
+            self.database_repository
+                .list_shared_documents(&caller.id, req.limit, after.as_deref())
+                .await?
+        } else {
+            self.database_repository
+                .list_documents(&caller.id, req.limit, after.as_deref())
+                .await?
+        };
+
+        let items = if req.expand {
+            let mut items = Vec::new();
+            for document in page.items.iter() {
+                let sk = get_s(document, SK)?;
+                match self.database_repository.fetch_by_id(id_of(&sk)).await {
+                    Ok(mut partition) => items.append(&mut partition),
+                    // Deleted since it was listed.
+                    Err(ApiError::NotFound(_)) => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+            items
+        } else {
+            page.items
+        };
+
+        let documents = Documents::try_from(items)?;
+        Ok(DocumentPage::new(
+            documents,
+            page.next.as_deref().map(encode_cursor),
+        ))
+    }
+
+    /// Pages through the documents carrying the tag in the tag index, the
+    /// caller's own or, with `shared`, those shared with the caller.
+    async fn list_tagged(
+        &self,
+        caller: &Caller,
+        tag: &str,
+        req: &DocumentListReq,
+        after: Option<&str>,
+    ) -> Result<Page> {
+        let store = self.database_repository.as_ref();
+        let scopes = if req.shared {
+            shared_scopes(store, caller).await?
+        } else {
+            vec![Scope::own(caller)]
+        };
+        let mut after = after.map(document_key);
+        let mut items = Vec::new();
+        let mut next = None;
+        'entries: loop {
+            let entries = tagged_entries(
+                store,
+                &scopes,
+                tag,
+                "document",
+                after.as_deref(),
+                req.limit + 1,
+            )
+            .await?;
+            let exhausted = entries.len() <= req.limit;
+            for entry in entries.iter() {
+                if items.len() == req.limit {
+                    next = items
+                        .last()
+                        .map(|document| get_s(document, SK))
+                        .transpose()?
+                        .map(|sk| id_of(&sk).to_string());
+                    break 'entries;
+                }
+                after = Some(get_s(entry, SK)?);
+                match store
+                    .fetch_document_by_id(&get_s(entry, DOCUMENT_ID)?)
+                    .await
+                {
+                    Ok(document) => items.push(document),
+                    // Deleted since it was listed.
+                    Err(ApiError::NotFound(_)) => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+            if exhausted {
+                break;
+            }
+        }
+        Ok(Page { items, next })
+    }
+
+    pub(crate) async fn fetch_by_id(&self, caller: &Caller, id: &str) -> Result<Documents> {
+        let items = self.database_repository.fetch_by_id(id).await?;
+        let document_sk = document_key(id);
+        let document = items
+            .iter()
+            .find(|item| get_s(item, SK).is_ok_and(|sk| sk == document_sk))
+            .ok_or_else(|| ApiError::not_found("Document", id))?;
+        ensure_role(document, share_of(&items, caller), caller, id, Role::Viewer)?;
+        Documents::try_from(items)
+    }
+
+    pub(crate) async fn save(&self, caller: &Caller, document: &DocumentReq) -> Result<Documents> {
+        let items = self.database_repository.save(document, &caller.id).await?;
+        Documents::try_from(items)
+    }
+
+    pub(crate) async fn update(
+        &self,
+        caller: &Caller,
+        id: &str,
+        update: &DocumentUpdateReq,
+        if_match: Option<u64>,
+    ) -> Result<Documents> {
+        fetch_accessible_document(self.database_repository.as_ref(), caller, id, Role::Editor)
+            .await?;
+        self.database_repository
+            .update_document(id, update, &caller.id, if_match)
+            .await?;
+        self.fetch_by_id(caller, id).await
+    }
+
+    pub(crate) async fn change_tags(
+        &self,
+        caller: &Caller,
+        id: &str,
+        change: TagChange<'_>,
+        if_match: Option<u64>,
+    ) -> Result<Tags> {
+        let store = self.database_repository.as_ref();
+        let document = fetch_accessible_document(store, caller, id, Role::Editor).await?;
+        change_tags(store, &document, change, caller, if_match).await
     }
 
-    pub(crate) async fn fetch_by_id(&self, id: &str) -> Documents {
-        let item = self.database_repository.fetch_by_id(id).await;
-        Documents::from(item)
+    pub(crate) async fn delete(
+        &self,
+        caller: &Caller,
+        id: &str,
+        if_match: Option<u64>,
+    ) -> Result<()> {
+        let document =
+            fetch_accessible_document(self.database_repository.as_ref(), caller, id, Role::Owner)
+                .await?;
+        ensure_version(&document, if_match, "Document", id)?;
+        self.database_repository
+            .trash(id, &document_key(id), &caller.id, if_match)
+            .await
     }
 
-    pub(crate) async fn save(&self, document: &DocumentReq) -> Result<Response<Body>> {
-        self.database_repository.save(document).await.unwrap();
-        let response = Response::builder().status(200).body(Body::Empty).unwrap();
-        Ok(response)
+    /// Takes a document its owner deleted back out of the trash, with all of
+    /// its groups and notes.
+    pub(crate) async fn restore(&self, caller: &Caller, id: &str) -> Result<Documents> {
+        let key = document_key(id);
+        let document = self.database_repository.fetch_trashed(id, &key).await?;
+        ensure_role(&document, None, caller, id, Role::Owner)?;
+        self.database_repository
+            .restore(id, &key, &caller.id)
+            .await?;
+        self.fetch_by_id(caller, id).await
     }
 }
@@ -1,54 +1,392 @@
+use lambda_http::aws_lambda_events::query_map::QueryMap;
 use nanoserde::{DeJson, SerJson};
 
+use crate::{
+    error::{ApiError, Result},
+    repositories::search_index::tokenize,
+};
+
+use self::validation::normalize_tag;
+
 pub mod document_controller;
+pub mod validation;
+
+/// Query parameters of `GET /documents`: `limit`, `cursor`, `expand`,
+/// `shared` and `tag`.
+pub struct DocumentListReq {
+    pub limit: usize,
+    pub cursor: Option<String>,
+    pub expand: bool,
+    pub shared: bool,
+    pub tag: Option<String>,
+}
+
+impl DocumentListReq {
+    const DEFAULT_LIMIT: usize = 20;
+    const MAX_LIMIT: usize = 100;
+
+    pub fn from_query(query: &QueryMap) -> Result<Self> {
+        Ok(Self {
+            limit: limit(query, Self::DEFAULT_LIMIT, Self::MAX_LIMIT)?,
+            cursor: query.first("cursor").map(String::from),
+            expand: flag(query, "expand", true)?,
+            shared: flag(query, "shared", false)?,
+            tag: query.first("tag").map(tag_param).transpose()?,
+        })
+    }
+}
+
+/// Query parameters of `GET /notes`: `tag`, `limit` and `cursor`.
+pub struct NoteListReq {
+    pub tag: String,
+    pub limit: usize,
+    pub cursor: Option<String>,
+}
+
+impl NoteListReq {
+    const DEFAULT_LIMIT: usize = 20;
+    const MAX_LIMIT: usize = 100;
+
+    pub fn from_query(query: &QueryMap) -> Result<Self> {
+        let tag = query
+            .first("tag")
+            .ok_or_else(|| ApiError::BadRequest(String::from("tag is required")))?;
+        Ok(Self {
+            tag: tag_param(tag)?,
+            limit: limit(query, Self::DEFAULT_LIMIT, Self::MAX_LIMIT)?,
+            cursor: query.first("cursor").map(String::from),
+        })
+    }
+}
+
+/// Query parameters of `GET /trash`: `limit` and `cursor`.
+pub struct TrashListReq {
+    pub limit: usize,
+    pub cursor: Option<String>,
+}
+
+impl TrashListReq {
+    const DEFAULT_LIMIT: usize = 20;
+    const MAX_LIMIT: usize = 100;
+
+    pub fn from_query(query: &QueryMap) -> Result<Self> {
+        Ok(Self {
+            limit: limit(query, Self::DEFAULT_LIMIT, Self::MAX_LIMIT)?,
+            cursor: query.first("cursor").map(String::from),
+        })
+    }
+}
+
+/// Query parameters of `GET .../revisions`: `limit` and `cursor` paging like
+/// in `GET /documents`.
+pub struct RevisionListReq {
+    pub limit: usize,
+    pub cursor: Option<String>,
+}
+
+impl RevisionListReq {
+    const DEFAULT_LIMIT: usize = 20;
+    const MAX_LIMIT: usize = 100;
+
+    pub fn from_query(query: &QueryMap) -> Result<Self> {
+        Ok(Self {
+            limit: limit(query, Self::DEFAULT_LIMIT, Self::MAX_LIMIT)?,
+            cursor: query.first("cursor").map(String::from),
+        })
+    }
+}
+
+/// Query parameters of `GET /documents/:id/activity`: `limit` and `cursor`
+/// paging like in `GET /documents`.
+pub struct ActivityListReq {
+    pub limit: usize,
+    pub cursor: Option<String>,
+}
+
+impl ActivityListReq {
+    const DEFAULT_LIMIT: usize = 50;
+    const MAX_LIMIT: usize = 100;
+
+    pub fn from_query(query: &QueryMap) -> Result<Self> {
+        Ok(Self {
+            limit: limit(query, Self::DEFAULT_LIMIT, Self::MAX_LIMIT)?,
+            cursor: query.first("cursor").map(String::from),
+        })
+    }
+}
+
+/// Query parameters of `GET /search`: the words to look for in `q`, all of
+/// which a hit must contain, and at most `limit` hits.
+pub struct SearchReq {
+    pub tokens: Vec<String>,
+    pub limit: usize,
+}
+
+impl SearchReq {
+    const DEFAULT_LIMIT: usize = 20;
+    const MAX_LIMIT: usize = 100;
+    const MAX_TOKENS: usize = 8;
+
+    pub fn from_query(query: &QueryMap) -> Result<Self> {
+        let tokens = tokenize(query.first("q").unwrap_or_default());
+        if tokens.is_empty() {
+            return Err(ApiError::BadRequest(String::from(
+                "q must hold at least one word of two or more letters or digits",
+            )));
+        }
+        if tokens.len() > Self::MAX_TOKENS {
+            return Err(ApiError::BadRequest(format!(
+                "q must hold at most {} words",
+                Self::MAX_TOKENS
+            )));
+        }
+        Ok(Self {
+            tokens,
+            limit: limit(query, Self::DEFAULT_LIMIT, Self::MAX_LIMIT)?,
+        })
+    }
+}
+
+fn limit(query: &QueryMap, default: usize, max: usize) -> Result<usize> {
+    match query.first("limit") {
+        Some(limit) => limit
+            .parse()
+            .ok()
+            .filter(|limit| (1..=max).contains(limit))
+            .ok_or_else(|| {
+                ApiError::BadRequest(format!("limit must be a number from 1 to {}", max))
+            }),
+        None => Ok(default),
+    }
+}
+
+/// A tag given in a path or query, lower-cased like the stored ones.
+pub fn tag_param(tag: &str) -> Result<String> {
+    normalize_tag(tag).ok_or_else(|| {
+        ApiError::BadRequest(format!(
+            "Tag '{}' is not 1 to {} letters, digits, '-' or '_'",
+            tag,
+            validation::TAG_MAX_LEN
+        ))
+    })
+}
+
+/// Cursors are the key of the last entry on the previous page, encoded so
+/// clients treat them as opaque.
+pub(crate) fn encode_cursor(key: &str) -> String {
+    base64::encode_config(key, base64::URL_SAFE_NO_PAD)
+}
+
+pub(crate) fn decode_cursor(cursor: &str) -> Result<String> {
+    base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| ApiError::BadRequest(String::from("Invalid cursor")))
+}
+
+fn flag(query: &QueryMap, name: &str, default: bool) -> Result<bool> {
+    match query.first(name) {
+        None => Ok(default),
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(_) => Err(ApiError::BadRequest(format!(
+            "{} must be true or false",
+            name
+        ))),
+    }
+}
 
 #[derive(Clone, SerJson, DeJson)]
 pub struct DocumentReq {
+    #[nserde(default)]
     pub title: String,
 
     #[nserde(default)]
-    pub updated_by: String,
+    pub description: String,
 
     #[nserde(default)]
-    pub description: String,
+    pub tags: Vec<String>,
 
     #[nserde(default)]
     pub groups: Vec<GroupReq>,
 }
 
+/// Partial update of a document. Fields left out of the payload are kept as
+/// they are; any `groups` supplied are added to the document.
 #[derive(Clone, SerJson, DeJson)]
-pub struct GroupReq {
-    pub title: String,
+pub struct DocumentUpdateReq {
+    // Optional fields name their default so the derived `DeJson` does not
+    // write the `else { None }` that clippy asks to turn into a `?`.
+    #[nserde(default_with = "Option::default")]
+    pub title: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub description: Option<String>,
 
     #[nserde(default)]
-    pub description: String,
+    pub groups: Vec<GroupReq>,
+}
 
+#[derive(Clone, SerJson, DeJson)]
+pub struct GroupReq {
     #[nserde(default)]
-    pub created: i64,
+    pub title: String,
 
     #[nserde(default)]
-    pub created_by: String,
+    pub description: String,
 
     #[nserde(default)]
-    pub updated_by: String,
+    pub tags: Vec<String>,
 
     #[nserde(default)]
     pub notes: Vec<NoteReq>,
 }
 
+/// Partial update of a group. Fields left out of the payload are kept as
+/// they are.
+#[derive(Clone, SerJson, DeJson)]
+pub struct GroupUpdateReq {
+    #[nserde(default_with = "Option::default")]
+    pub title: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub description: Option<String>,
+}
+
 #[derive(Clone, SerJson, DeJson)]
 pub struct NoteReq {
+    #[nserde(default)]
     pub title: String,
 
     #[nserde(default)]
     pub description: String,
 
+    #[nserde(default_with = "Option::default")]
+    pub content: Option<NoteContent>,
+
     #[nserde(default)]
-    pub created: i64,
+    pub tags: Vec<String>,
+}
+
+/// Partial update of a note. Fields left out of the payload are kept as they
+/// are.
+#[derive(Clone, SerJson, DeJson)]
+pub struct NoteUpdateReq {
+    #[nserde(default_with = "Option::default")]
+    pub title: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub description: Option<String>,
+
+    /// Replaces the whole body of the note.
+    #[nserde(default_with = "Option::default")]
+    pub content: Option<NoteContent>,
+}
+
+/// Body of `POST .../groups/:groupId/move`, by default to the end of the
+/// group's own document.
+#[derive(Clone, SerJson, DeJson)]
+pub struct GroupMoveReq {
+    #[nserde(rename = "documentId")]
+    #[nserde(default_with = "Option::default")]
+    pub document_id: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub position: Option<usize>,
+}
+
+/// Body of `POST .../notes/:noteId/move`, like `GroupMoveReq` with the group
+/// to move the note to. Moving it to another document takes both ids.
+#[derive(Clone, SerJson, DeJson)]
+pub struct NoteMoveReq {
+    #[nserde(rename = "documentId")]
+    #[nserde(default_with = "Option::default")]
+    pub document_id: Option<String>,
+
+    #[nserde(rename = "groupId")]
+    #[nserde(default_with = "Option::default")]
+    pub group_id: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub position: Option<usize>,
+}
+
+/// The typed body of a note; `type` decides which other fields are used.
+#[derive(Clone, SerJson, DeJson)]
+pub struct NoteContent {
+    #[nserde(rename = "type")]
+    pub kind: String,
+
+    #[nserde(default_with = "Option::default")]
+    pub text: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub language: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub code: Option<String>,
 
+    #[nserde(default_with = "Option::default")]
+    pub url: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub title: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub items: Option<Vec<ChecklistItem>>,
+}
+
+/// An entry of a checklist body. Items sent without an `id` get one when
+/// they are stored, which the checklist endpoints address them by.
+#[derive(Clone, SerJson, DeJson)]
+pub struct ChecklistItem {
+    #[nserde(default)]
+    pub id: String,
+
+    #[nserde(default)]
+    pub text: String,
+
+    #[nserde(default)]
+    pub done: bool,
+}
+
+/// Body of `POST .../notes/:noteId/items`: a new checklist item, added at
+/// the end unless `position` says how many items go before it.
+#[derive(Clone, SerJson, DeJson)]
+pub struct ChecklistItemReq {
     #[nserde(default)]
-    pub created_by: String,
+    pub text: String,
 
     #[nserde(default)]
-    pub updated_by: String,
+    pub done: bool,
+
+    #[nserde(default_with = "Option::default")]
+    pub position: Option<usize>,
+}
+
+/// Body of `PATCH .../items/:itemId`, checking or unchecking the item with
+/// `done` and renaming it with `text`.
+#[derive(Clone, SerJson, DeJson)]
+pub struct ChecklistItemUpdateReq {
+    #[nserde(default_with = "Option::default")]
+    pub text: Option<String>,
+
+    #[nserde(default_with = "Option::default")]
+    pub done: Option<bool>,
+}
+
+/// Body of `PUT .../notes/:noteId/items`: the ids of all of the checklist's
+/// items in their new order.
+#[derive(Clone, SerJson, DeJson)]
+pub struct ChecklistOrderReq {
+    #[nserde(default)]
+    pub items: Vec<String>,
+}
+
+/// Body of `PUT /documents/:id/shares/:userId`, `role` is `editor` or
+/// `viewer`.
+#[derive(Clone, SerJson, DeJson)]
+pub struct ShareReq {
+    #[nserde(default)]
+    pub role: String,
 }
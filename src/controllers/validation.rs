@@ -0,0 +1,523 @@
+use std::collections::HashSet;
+
+use nanoserde::{DeJson, SerJson};
+
+use crate::error::{ApiError, Result};
+
+use super::{
+    ChecklistItem, ChecklistItemReq, ChecklistItemUpdateReq, ChecklistOrderReq, DocumentReq,
+    DocumentUpdateReq, GroupMoveReq, GroupReq, GroupUpdateReq, NoteContent, NoteMoveReq, NoteReq,
+    NoteUpdateReq, ShareReq,
+};
+
+pub const TITLE_MAX_LEN: usize = 200;
+pub const DESCRIPTION_MAX_LEN: usize = 10_000;
+/// Groups a create or update may add at once, as many as fit one DynamoDB
+/// transaction with the document and the revision its delete writes.
+pub const GROUPS_MAX_LEN: usize = 98;
+/// Notes a group may be created with, bounded the same way.
+pub const NOTES_MAX_LEN: usize = 98;
+/// Text, markdown and code bodies; a DynamoDB item holds at most 400 KB.
+pub const CONTENT_MAX_LEN: usize = 100_000;
+pub const LANGUAGE_MAX_LEN: usize = 50;
+pub const URL_MAX_LEN: usize = 2_048;
+pub const CHECKLIST_MAX_LEN: usize = 200;
+pub const CHECKLIST_ITEM_MAX_LEN: usize = 500;
+pub const ITEM_ID_MAX_LEN: usize = 64;
+pub const TAG_MAX_LEN: usize = 50;
+pub const TAGS_MAX_LEN: usize = 20;
+
+/// Tags are case-insensitive and kept lower-cased: up to `TAG_MAX_LEN`
+/// ASCII letters, digits, `-` and `_`, so they can sit in a URL path as is.
+pub(crate) fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_ascii_lowercase();
+    let valid = (1..=TAG_MAX_LEN).contains(&tag.len())
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(tag)
+}
+
+/// One rule a request body broke, `field` being a path like `groups[2].title`.
+#[derive(Clone, Debug, SerJson)]
+pub struct Violation {
+    pub field: String,
+    pub message: String,
+}
+
+/// Rules a request body must satisfy. Every broken rule is collected.
+pub trait Validate {
+    fn validate(&self, violations: &mut Violations);
+}
+
+/// Parses a JSON request body and checks it, answering `422` with every
+/// violation when the body is well-formed but breaks a rule.
+pub fn parse<T: DeJson + Validate>(body: &str) -> Result<T> {
+    let value: T = DeJson::deserialize_json(body)?;
+    let server_fields: ServerFields = DeJson::deserialize_json(body)?;
+
+    let mut violations = Violations::default();
+    value.validate(&mut violations);
+    server_fields.validate(&mut violations);
+    violations.into_result().map(|_| value)
+}
+
+#[derive(Default)]
+pub struct Violations {
+    prefix: String,
+    violations: Vec<Violation>,
+}
+
+impl Violations {
+    fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.violations.push(Violation {
+            field: format!("{}{}", self.prefix, field),
+            message: message.into(),
+        });
+    }
+
+    /// Text that must be present, not blank, and at most `max_len` characters.
+    pub fn required(&mut self, field: &str, value: &str, max_len: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "is required");
+        } else {
+            self.max_len(field, value, max_len);
+        }
+    }
+
+    /// Text of a partial update: when given it follows the same rules as
+    /// `required`, leaving it out keeps the stored value.
+    pub fn required_if_present(&mut self, field: &str, value: Option<&str>, max_len: usize) {
+        if let Some(value) = value {
+            self.required(field, value, max_len);
+        }
+    }
+
+    pub fn max_len(&mut self, field: &str, value: &str, max_len: usize) {
+        if value.chars().count() > max_len {
+            self.add(
+                field,
+                format!("must be at most {} characters long", max_len),
+            );
+        }
+    }
+
+    pub fn max_items<T>(&mut self, field: &str, values: &[T], max_len: usize) {
+        if values.len() > max_len {
+            self.add(field, format!("must hold at most {} entries", max_len));
+        }
+    }
+
+    pub fn one_of(&mut self, field: &str, value: &str, allowed: &[&str]) {
+        if !allowed.contains(&value) {
+            self.add(field, format!("must be one of {}", allowed.join(", ")));
+        }
+    }
+
+    pub fn tags(&mut self, field: &str, tags: &[String]) {
+        self.max_items(field, tags, TAGS_MAX_LEN);
+        for (index, tag) in tags.iter().enumerate() {
+            if normalize_tag(tag).is_none() {
+                self.add(
+                    &format!("{}[{}]", field, index),
+                    format!("must be 1 to {} letters, digits, '-' or '_'", TAG_MAX_LEN),
+                );
+            }
+        }
+    }
+
+    /// Validates a nested object, reporting its violations under `field.`.
+    pub fn nested<T: Validate>(&mut self, field: &str, value: &T) {
+        let prefix = self.prefix.clone();
+        self.prefix = format!("{}{}.", prefix, field);
+        value.validate(self);
+        self.prefix = prefix;
+    }
+
+    /// Fields the server fills in itself and a client must not send.
+    pub fn forbidden<T>(&mut self, field: &str, value: &Option<T>) {
+        if value.is_some() {
+            self.add(field, "is set by the server and cannot be supplied");
+        }
+    }
+
+    /// Validates every entry of a nested list, reporting its violations
+    /// under `field[index].`.
+    pub fn each<T: Validate>(&mut self, field: &str, values: &[T]) {
+        let prefix = self.prefix.clone();
+        for (index, value) in values.iter().enumerate() {
+            self.prefix = format!("{}{}[{}].", prefix, field, index);
+            value.validate(self);
+        }
+        self.prefix = prefix;
+    }
+
+    fn into_result(self) -> Result<()> {
+        if self.violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::InvalidFields(self.violations))
+        }
+    }
+}
+
+/// The attributes the server keeps on every item, looked for in each body
+/// and in the groups and notes nested in it.
+#[derive(DeJson)]
+struct ServerFields {
+    #[nserde(default_with = "Option::default")]
+    id: Option<String>,
+    #[nserde(default_with = "Option::default")]
+    owner: Option<String>,
+    #[nserde(default_with = "Option::default")]
+    created: Option<i64>,
+    #[nserde(rename = "createdBy")]
+    #[nserde(default_with = "Option::default")]
+    created_by: Option<String>,
+    #[nserde(rename = "lastUpdated")]
+    #[nserde(default_with = "Option::default")]
+    last_updated: Option<i64>,
+    #[nserde(rename = "updatedBy")]
+    #[nserde(default_with = "Option::default")]
+    updated_by: Option<String>,
+    #[nserde(default)]
+    groups: Vec<ServerFields>,
+    #[nserde(default)]
+    notes: Vec<ServerFields>,
+}
+
+impl Validate for ServerFields {
+    fn validate(&self, v: &mut Violations) {
+        v.forbidden("id", &self.id);
+        v.forbidden("owner", &self.owner);
+        v.forbidden("created", &self.created);
+        v.forbidden("createdBy", &self.created_by);
+        v.forbidden("lastUpdated", &self.last_updated);
+        v.forbidden("updatedBy", &self.updated_by);
+        v.each("groups", &self.groups);
+        v.each("notes", &self.notes);
+    }
+}
+
+impl Validate for DocumentReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required("title", &self.title, TITLE_MAX_LEN);
+        v.max_len("description", &self.description, DESCRIPTION_MAX_LEN);
+        v.tags("tags", &self.tags);
+        v.max_items("groups", &self.groups, GROUPS_MAX_LEN);
+        v.each("groups", &self.groups);
+    }
+}
+
+impl Validate for DocumentUpdateReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required_if_present("title", self.title.as_deref(), TITLE_MAX_LEN);
+        if let Some(description) = &self.description {
+            v.max_len("description", description, DESCRIPTION_MAX_LEN);
+        }
+        v.max_items("groups", &self.groups, GROUPS_MAX_LEN);
+        v.each("groups", &self.groups);
+    }
+}
+
+impl Validate for GroupReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required("title", &self.title, TITLE_MAX_LEN);
+        v.max_len("description", &self.description, DESCRIPTION_MAX_LEN);
+        v.tags("tags", &self.tags);
+        v.max_items("notes", &self.notes, NOTES_MAX_LEN);
+        v.each("notes", &self.notes);
+    }
+}
+
+impl Validate for GroupUpdateReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required_if_present("title", self.title.as_deref(), TITLE_MAX_LEN);
+        if let Some(description) = &self.description {
+            v.max_len("description", description, DESCRIPTION_MAX_LEN);
+        }
+    }
+}
+
+impl Validate for NoteReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required("title", &self.title, TITLE_MAX_LEN);
+        v.max_len("description", &self.description, DESCRIPTION_MAX_LEN);
+        if let Some(content) = &self.content {
+            v.nested("content", content);
+        }
+        v.tags("tags", &self.tags);
+    }
+}
+
+impl Validate for NoteUpdateReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required_if_present("title", self.title.as_deref(), TITLE_MAX_LEN);
+        if let Some(description) = &self.description {
+            v.max_len("description", description, DESCRIPTION_MAX_LEN);
+        }
+        if let Some(content) = &self.content {
+            v.nested("content", content);
+        }
+    }
+}
+
+impl Validate for NoteContent {
+    fn validate(&self, v: &mut Violations) {
+        let used: &[&str] = match self.kind.as_str() {
+            "text" | "markdown" => {
+                v.required(
+                    "text",
+                    self.text.as_deref().unwrap_or_default(),
+                    CONTENT_MAX_LEN,
+                );
+                &["text"]
+            }
+            "code" => {
+                v.required(
+                    "code",
+                    self.code.as_deref().unwrap_or_default(),
+                    CONTENT_MAX_LEN,
+                );
+                if let Some(language) = &self.language {
+                    v.max_len("language", language, LANGUAGE_MAX_LEN);
+                }
+                &["code", "language"]
+            }
+            "link" => {
+                let url = self.url.as_deref().unwrap_or_default();
+                v.required("url", url, URL_MAX_LEN);
+                let scheme = url.starts_with("https://") || url.starts_with("http://");
+                if !(scheme || url.trim().is_empty()) {
+                    v.add("url", "must be an http or https URL");
+                }
+                if let Some(title) = &self.title {
+                    v.max_len("title", title, TITLE_MAX_LEN);
+                }
+                &["url", "title"]
+            }
+            "checklist" => {
+                let items = self.items.as_deref().unwrap_or_default();
+                v.max_items("items", items, CHECKLIST_MAX_LEN);
+                v.each("items", items);
+                let mut ids = HashSet::new();
+                for (index, item) in items.iter().enumerate() {
+                    if !item.id.is_empty() && !ids.insert(item.id.as_str()) {
+                        v.add(&format!("items[{}].id", index), "is used by another item");
+                    }
+                }
+                &["items"]
+            }
+            _ => {
+                v.one_of(
+                    "type",
+                    &self.kind,
+                    &["text", "markdown", "code", "link", "checklist"],
+                );
+                return;
+            }
+        };
+
+        let given = [
+            ("text", self.text.is_some()),
+            ("code", self.code.is_some()),
+            ("language", self.language.is_some()),
+            ("url", self.url.is_some()),
+            ("title", self.title.is_some()),
+            ("items", self.items.is_some()),
+        ];
+        for (field, _) in given
+            .iter()
+            .filter(|(field, given)| *given && !used.contains(field))
+        {
+            v.add(field, format!("is not used by {} content", self.kind));
+        }
+    }
+}
+
+impl Validate for ChecklistItem {
+    fn validate(&self, v: &mut Violations) {
+        v.max_len("id", &self.id, ITEM_ID_MAX_LEN);
+        v.required("text", &self.text, CHECKLIST_ITEM_MAX_LEN);
+    }
+}
+
+impl Validate for ChecklistItemReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required("text", &self.text, CHECKLIST_ITEM_MAX_LEN);
+    }
+}
+
+impl Validate for ChecklistItemUpdateReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required_if_present("text", self.text.as_deref(), CHECKLIST_ITEM_MAX_LEN);
+    }
+}
+
+impl Validate for ChecklistOrderReq {
+    fn validate(&self, v: &mut Violations) {
+        v.max_items("items", &self.items, CHECKLIST_MAX_LEN);
+    }
+}
+
+impl Validate for GroupMoveReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required_if_present("documentId", self.document_id.as_deref(), ITEM_ID_MAX_LEN);
+    }
+}
+
+impl Validate for NoteMoveReq {
+    fn validate(&self, v: &mut Violations) {
+        v.required_if_present("documentId", self.document_id.as_deref(), ITEM_ID_MAX_LEN);
+        v.required_if_present("groupId", self.group_id.as_deref(), ITEM_ID_MAX_LEN);
+        if self.document_id.is_some() && self.group_id.is_none() {
+            v.add("groupId", "is required to move a note to another document");
+        }
+    }
+}
+
+impl Validate for ShareReq {
+    fn validate(&self, v: &mut Violations) {
+        v.one_of("role", &self.role, &["editor", "viewer"]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `(field, message)` of every violation `body` breaks.
+    fn violations<T: DeJson + Validate>(body: &str) -> Vec<(String, String)> {
+        match parse::<T>(body) {
+            Err(ApiError::InvalidFields(violations)) => violations
+                .into_iter()
+                .map(|violation| (violation.field, violation.message))
+                .collect(),
+            Ok(_) => Vec::new(),
+            Err(err) => panic!("expected violations, got {}", err),
+        }
+    }
+
+    fn fields<T: DeJson + Validate>(body: &str) -> Vec<String> {
+        violations::<T>(body)
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_document() {
+        let body = r#"{"title": "Groceries", "groups": [{"title": "Fruit", "notes": [{"title": "Apples"}]}]}"#;
+        assert!(parse::<DocumentReq>(body).is_ok());
+    }
+
+    #[test]
+    fn requires_a_title() {
+        assert_eq!(
+            violations::<DocumentReq>(r#"{"description": "Weekly"}"#),
+            [(String::from("title"), String::from("is required"))]
+        );
+    }
+
+    #[test]
+    fn rejects_a_blank_title() {
+        assert_eq!(fields::<DocumentReq>(r#"{"title": "  "}"#), ["title"]);
+        assert_eq!(fields::<DocumentUpdateReq>(r#"{"title": ""}"#), ["title"]);
+        assert!(fields::<DocumentUpdateReq>("{}").is_empty());
+    }
+
+    #[test]
+    fn bounds_the_length_of_text() {
+        let title = "a".repeat(TITLE_MAX_LEN);
+        assert!(fields::<DocumentReq>(&format!(r#"{{"title": "{}"}}"#, title)).is_empty());
+        let body = format!(r#"{{"title": "{}a"}}"#, title);
+        assert_eq!(
+            violations::<DocumentReq>(&body),
+            [(
+                String::from("title"),
+                format!("must be at most {} characters long", TITLE_MAX_LEN)
+            )]
+        );
+        let title = "é".repeat(TITLE_MAX_LEN);
+        assert!(fields::<DocumentReq>(&format!(r#"{{"title": "{}"}}"#, title)).is_empty());
+    }
+
+    #[test]
+    fn bounds_the_groups_and_notes_of_a_request() {
+        let list = |entry: &str, count: usize| vec![entry; count].join(",");
+        let notes = list(r#"{"title": "Note"}"#, NOTES_MAX_LEN + 1);
+        let body = format!(r#"{{"title": "Fruit", "notes": [{}]}}"#, notes);
+        assert_eq!(fields::<GroupReq>(&body), ["notes"]);
+
+        let groups = list(r#"{"title": "Group"}"#, GROUPS_MAX_LEN + 1);
+        let body = format!(r#"{{"title": "Groceries", "groups": [{}]}}"#, groups);
+        assert_eq!(fields::<DocumentReq>(&body), ["groups"]);
+        let body = format!(r#"{{"groups": [{}]}}"#, groups);
+        assert_eq!(fields::<DocumentUpdateReq>(&body), ["groups"]);
+    }
+
+    #[test]
+    fn forbids_server_fields_at_every_level() {
+        let body = r#"{
+            "title": "Groceries",
+            "id": "01H",
+            "owner": "bob",
+            "groups": [{
+                "title": "Fruit",
+                "createdBy": "bob",
+                "notes": [{"title": "Apples", "lastUpdated": 1}]
+            }]
+        }"#;
+        let violations = violations::<DocumentReq>(body);
+        assert_eq!(
+            violations
+                .iter()
+                .map(|(field, _)| field.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "id",
+                "owner",
+                "groups[0].createdBy",
+                "groups[0].notes[0].lastUpdated"
+            ]
+        );
+        assert!(violations
+            .iter()
+            .all(|(_, message)| message == "is set by the server and cannot be supplied"));
+    }
+
+    #[test]
+    fn collects_every_violation_into_one_response() {
+        let body = format!(
+            r#"{{
+                "title": "",
+                "description": "{}",
+                "tags": ["no spaces"],
+                "groups": [{{"title": "Fruit", "notes": [{{"title": "", "content": {{"type": "video"}}}}]}}],
+                "created": 1
+            }}"#,
+            "a".repeat(DESCRIPTION_MAX_LEN + 1)
+        );
+        let error = parse::<DocumentReq>(&body)
+            .err()
+            .expect("the body is invalid");
+        assert_eq!(error.status(), 422);
+        let ApiError::InvalidFields(violations) = error else {
+            panic!("expected violations");
+        };
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| violation.field.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "title",
+                "description",
+                "tags[0]",
+                "groups[0].notes[0].title",
+                "groups[0].notes[0].content.type",
+                "created"
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::{self, Display};
+
+use aws_sdk_dynamodb::types::SdkError;
+use lambda_http::{Body, Response};
+use nanoserde::{DeJsonErr, SerJson};
+
+use crate::controllers::validation::Violation;
+
+pub type Result<T> = std::result::Result<T, ApiError>;
+
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    Validation(String),
+    /// A request body that parsed but broke the rules of its `Validate` impl.
+    InvalidFields(Vec<Violation>),
+    Conflict(String),
+    /// An `If-Match` version that is no longer the stored one.
+    PreconditionFailed(String),
+    Storage(String),
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    MethodNotAllowed,
+}
+
+#[derive(SerJson)]
+struct ErrorBody {
+    code: String,
+    message: String,
+    #[nserde(rename = "requestId")]
+    request_id: String,
+    fields: Option<Vec<Violation>>,
+}
+
+impl ApiError {
+    pub fn not_found(entity: &str, id: &str) -> Self {
+        ApiError::NotFound(format!("{} '{}' does not exist", entity, id))
+    }
+
+    pub fn stale_version(entity: &str, id: &str, expected: u64) -> Self {
+        ApiError::PreconditionFailed(format!(
+            "{} '{}' is no longer at version {}",
+            entity, id, expected
+        ))
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            ApiError::NotFound(_) => 404,
+            ApiError::Validation(_) | ApiError::InvalidFields(_) => 422,
+            ApiError::Conflict(_) => 409,
+            ApiError::PreconditionFailed(_) => 412,
+            ApiError::Storage(_) => 500,
+            ApiError::BadRequest(_) => 400,
+            ApiError::Unauthorized(_) => 401,
+            ApiError::Forbidden(_) => 403,
+            ApiError::MethodNotAllowed => 405,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::Validation(_) | ApiError::InvalidFields(_) => "VALIDATION_FAILED",
+            ApiError::Conflict(_) => "CONFLICT",
+            ApiError::PreconditionFailed(_) => "PRECONDITION_FAILED",
+            ApiError::Storage(_) => "STORAGE_ERROR",
+            ApiError::BadRequest(_) => "BAD_REQUEST",
+            ApiError::Unauthorized(_) => "UNAUTHORIZED",
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            // Storage failures are logged in full but never leak to the caller.
+            ApiError::Storage(_) => String::from("An internal storage error occurred"),
+            ApiError::MethodNotAllowed => String::from("Method not allowed on this resource"),
+            ApiError::InvalidFields(_) => String::from("The request body failed validation"),
+            ApiError::NotFound(message)
+            | ApiError::Validation(message)
+            | ApiError::Conflict(message)
+            | ApiError::PreconditionFailed(message)
+            | ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message) => message.clone(),
+        }
+    }
+
+    pub fn into_response(self, request_id: &str) -> Response<Body> {
+        if let ApiError::Storage(cause) = &self {
+            tracing::error!(request_id, "storage error: {}", cause);
+        }
+        let status = self.status();
+        let body = ErrorBody {
+            code: self.code().to_string(),
+            message: self.message(),
+            request_id: request_id.to_string(),
+            fields: match self {
+                ApiError::InvalidFields(violations) => Some(violations),
+                _ => None,
+            },
+        };
+        Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(SerJson::serialize_json(&body).into())
+            .expect("error response is always valid")
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Storage(cause) => write!(f, "{}: {}", self.code(), cause),
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<DeJsonErr> for ApiError {
+    fn from(err: DeJsonErr) -> Self {
+        ApiError::BadRequest(format!("Malformed JSON body: {}", err))
+    }
+}
+
+impl<E> From<SdkError<E>> for ApiError
+where
+    aws_sdk_dynamodb::Error: From<SdkError<E>>,
+{
+    fn from(err: SdkError<E>) -> Self {
+        match aws_sdk_dynamodb::Error::from(err) {
+            aws_sdk_dynamodb::Error::ConditionalCheckFailedException(_) => {
+                ApiError::Conflict(String::from("The item was modified by another request"))
+            }
+            aws_sdk_dynamodb::Error::TransactionCanceledException(cause) => {
+                ApiError::Conflict(format!("The transaction was cancelled: {}", cause))
+            }
+            err => ApiError::Storage(err.to_string()),
+        }
+    }
+}
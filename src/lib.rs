@@ -0,0 +1,11 @@
+//! The notes API, shared by the `bootstrap` and `streams` functions.
+
+pub mod auth;
+pub mod changes;
+pub mod config;
+pub mod controllers;
+pub mod error;
+pub mod local;
+pub mod repositories;
+pub mod router;
+pub mod services;
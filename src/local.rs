@@ -0,0 +1,111 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Server,
+};
+use lambda_http::{
+    aws_lambda_events::{apigw::ApiGatewayProxyRequestContext, query_map::QueryMap},
+    http::{header::AUTHORIZATION, HeaderValue, Method},
+    request::RequestContext,
+    Body, Context, Error, Request, RequestExt, Response,
+};
+
+use crate::{repositories::new_id, router::RouterDelegate};
+
+/// Serves the router over plain HTTP on localhost. Requests without a bearer
+/// token are made as `user`.
+pub async fn serve(
+    port: u16,
+    router: RouterDelegate,
+    user: String,
+    log_requests: bool,
+) -> Result<(), Error> {
+    let router = Arc::new(router);
+    let user = Arc::new(user);
+    let make_service = make_service_fn(move |_| {
+        let router = router.clone();
+        let user = user.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let router = router.clone();
+                let user = user.clone();
+                async move { handle(&router, request, &user, log_requests).await }
+            }))
+        }
+    });
+
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    tracing::info!("serving the notes API on http://{}/api/notes", address);
+    Server::bind(&address).serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle(
+    router: &RouterDelegate,
+    request: hyper::Request<hyper::Body>,
+    user: &str,
+    log_requests: bool,
+) -> Result<hyper::Response<hyper::Body>, Error> {
+    let (head, body) = request.into_parts();
+    if log_requests {
+        tracing::info!("{} {}", head.method, head.uri);
+    }
+
+    // API Gateway answers CORS preflights itself, see `Cors` in template.yml.
+    if head.method == Method::OPTIONS {
+        let response = hyper::Response::builder()
+            .status(204)
+            .body(hyper::Body::empty())?;
+        return Ok(with_cors(response));
+    }
+
+    let bytes = hyper::body::to_bytes(body).await?;
+    let body = if bytes.is_empty() {
+        Body::Empty
+    } else {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => Body::Text(text),
+            Err(err) => Body::Binary(err.into_bytes()),
+        }
+    };
+    let query = head.uri.query().unwrap_or_default().parse::<QueryMap>()?;
+
+    let mut context = Context::default();
+    context.request_id = new_id();
+    let mut event = Request::from_parts(head, body).with_query_string_parameters(query);
+    event.extensions_mut().insert(context);
+    if !event.headers().contains_key(AUTHORIZATION) {
+        let mut request_context = ApiGatewayProxyRequestContext::default();
+        request_context
+            .authorizer
+            .insert(String::from("principalId"), user.into());
+        event = event.with_request_context(RequestContext::ApiGatewayV1(request_context));
+    }
+
+    let (head, body) = router.handle(event).await.into_parts();
+    let body = match body {
+        Body::Empty => hyper::Body::empty(),
+        Body::Text(text) => text.into(),
+        Body::Binary(bytes) => bytes.into(),
+    };
+    Ok(with_cors(Response::from_parts(head, body)))
+}
+
+fn with_cors(mut response: hyper::Response<hyper::Body>) -> hyper::Response<hyper::Body> {
+    let headers = response.headers_mut();
+    headers.insert("access-control-allow-origin", HeaderValue::from_static("*"));
+    headers.insert(
+        "access-control-allow-methods",
+        HeaderValue::from_static("GET,POST,PUT,PATCH,DELETE,OPTIONS"),
+    );
+    headers.insert(
+        "access-control-allow-headers",
+        HeaderValue::from_static("content-type,authorization,if-match"),
+    );
+    headers.insert(
+        "access-control-expose-headers",
+        HeaderValue::from_static("etag"),
+    );
+    response
+}
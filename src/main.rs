@@ -1,34 +1,81 @@
+use std::sync::Arc;
+
 use aws_sdk_dynamodb::Client;
 use lambda_http::{run, service_fn, Body, Error, Request, Response};
-use router::RouterDelegate;
+use structopt::StructOpt;
+
+use noterino_lambda::{
+    auth::Authenticator,
+    config::{AppConfig, Store},
+    local,
+    repositories::{
+        document_repository::DatabaseRepository, memory_repository::MemoryRepository, NotesStore,
+    },
+    router::RouterDelegate,
+};
 
-use crate::repositories::document_repository::DatabaseRepository;
+/// Runs as an AWS Lambda function unless `--local` is given.
+#[derive(StructOpt)]
+#[structopt(name = "bootstrap")]
+struct Opt {
+    /// Serve the API over plain HTTP on localhost instead of running as a
+    /// Lambda function
+    #[structopt(long)]
+    local: bool,
 
-mod config;
-mod controllers;
-mod repositories;
-mod router;
-mod services;
+    /// Port the local server listens on
+    #[structopt(long, default_value = "3000")]
+    port: u16,
+
+    /// User the local server makes requests as when they carry no bearer
+    /// token
+    #[structopt(long, default_value = "local-user")]
+    user: String,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
+    let opt = Opt::from_args();
+    let config = AppConfig::from_env()?;
+
     tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
+        .with_max_level(config.log_level)
         .with_target(false)
         .without_time()
         .init();
-    run(service_fn(handler)).await?;
-    Ok(())
-}
 
-async fn handler(event: Request) -> Result<Response<Body>, Error> {
-    let config = config::load_config().await;
-    let client = Client::new(&config);
+    // Built once per container and shared by every invocation it serves, so
+    // warm invocations skip credential resolution and route table setup.
+    let database: Arc<dyn NotesStore> = match &config.store {
+        Store::Memory => Arc::new(MemoryRepository::new(config.trash_retention)),
+        Store::DynamoDb(dynamodb) => {
+            let sdk_config = dynamodb.load_sdk_config().await;
+            let client = Client::new(&sdk_config);
+            Arc::new(DatabaseRepository::new(
+                client,
+                &dynamodb.table_name,
+                config.trash_retention,
+            ))
+        }
+    };
+    let authenticator = Authenticator::new(config.jwt_secret.as_deref());
+    let router = RouterDelegate::new(database, authenticator);
 
-    dbg!(&event);
-    let database = DatabaseRepository::from_client(client);
-    let router = RouterDelegate::new(&database);
-    let response = router.handle(event).await;
+    if opt.local {
+        local::serve(opt.port, router, opt.user, config.log_requests).await?;
+    } else {
+        run(service_fn(|event| handler(&config, &router, event))).await?;
+    }
+    Ok(())
+}
 
-    Ok(response)
+async fn handler(
+    config: &AppConfig,
+    router: &RouterDelegate,
+    event: Request,
+) -> Result<Response<Body>, Error> {
+    if config.log_requests {
+        tracing::info!("{:?}", event);
+    }
+    Ok(router.handle(event).await)
 }
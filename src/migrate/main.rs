@@ -0,0 +1,98 @@
+use aws_sdk_dynamodb::{Client, Endpoint};
+use lambda_http::http::Uri;
+use structopt::StructOpt;
+
+use crate::{migrations::Options, table::Table};
+
+mod migrations;
+mod table;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Brings a notes table up to the schema the lambda expects. Migrations are
+/// idempotent, so a failed run can simply be started again.
+#[derive(StructOpt)]
+#[structopt(name = "migrate")]
+struct Opt {
+    /// Table to migrate
+    #[structopt(long, env = "TABLE_NAME")]
+    table_name: String,
+
+    /// Talk to this endpoint instead of AWS, e.g. http://localhost:8000 for DynamoDB Local
+    #[structopt(long)]
+    endpoint_url: Option<String>,
+
+    /// Stop after this version instead of applying every pending migration
+    #[structopt(long)]
+    to: Option<u32>,
+
+    /// Report what each pending migration would write without writing it
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Owner to give documents that predate ownership
+    #[structopt(long)]
+    default_owner: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .without_time()
+        .init();
+
+    let opt = Opt::from_args();
+
+    let mut loader = aws_config::from_env();
+    if let Some(endpoint_url) = opt.endpoint_url.as_deref() {
+        loader = loader.endpoint_resolver(Endpoint::immutable(endpoint_url.parse::<Uri>()?));
+    }
+    let config = loader.load().await;
+    let table = Table::new(Client::new(&config), opt.table_name);
+    let options = Options {
+        default_owner: opt.default_owner,
+    };
+
+    let current = match table.schema_version().await? {
+        Some(current) => current,
+        None => {
+            let detected = migrations::detect_version(&table.scan().await?, &options);
+            tracing::info!(
+                "{} has no schema version, its items match version {}",
+                table.name(),
+                detected
+            );
+            if detected > 0 && !opt.dry_run {
+                table.set_schema_version(detected).await?;
+            }
+            detected
+        }
+    };
+    tracing::info!("{} is at schema version {}", table.name(), current);
+
+    let pending = migrations::all()
+        .into_iter()
+        .filter(|migration| migration.version > current)
+        .filter(|migration| opt.to.is_none_or(|to| migration.version <= to));
+
+    for migration in pending {
+        let items = table.scan().await?;
+        let writes = (migration.run)(&items, &options)?;
+        tracing::info!(
+            "{:03} {}: {} writes for {} items",
+            migration.version,
+            migration.name,
+            writes.len(),
+            items.len()
+        );
+
+        if opt.dry_run {
+            continue;
+        }
+        table.write_all(writes).await?;
+        table.set_schema_version(migration.version).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,768 @@
+//! The migrations, oldest first. Attribute names and key formats are spelled
+//! out here so each keeps describing the schema it was written for.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use aws_sdk_dynamodb::model::AttributeValue;
+use noterino_lambda::repositories::search_index::escape_owner;
+use ulid::Ulid;
+
+use crate::Error;
+
+pub type Item = HashMap<String, AttributeValue>;
+
+pub const PK: &str = "PK";
+pub const SK: &str = "SK";
+const GSI1PK: &str = "GSI1PK";
+const GSI1SK: &str = "GSI1SK";
+const PARENT: &str = "parent";
+const OWNER: &str = "owner";
+const LAST_UPDATE: &str = "lastUpdate";
+const LAST_UPDATED: &str = "lastUpdated";
+const VERSION: &str = "version";
+const TITLE: &str = "title";
+const DESCRIPTION: &str = "description";
+const CONTENT: &str = "content";
+const POSITION: &str = "position";
+const TRASHED: &str = "trashed";
+
+pub enum Write {
+    Put(Item),
+    Delete { pk: String, sk: String },
+}
+
+/// Input some migrations need beyond the items themselves.
+pub struct Options {
+    /// Owner given to documents created before documents had owners.
+    pub default_owner: Option<String>,
+}
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    /// Looks at every item in the table and returns the writes needed to
+    /// migrate it. Items already in the target shape produce no writes.
+    pub run: fn(&[Item], &Options) -> Result<Vec<Write>, Error>,
+}
+
+pub fn all() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            name: "document partitions",
+            run: document_partitions,
+        },
+        Migration {
+            version: 2,
+            name: "numeric lastUpdated",
+            run: numeric_last_updated,
+        },
+        Migration {
+            version: 3,
+            name: "document owners",
+            run: document_owners,
+        },
+        Migration {
+            version: 4,
+            name: "item versions",
+            run: item_versions,
+        },
+        Migration {
+            version: 5,
+            name: "search index",
+            run: search_index,
+        },
+        Migration {
+            version: 6,
+            name: "checklist item ids",
+            run: checklist_item_ids,
+        },
+        Migration {
+            version: 7,
+            name: "positions",
+            run: positions,
+        },
+    ]
+}
+
+/// Works out the version of a table without a meta item: migrations before
+/// the first that would still write something count as applied.
+pub fn detect_version(items: &[Item], options: &Options) -> u32 {
+    let mut version = 0;
+    for migration in all() {
+        match (migration.run)(items, options) {
+            Ok(writes) if writes.is_empty() => version = migration.version,
+            _ => break,
+        }
+    }
+    version
+}
+
+/// Moves items out of the shared `document`, `group` and `note` partitions
+/// into one `DOCUMENT#<id>` partition per document.
+fn document_partitions(items: &[Item], _: &Options) -> Result<Vec<Write>, Error> {
+    // Group to document, from groups in either layout, so notes can still be
+    // placed if their group was moved by an earlier, interrupted run.
+    let documents_of_groups = items
+        .iter()
+        .filter_map(|item| {
+            let sk = s(item, SK)?;
+            let is_group = sk.starts_with("GROUP#") && !sk.contains("#NOTE#");
+            is_group.then_some((sk, s(item, PARENT)?))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut puts = Vec::new();
+    let mut deletes = Vec::new();
+    for item in items {
+        let (Some(pk), Some(sk)) = (s(item, PK), s(item, SK)) else {
+            continue;
+        };
+
+        let mut migrated = item.clone();
+        match pk {
+            "document" => {
+                migrated.insert(PK.to_string(), AttributeValue::S(sk.to_string()));
+                migrated.insert(
+                    GSI1PK.to_string(),
+                    AttributeValue::S("DOCUMENT".to_string()),
+                );
+                migrated.insert(GSI1SK.to_string(), AttributeValue::S(sk.to_string()));
+            }
+            "group" => {
+                let Some(document) = s(item, PARENT) else {
+                    tracing::warn!("skipping group {} without a parent", sk);
+                    continue;
+                };
+                migrated.insert(PK.to_string(), AttributeValue::S(document.to_string()));
+            }
+            "note" => {
+                let group = s(item, PARENT);
+                let Some((group, document)) =
+                    group.and_then(|group| Some((group, *documents_of_groups.get(group)?)))
+                else {
+                    tracing::warn!("skipping note {} whose group cannot be found", sk);
+                    continue;
+                };
+                let note_id = sk.rsplit_once('#').map_or(sk, |(_, id)| id);
+                migrated.insert(PK.to_string(), AttributeValue::S(document.to_string()));
+                migrated.insert(
+                    SK.to_string(),
+                    AttributeValue::S(format!("{}#NOTE#{}", group, note_id)),
+                );
+            }
+            _ => continue,
+        }
+
+        puts.push(Write::Put(migrated));
+        deletes.push(Write::Delete {
+            pk: pk.to_string(),
+            sk: sk.to_string(),
+        });
+    }
+
+    // Every new item is written before any old one goes away.
+    puts.append(&mut deletes);
+    Ok(puts)
+}
+
+/// Renames `lastUpdate` to `lastUpdated` and stores it as a number on every
+/// item; documents used to write it as a string.
+fn numeric_last_updated(items: &[Item], _: &Options) -> Result<Vec<Write>, Error> {
+    let mut writes = Vec::new();
+    for item in items {
+        let mut migrated = item.clone();
+        if let Some(last_update) = migrated.remove(LAST_UPDATE) {
+            migrated
+                .entry(LAST_UPDATED.to_string())
+                .or_insert(last_update);
+        }
+        if let Some(AttributeValue::S(last_updated)) = migrated.get(LAST_UPDATED) {
+            match last_updated.parse::<i64>() {
+                Ok(timestamp) => {
+                    migrated.insert(
+                        LAST_UPDATED.to_string(),
+                        AttributeValue::N(timestamp.to_string()),
+                    );
+                }
+                Err(_) => tracing::warn!(
+                    "leaving non-numeric {} '{}' as it is",
+                    LAST_UPDATED,
+                    last_updated
+                ),
+            }
+        }
+
+        if &migrated != item {
+            writes.push(Write::Put(migrated));
+        }
+    }
+    Ok(writes)
+}
+
+/// Gives every document an `owner` and lists it under `OWNER#<id>`.
+fn document_owners(items: &[Item], options: &Options) -> Result<Vec<Write>, Error> {
+    let mut writes = Vec::new();
+    for item in items {
+        let (Some(pk), Some(sk)) = (s(item, PK), s(item, SK)) else {
+            continue;
+        };
+        if pk != sk || !pk.starts_with("DOCUMENT#") {
+            continue;
+        }
+
+        let owner = match (s(item, OWNER), options.default_owner.as_deref()) {
+            (Some(owner), _) | (None, Some(owner)) => owner,
+            (None, None) => {
+                return Err(
+                    format!("{} has no owner, pass --default-owner to assign one", pk).into(),
+                )
+            }
+        };
+
+        let mut migrated = item.clone();
+        migrated.insert(OWNER.to_string(), AttributeValue::S(owner.to_string()));
+        migrated.insert(
+            GSI1PK.to_string(),
+            AttributeValue::S(format!("OWNER#{}", owner)),
+        );
+        if &migrated != item {
+            writes.push(Write::Put(migrated));
+        }
+    }
+    Ok(writes)
+}
+
+/// Starts every document, group and note without a `version` at 1.
+fn item_versions(items: &[Item], _: &Options) -> Result<Vec<Write>, Error> {
+    let mut writes = Vec::new();
+    for item in items {
+        let (Some(pk), Some(sk)) = (s(item, PK), s(item, SK)) else {
+            continue;
+        };
+        let entity = sk.starts_with("DOCUMENT#") || sk.starts_with("GROUP#");
+        if !pk.starts_with("DOCUMENT#") || !entity || item.contains_key(VERSION) {
+            continue;
+        }
+
+        let mut migrated = item.clone();
+        migrated.insert(VERSION.to_string(), AttributeValue::N(String::from("1")));
+        writes.push(Write::Put(migrated));
+    }
+    Ok(writes)
+}
+
+/// Indexes every document, group and note written before search existed.
+fn search_index(items: &[Item], _: &Options) -> Result<Vec<Write>, Error> {
+    let indexed = items
+        .iter()
+        .filter(|item| s(item, PK).is_some_and(|pk| pk.starts_with("SEARCH#")))
+        .filter_map(|entry| Some((s(entry, PK)?, s(entry, SK)?)))
+        .collect::<HashSet<_>>();
+    let owners = items
+        .iter()
+        .filter(|item| s(item, PK).is_some() && s(item, PK) == s(item, SK))
+        .filter_map(|document| Some((s(document, PK)?, s(document, OWNER)?)))
+        .collect::<HashMap<_, _>>();
+
+    let mut writes = Vec::new();
+    for item in items {
+        let (Some(pk), Some(sk)) = (s(item, PK), s(item, SK)) else {
+            continue;
+        };
+        let Some(document_id) = pk.strip_prefix("DOCUMENT#") else {
+            continue;
+        };
+        let Some(owner) = owners.get(pk) else {
+            continue;
+        };
+        if item.contains_key(TRASHED) {
+            continue;
+        }
+
+        let mut entry = HashMap::from([(
+            "documentId".to_string(),
+            AttributeValue::S(document_id.to_string()),
+        )]);
+        let (kind, path) = if sk == pk {
+            ("document", pk.to_string())
+        } else if let Some(group) = sk.strip_prefix("GROUP#") {
+            let (group_id, note_id) = match group.split_once("#NOTE#") {
+                Some((group_id, note_id)) => (group_id, Some(note_id)),
+                None => (group, None),
+            };
+            entry.insert(
+                "groupId".to_string(),
+                AttributeValue::S(group_id.to_string()),
+            );
+            match note_id {
+                Some(note_id) => {
+                    entry.insert("noteId".to_string(), AttributeValue::S(note_id.to_string()));
+                    ("note", format!("{}#{}", pk, sk))
+                }
+                None => ("group", format!("{}#{}", pk, sk)),
+            }
+        } else {
+            continue;
+        };
+
+        let title = s(item, TITLE).unwrap_or_default();
+        let text = format!("{} {}", title, s(item, DESCRIPTION).unwrap_or_default());
+        let tokens = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| (2..=64).contains(&word.chars().count()))
+            .map(str::to_lowercase)
+            .collect::<BTreeSet<_>>();
+
+        entry.insert(SK.to_string(), AttributeValue::S(path.clone()));
+        entry.insert("kind".to_string(), AttributeValue::S(kind.to_string()));
+        entry.insert(TITLE.to_string(), AttributeValue::S(title.to_string()));
+        for token in tokens {
+            let key = format!("SEARCH#{}#{}", escape_owner(owner), token);
+            if indexed.contains(&(key.as_str(), path.as_str())) {
+                continue;
+            }
+            let mut entry = entry.clone();
+            entry.insert(PK.to_string(), AttributeValue::S(key));
+            writes.push(Write::Put(entry));
+        }
+    }
+    Ok(writes)
+}
+
+/// Gives every checklist item stored before items could be addressed on
+/// their own an `id`. Items that have one keep it.
+fn checklist_item_ids(items: &[Item], _: &Options) -> Result<Vec<Write>, Error> {
+    let mut writes = Vec::new();
+    for item in items {
+        let Some(AttributeValue::M(content)) = item.get(CONTENT) else {
+            continue;
+        };
+        let Some(AttributeValue::L(entries)) = content.get("items") else {
+            continue;
+        };
+
+        let mut changed = false;
+        let entries = entries
+            .iter()
+            .map(|entry| match entry {
+                AttributeValue::M(entry) if !entry.contains_key("id") => {
+                    changed = true;
+                    let mut entry = entry.clone();
+                    entry.insert("id".to_string(), AttributeValue::S(Ulid::new().to_string()));
+                    AttributeValue::M(entry)
+                }
+                entry => entry.clone(),
+            })
+            .collect();
+        if !changed {
+            continue;
+        }
+
+        let mut content = content.clone();
+        content.insert("items".to_string(), AttributeValue::L(entries));
+        let mut migrated = item.clone();
+        migrated.insert(CONTENT.to_string(), AttributeValue::M(content));
+        writes.push(Write::Put(migrated));
+    }
+    Ok(writes)
+}
+
+/// Numbers groups and notes in creation order, `65536` apart.
+fn positions(items: &[Item], _: &Options) -> Result<Vec<Write>, Error> {
+    let mut children = BTreeMap::<(&str, &str), Vec<&Item>>::new();
+    for item in items {
+        let (Some(pk), Some(sk), Some(parent)) = (s(item, PK), s(item, SK), s(item, PARENT)) else {
+            continue;
+        };
+        if pk.starts_with("DOCUMENT#") && sk.starts_with("GROUP#") {
+            children.entry((pk, parent)).or_default().push(item);
+        }
+    }
+
+    let mut writes = Vec::new();
+    for siblings in children.values_mut() {
+        if siblings.iter().all(|item| item.contains_key(POSITION)) {
+            continue;
+        }
+        siblings.sort_by_key(|item| s(item, SK));
+        for (index, item) in siblings.iter().enumerate() {
+            let position = (index as i64 + 1) * 65_536;
+            let mut migrated = (*item).clone();
+            migrated.insert(
+                POSITION.to_string(),
+                AttributeValue::N(position.to_string()),
+            );
+            if &migrated != *item {
+                writes.push(Write::Put(migrated));
+            }
+        }
+    }
+    Ok(writes)
+}
+
+fn s<'a>(item: &'a Item, key: &str) -> Option<&'a str> {
+    match item.get(key) {
+        Some(AttributeValue::S(value)) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER_ID: &str = "alice";
+
+    fn item(strings: &[(&str, &str)]) -> Item {
+        strings
+            .iter()
+            .map(|(name, value)| (name.to_string(), AttributeValue::S(value.to_string())))
+            .collect()
+    }
+
+    fn with_n(mut item: Item, name: &str, value: i64) -> Item {
+        item.insert(name.to_string(), AttributeValue::N(value.to_string()));
+        item
+    }
+
+    fn options(default_owner: Option<&str>) -> Options {
+        Options {
+            default_owner: default_owner.map(String::from),
+        }
+    }
+
+    fn run(
+        migration: fn(&[Item], &Options) -> Result<Vec<Write>, Error>,
+        items: &[Item],
+    ) -> Vec<Write> {
+        migration(items, &options(None)).expect("the migration runs")
+    }
+
+    fn puts(writes: &[Write]) -> Vec<&Item> {
+        writes
+            .iter()
+            .filter_map(|write| match write {
+                Write::Put(item) => Some(item),
+                Write::Delete { .. } => None,
+            })
+            .collect()
+    }
+
+    fn deletes(writes: &[Write]) -> Vec<(&str, &str)> {
+        writes
+            .iter()
+            .filter_map(|write| match write {
+                Write::Delete { pk, sk } => Some((pk.as_str(), sk.as_str())),
+                Write::Put(_) => None,
+            })
+            .collect()
+    }
+
+    fn keys<'a>(items: &[&'a Item]) -> Vec<(&'a str, &'a str)> {
+        items
+            .iter()
+            .map(|item| (s(item, PK).unwrap(), s(item, SK).unwrap()))
+            .collect()
+    }
+
+    /// A document, a group and a note the way the lambda writes them today.
+    fn current_items() -> Vec<Item> {
+        let document = item(&[
+            (PK, "DOCUMENT#D1"),
+            (SK, "DOCUMENT#D1"),
+            (GSI1PK, "OWNER#alice"),
+            (GSI1SK, "DOCUMENT#D1"),
+            (OWNER, OWNER_ID),
+            (TITLE, "Groceries"),
+        ]);
+        let group = item(&[
+            (PK, "DOCUMENT#D1"),
+            (SK, "GROUP#G1"),
+            (PARENT, "DOCUMENT#D1"),
+            (TITLE, "Fruit"),
+        ]);
+        let note = item(&[
+            (PK, "DOCUMENT#D1"),
+            (SK, "GROUP#G1#NOTE#N1"),
+            (PARENT, "GROUP#G1"),
+            (TITLE, "Apples"),
+        ]);
+        let mut items = vec![document, group, note]
+            .into_iter()
+            .map(|item| with_n(with_n(item, VERSION, 1), LAST_UPDATED, 1_700_000_000))
+            .collect::<Vec<_>>();
+        items[1] = with_n(items[1].clone(), POSITION, 65_536);
+        items[2] = with_n(items[2].clone(), POSITION, 65_536);
+        let indexed = run(search_index, &items)
+            .into_iter()
+            .filter_map(|write| match write {
+                Write::Put(entry) => Some(entry),
+                Write::Delete { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        items.extend(indexed);
+        items
+    }
+
+    #[test]
+    fn moves_items_into_document_partitions() {
+        let items = [
+            item(&[(PK, "document"), (SK, "DOCUMENT#D1")]),
+            item(&[(PK, "group"), (SK, "GROUP#G1"), (PARENT, "DOCUMENT#D1")]),
+            item(&[(PK, "note"), (SK, "NOTE#N1"), (PARENT, "GROUP#G1")]),
+            item(&[(PK, "note"), (SK, "NOTE#N2"), (PARENT, "GROUP#missing")]),
+        ];
+
+        let writes = run(document_partitions, &items);
+        let puts = puts(&writes);
+        assert_eq!(
+            keys(&puts),
+            [
+                ("DOCUMENT#D1", "DOCUMENT#D1"),
+                ("DOCUMENT#D1", "GROUP#G1"),
+                ("DOCUMENT#D1", "GROUP#G1#NOTE#N1"),
+            ]
+        );
+        assert_eq!(s(puts[0], GSI1PK), Some("DOCUMENT"));
+        assert_eq!(
+            deletes(&writes),
+            [
+                ("document", "DOCUMENT#D1"),
+                ("group", "GROUP#G1"),
+                ("note", "NOTE#N1"),
+            ]
+        );
+        // Every new item is written before any old one is deleted.
+        assert!(matches!(writes[3], Write::Delete { .. }));
+    }
+
+    #[test]
+    fn places_notes_whose_group_an_interrupted_run_already_moved() {
+        let items = [
+            item(&[
+                (PK, "DOCUMENT#D1"),
+                (SK, "GROUP#G1"),
+                (PARENT, "DOCUMENT#D1"),
+            ]),
+            item(&[(PK, "note"), (SK, "NOTE#N1"), (PARENT, "GROUP#G1")]),
+        ];
+        let writes = run(document_partitions, &items);
+        assert_eq!(keys(&puts(&writes)), [("DOCUMENT#D1", "GROUP#G1#NOTE#N1")]);
+    }
+
+    #[test]
+    fn stores_last_updated_as_a_number() {
+        let key = [(PK, "DOCUMENT#D1"), (SK, "DOCUMENT#D1")];
+        let string = item(&[key[0], key[1], (LAST_UPDATE, "1700000000")]);
+        let number = with_n(item(&key), LAST_UPDATE, 1_700_000_000);
+        let renamed = with_n(item(&key), LAST_UPDATED, 1_700_000_000);
+
+        for legacy in [string, number] {
+            let writes = run(numeric_last_updated, &[legacy]);
+            let puts = puts(&writes);
+            assert_eq!(puts.len(), 1);
+            assert_eq!(puts[0], &renamed);
+        }
+        assert!(run(numeric_last_updated, &[renamed]).is_empty());
+
+        let unparsable = item(&[key[0], key[1], (LAST_UPDATED, "yesterday")]);
+        assert!(run(numeric_last_updated, &[unparsable]).is_empty());
+    }
+
+    #[test]
+    fn gives_documents_an_owner() {
+        let owned = item(&[(PK, "DOCUMENT#D1"), (SK, "DOCUMENT#D1"), (OWNER, "bob")]);
+        let unowned = item(&[(PK, "DOCUMENT#D2"), (SK, "DOCUMENT#D2")]);
+        let group = item(&[(PK, "DOCUMENT#D1"), (SK, "GROUP#G1")]);
+        let items = [owned, unowned, group];
+
+        assert!(document_owners(&items, &options(None)).is_err());
+        let writes = document_owners(&items, &options(Some(OWNER_ID))).unwrap();
+        let puts = puts(&writes);
+        assert_eq!(puts.len(), 2);
+        assert_eq!(s(puts[0], OWNER), Some("bob"));
+        assert_eq!(s(puts[0], GSI1PK), Some("OWNER#bob"));
+        assert_eq!(s(puts[1], OWNER), Some(OWNER_ID));
+        assert_eq!(s(puts[1], GSI1PK), Some("OWNER#alice"));
+        assert!(document_owners(&current_items(), &options(None))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn starts_items_at_version_one() {
+        let items = [
+            item(&[(PK, "DOCUMENT#D1"), (SK, "DOCUMENT#D1")]),
+            with_n(item(&[(PK, "DOCUMENT#D1"), (SK, "GROUP#G1")]), VERSION, 3),
+            item(&[(PK, "SEARCH#alice#fruit"), (SK, "DOCUMENT#D1#GROUP#G1")]),
+        ];
+        let writes = run(item_versions, &items);
+        let puts = puts(&writes);
+        assert_eq!(keys(&puts), [("DOCUMENT#D1", "DOCUMENT#D1")]);
+        assert_eq!(
+            puts[0].get(VERSION),
+            Some(&AttributeValue::N(String::from("1")))
+        );
+    }
+
+    #[test]
+    fn indexes_the_words_of_items_outside_the_trash() {
+        let mut items = current_items()[..3].to_vec();
+        items[2].insert(
+            DESCRIPTION.to_string(),
+            AttributeValue::S(String::from("Granny Smith, a")),
+        );
+        items.push(with_n(
+            item(&[(PK, "DOCUMENT#D1"), (SK, "GROUP#G2"), (TITLE, "Dairy")]),
+            TRASHED,
+            1,
+        ));
+
+        let writes = run(search_index, &items);
+        let entries = puts(&writes);
+        assert_eq!(
+            keys(&entries),
+            [
+                ("SEARCH#alice#groceries", "DOCUMENT#D1"),
+                ("SEARCH#alice#fruit", "DOCUMENT#D1#GROUP#G1"),
+                ("SEARCH#alice#apples", "DOCUMENT#D1#GROUP#G1#NOTE#N1"),
+                ("SEARCH#alice#granny", "DOCUMENT#D1#GROUP#G1#NOTE#N1"),
+                ("SEARCH#alice#smith", "DOCUMENT#D1#GROUP#G1#NOTE#N1"),
+            ]
+        );
+        assert_eq!(s(entries[2], "kind"), Some("note"));
+        assert_eq!(s(entries[2], "groupId"), Some("G1"));
+        assert_eq!(s(entries[2], "noteId"), Some("N1"));
+    }
+
+    #[test]
+    fn escapes_the_owner_of_search_entries() {
+        let mut items = current_items()[..1].to_vec();
+        items[0].insert(OWNER.to_string(), AttributeValue::S(String::from("team#1")));
+        let writes = run(search_index, &items);
+        assert_eq!(
+            keys(&puts(&writes)),
+            [("SEARCH#team%231#groceries", "DOCUMENT#D1")]
+        );
+    }
+
+    #[test]
+    fn resumes_an_interrupted_search_index() {
+        let mut items = current_items()[..3].to_vec();
+        items[0].insert(
+            DESCRIPTION.to_string(),
+            AttributeValue::S(String::from("Weekly shop")),
+        );
+        let all = run(search_index, &items);
+        assert_eq!(all.len(), 5);
+
+        // Only the first entry of the document made it before the run died.
+        let Write::Put(first) = &all[0] else {
+            panic!("expected a put");
+        };
+        items.push(first.clone());
+        let rest = run(search_index, &items);
+        assert_eq!(rest.len(), 4);
+        assert!(puts(&rest).iter().all(|entry| *entry != first));
+    }
+
+    #[test]
+    fn gives_checklist_items_ids() {
+        let entry = |id: Option<&str>| {
+            let mut entry = HashMap::from([(
+                String::from("text"),
+                AttributeValue::S(String::from("Milk")),
+            )]);
+            if let Some(id) = id {
+                entry.insert(String::from("id"), AttributeValue::S(id.to_string()));
+            }
+            AttributeValue::M(entry)
+        };
+        let note = |entries: Vec<AttributeValue>| {
+            let mut note = item(&[(PK, "DOCUMENT#D1"), (SK, "GROUP#G1#NOTE#N1")]);
+            let content = HashMap::from([
+                (
+                    String::from("type"),
+                    AttributeValue::S(String::from("checklist")),
+                ),
+                (String::from("items"), AttributeValue::L(entries)),
+            ]);
+            note.insert(CONTENT.to_string(), AttributeValue::M(content));
+            note
+        };
+
+        let writes = run(
+            checklist_item_ids,
+            &[note(vec![entry(Some("I1")), entry(None)])],
+        );
+        let puts = puts(&writes);
+        assert_eq!(puts.len(), 1);
+        let Some(AttributeValue::M(content)) = puts[0].get(CONTENT) else {
+            panic!("expected content");
+        };
+        let Some(AttributeValue::L(entries)) = content.get("items") else {
+            panic!("expected checklist items");
+        };
+        let ids = entries
+            .iter()
+            .map(|entry| match entry {
+                AttributeValue::M(entry) => s(entry, "id").map(String::from),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids[0].as_deref(), Some("I1"));
+        assert!(ids[1].as_ref().is_some_and(|id| !id.is_empty()));
+
+        assert!(run(checklist_item_ids, &[note(vec![entry(Some("I1"))])]).is_empty());
+    }
+
+    #[test]
+    fn numbers_groups_and_notes_in_creation_order() {
+        let group = |sk: &str| item(&[(PK, "DOCUMENT#D1"), (SK, sk), (PARENT, "DOCUMENT#D1")]);
+        let note = |sk: &str| item(&[(PK, "DOCUMENT#D1"), (SK, sk), (PARENT, "GROUP#G1")]);
+        let items = [
+            group("GROUP#G2"),
+            group("GROUP#G1"),
+            with_n(note("GROUP#G1#NOTE#N1"), POSITION, 65_536),
+        ];
+
+        let writes = run(positions, &items);
+        let puts = puts(&writes);
+        assert_eq!(
+            keys(&puts),
+            [("DOCUMENT#D1", "GROUP#G1"), ("DOCUMENT#D1", "GROUP#G2")]
+        );
+        assert_eq!(
+            puts[0].get(POSITION),
+            Some(&AttributeValue::N(String::from("65536")))
+        );
+        assert_eq!(
+            puts[1].get(POSITION),
+            Some(&AttributeValue::N(String::from("131072")))
+        );
+    }
+
+    #[test]
+    fn detects_the_version_of_a_table_without_a_meta_item() {
+        let options = options(None);
+        assert_eq!(detect_version(&[], &options), 7);
+        assert_eq!(detect_version(&current_items(), &options), 7);
+
+        let legacy = [item(&[(PK, "document"), (SK, "DOCUMENT#D1")])];
+        assert_eq!(detect_version(&legacy, &options), 0);
+
+        // Documents used to keep `lastUpdate` as a string.
+        let mut items = current_items();
+        items[0].remove(LAST_UPDATED);
+        items[0].insert(
+            LAST_UPDATE.to_string(),
+            AttributeValue::S(String::from("1700000000")),
+        );
+        assert_eq!(detect_version(&items, &options), 1);
+
+        let mut items = current_items();
+        items.retain(|item| !s(item, PK).is_some_and(|pk| pk.starts_with("SEARCH#")));
+        assert_eq!(detect_version(&items, &options), 4);
+    }
+}
@@ -0,0 +1,141 @@
+use std::{collections::HashMap, time::Duration};
+
+use aws_sdk_dynamodb::{
+    model::{AttributeValue, DeleteRequest, PutRequest, WriteRequest},
+    Client,
+};
+use chrono::Utc;
+use tokio_stream::StreamExt;
+
+use crate::{
+    migrations::{Item, Write, PK, SK},
+    Error,
+};
+
+/// DynamoDB rejects batch writes with more requests than this.
+const MAX_BATCH_ITEMS: usize = 25;
+
+/// Key of the item recording the last applied migration. It has no `GSI1PK`
+/// and no `DOCUMENT#` partition, so the lambda never reads it.
+const META_PK: &str = "META";
+const META_SK: &str = "SCHEMA_VERSION";
+const VERSION: &str = "version";
+const APPLIED_AT: &str = "appliedAt";
+
+pub struct Table {
+    client: Client,
+    name: String,
+}
+
+impl Table {
+    pub fn new(client: Client, name: String) -> Self {
+        Self { client, name }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the last applied migration, or `None` when the table has no
+    /// meta item yet.
+    pub async fn schema_version(&self) -> Result<Option<u32>, Error> {
+        let response = self
+            .client
+            .get_item()
+            .table_name(&self.name)
+            .key(PK, AttributeValue::S(META_PK.to_string()))
+            .key(SK, AttributeValue::S(META_SK.to_string()))
+            .consistent_read(true)
+            .send()
+            .await?;
+
+        match response.item().and_then(|item| item.get(VERSION)) {
+            Some(AttributeValue::N(version)) => Ok(Some(version.parse()?)),
+            Some(_) => Err(format!("{} on the meta item is not a number", VERSION).into()),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn set_schema_version(&self, version: u32) -> Result<(), Error> {
+        self.client
+            .put_item()
+            .table_name(&self.name)
+            .item(PK, AttributeValue::S(META_PK.to_string()))
+            .item(SK, AttributeValue::S(META_SK.to_string()))
+            .item(VERSION, AttributeValue::N(version.to_string()))
+            .item(
+                APPLIED_AT,
+                AttributeValue::N(Utc::now().timestamp().to_string()),
+            )
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Returns every item in the table except the meta item.
+    pub async fn scan(&self) -> Result<Vec<Item>, Error> {
+        let items = self
+            .client
+            .scan()
+            .table_name(&self.name)
+            .consistent_read(true)
+            .into_paginator()
+            .items()
+            .send()
+            .collect::<Result<Vec<_>, _>>()
+            .await?;
+
+        Ok(items
+            .into_iter()
+            .filter(|item| !matches!(item.get(PK), Some(AttributeValue::S(pk)) if pk == META_PK))
+            .collect())
+    }
+
+    /// Applies the writes in batches, resending whatever DynamoDB hands back
+    /// as unprocessed until everything has gone through.
+    pub async fn write_all(&self, writes: Vec<Write>) -> Result<(), Error> {
+        let requests = writes.into_iter().map(write_request).collect::<Vec<_>>();
+
+        for batch in requests.chunks(MAX_BATCH_ITEMS) {
+            let mut pending = batch.to_vec();
+            let mut backoff = Duration::from_millis(50);
+            while !pending.is_empty() {
+                let response = self
+                    .client
+                    .batch_write_item()
+                    .request_items(&self.name, pending)
+                    .send()
+                    .await?;
+
+                pending = response
+                    .unprocessed_items()
+                    .and_then(|unprocessed| unprocessed.get(&self.name))
+                    .cloned()
+                    .unwrap_or_default();
+                if !pending.is_empty() {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(5));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_request(write: Write) -> WriteRequest {
+    match write {
+        Write::Put(item) => WriteRequest::builder()
+            .put_request(PutRequest::builder().set_item(Some(item)).build())
+            .build(),
+        Write::Delete { pk, sk } => WriteRequest::builder()
+            .delete_request(
+                DeleteRequest::builder()
+                    .set_key(Some(HashMap::from([
+                        (PK.to_string(), AttributeValue::S(pk)),
+                        (SK.to_string(), AttributeValue::S(sk)),
+                    ])))
+                    .build(),
+            )
+            .build(),
+    }
+}
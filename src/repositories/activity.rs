@@ -0,0 +1,215 @@
+//! The activity feed of a document: one event per write, in an
+//! `ACTIVITY#<document id>` partition keyed by ULID.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::model::AttributeValue;
+
+use crate::{
+    error::Result,
+    services::{get_s, id_of, Role, PARENT, PK, SK, TITLE},
+};
+
+use super::{new_id, Item, TagChange};
+
+pub const ACTOR: &str = "actor";
+pub const ACTION: &str = "action";
+pub const PATH: &str = "path";
+pub const TIMESTAMP: &str = "timestamp";
+pub const SUMMARY: &str = "summary";
+
+/// What an event did to the item at its path.
+#[derive(Clone, Copy)]
+pub(crate) enum Action {
+    Created,
+    Updated,
+    Tagged,
+    Moved,
+    Trashed,
+    Restored,
+    Reverted,
+    Shared,
+    Unshared,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Created => "created",
+            Action::Updated => "updated",
+            Action::Tagged => "tagged",
+            Action::Moved => "moved",
+            Action::Trashed => "trashed",
+            Action::Restored => "restored",
+            Action::Reverted => "reverted",
+            Action::Shared => "shared",
+            Action::Unshared => "unshared",
+        }
+    }
+}
+
+pub(crate) fn activity_key(document_id: &str) -> String {
+    format!("ACTIVITY#{}", document_id)
+}
+
+pub(crate) fn event_key(event_id: &str) -> String {
+    format!("EVENT#{}", event_id)
+}
+
+/// The route of the item stored under `sk` in the document, the way the API
+/// spells it, `/documents/<id>/groups/<id>/notes/<id>`.
+pub(crate) fn entity_path(document_id: &str, sk: &str) -> String {
+    let document = format!("/documents/{}", document_id);
+    let mut segments = sk.split('#');
+    match (segments.next(), segments.next(), segments.nth(1)) {
+        (Some("GROUP"), Some(group_id), Some(note_id)) => {
+            format!("{}/groups/{}/notes/{}", document, group_id, note_id)
+        }
+        (Some("GROUP"), Some(group_id), None) => format!("{}/groups/{}", document, group_id),
+        (Some("SHARE"), Some(user), _) => format!("{}/shares/{}", document, user),
+        _ => document,
+    }
+}
+
+/// The event of `actor` doing `action` to the item stored under `sk`, for
+/// the feed of the document with the id.
+pub(crate) fn event_item(
+    document_id: &str,
+    sk: &str,
+    actor: &str,
+    action: Action,
+    summary: String,
+    timestamp: i64,
+) -> Item {
+    HashMap::from([
+        (PK.to_string(), AttributeValue::S(activity_key(document_id))),
+        (SK.to_string(), AttributeValue::S(event_key(&new_id()))),
+        (ACTOR.to_string(), AttributeValue::S(actor.to_string())),
+        (
+            ACTION.to_string(),
+            AttributeValue::S(action.as_str().to_string()),
+        ),
+        (
+            PATH.to_string(),
+            AttributeValue::S(entity_path(document_id, sk)),
+        ),
+        (
+            TIMESTAMP.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        ),
+        (SUMMARY.to_string(), AttributeValue::S(summary)),
+    ])
+}
+
+/// Summarises an edit by the fields it changed, `changed title and
+/// description`.
+pub(crate) fn changes_summary(changed: &[&str]) -> String {
+    match changed {
+        [] => String::from("saved without changes"),
+        [field] => format!("changed {}", field),
+        [fields @ .., last] => format!("changed {} and {}", fields.join(", "), last),
+    }
+}
+
+/// Summarises a move by where the item came from, unless it only changed
+/// places among its siblings.
+pub(crate) fn move_summary(old: &Item) -> Result<String> {
+    let (pk, sk) = (get_s(old, PK)?, get_s(old, SK)?);
+    Ok(format!("moved from {}", entity_path(id_of(&pk), &sk)))
+}
+
+pub(crate) fn created_summary(item: &Item) -> String {
+    format!("created '{}'", get_s(item, TITLE).unwrap_or_default())
+}
+
+pub(crate) fn tag_summary(change: &TagChange<'_>) -> String {
+    match change {
+        TagChange::Add(tag) => format!("added tag '{}'", tag),
+        TagChange::Remove(tag) => format!("removed tag '{}'", tag),
+    }
+}
+
+pub(crate) fn share_summary(user: &str, role: Role) -> String {
+    format!("gave {} the {} role", user, role)
+}
+
+pub(crate) fn unshare_summary(user: &str) -> String {
+    format!("took away the access of {}", user)
+}
+
+/// Summarises a trash or restore by how many items went along with the one
+/// the event is about.
+pub(crate) fn trash_summary(action: Action, items: usize) -> String {
+    let what = match action {
+        Action::Restored => "restored from the trash",
+        _ => "moved to the trash",
+    };
+    match items.saturating_sub(1) {
+        0 => what.to_string(),
+        1 => format!("{} with 1 item below it", what),
+        below => format!("{} with {} items below it", what, below),
+    }
+}
+
+pub(crate) fn revert_summary(version: u64, changed: &[&str]) -> String {
+    format!(
+        "went back to version {}, {}",
+        version,
+        changes_summary(changed)
+    )
+}
+
+/// An event for each of the freshly stored items.
+pub(crate) fn created_events(
+    document_id: &str,
+    items: &[Item],
+    actor: &str,
+    timestamp: i64,
+) -> Result<Vec<Item>> {
+    items
+        .iter()
+        .map(|item| {
+            Ok(event_item(
+                document_id,
+                &get_s(item, SK)?,
+                actor,
+                Action::Created,
+                created_summary(item),
+                timestamp,
+            ))
+        })
+        .collect()
+}
+
+/// The event of a move, in the feeds of both documents when it crossed
+/// them. Reordering is not recorded.
+pub(crate) fn moved_events(
+    old: &Item,
+    new: &Item,
+    actor: &str,
+    timestamp: i64,
+) -> Result<Vec<Item>> {
+    let (from, to) = (get_s(old, PK)?, get_s(new, PK)?);
+    if from == to && get_s(old, PARENT)? == get_s(new, PARENT)? {
+        return Ok(Vec::new());
+    }
+    let event = event_item(
+        id_of(&to),
+        &get_s(new, SK)?,
+        actor,
+        Action::Moved,
+        move_summary(old)?,
+        timestamp,
+    );
+    let mut events = vec![event.clone()];
+    if from != to {
+        let mut event = event;
+        event.insert(
+            PK.to_string(),
+            AttributeValue::S(activity_key(id_of(&from))),
+        );
+        event.insert(SK.to_string(), AttributeValue::S(event_key(&new_id())));
+        events.push(event);
+    }
+    Ok(events)
+}
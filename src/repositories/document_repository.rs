@@ -1,250 +1,1241 @@
-use std::collections::HashMap;
-
+use async_trait::async_trait;
 use aws_sdk_dynamodb::{
-    model::{AttributeValue, Put, TransactWriteItem},
-    Client, Error,
+    model::{
+        AttributeValue, Delete, DeleteRequest, Put, PutRequest, TransactWriteItem, Update,
+        WriteRequest,
+    },
+    Client,
 };
+use std::{collections::HashMap, time::Duration};
+
 use chrono::Utc;
+use tokio_stream::StreamExt;
 
 use crate::{
-    controllers::DocumentReq,
+    controllers::{DocumentReq, DocumentUpdateReq, GroupReq, GroupUpdateReq, NoteUpdateReq},
+    error::{ApiError, Result},
     services::{
-        notes_service::Note, CREATED, DESCRIPTION, LAST_UPDATED, PARENT, PK, SK, TITLE, UPDATED_BY,
+        ensure_version, get_n, get_s, id_of, notes_service::Note, Role, CONTENT, GSI1PK, GSI1SK,
+        LAST_UPDATED, OWNER, PK, POSITION, SK, TAGS, TRASHED, TRASH_ROOT, UPDATED_BY, VERSION,
+    },
+};
+
+use super::{
+    activity::{
+        self, activity_key, changes_summary, created_events, event_item, event_key, moved_events,
+        Action,
     },
+    content_value, document_items, document_key, edited_fields, ensure_one_transaction, entity_of,
+    group_items, group_key, is_in_group, is_trashed, moved_group, moved_note, next_position,
+    not_in_trash, note_item, note_key, owner_key, restored_items,
+    revisions::{changed_fields, reverted_item, revision_item, revision_key, version_key},
+    search_index, share_item, share_key, shared_key, tagged_already, trash_key, trashed_items,
+    without_trashed, Field, Item, NotesStore, Page, Placement, TagChange, POSITION_STEP,
 };
 
+/// DynamoDB rejects batch writes with more requests than this.
+const MAX_BATCH_ITEMS: usize = 25;
+
+/// Sparse index holding one entry per document and share, see `owner_key`.
+const DOCUMENTS_INDEX: &str = "GSI1";
+
 pub struct DatabaseRepository {
     client: Client,
     table_name: String,
+    /// How long after a delete the table's TTL purges the trashed items.
+    trash_retention: Duration,
 }
 
 impl DatabaseRepository {
-    pub fn from_client(client: Client) -> Self {
-        let table_name =
-            std::env::var("TABLE_NAME").expect("A TABLE_NAME must be set in this app's Lambda");
-        Self { client, table_name }
+    pub fn new(client: Client, table_name: impl Into<String>, trash_retention: Duration) -> Self {
+        Self {
+            client,
+            table_name: table_name.into(),
+            trash_retention,
+        }
     }
 
-    pub(crate) async fn list_all(&self) -> Vec<HashMap<String, AttributeValue>> {
-        let req = self
+    /// Returns the items of a partition whose sort key starts with
+    /// `sk_prefix`, in sort key order.
+    async fn query_partition(&self, pk: &str, sk_prefix: &str) -> Result<Vec<Item>> {
+        let items = self
             .client
-            .scan()
+            .query()
             .table_name(&self.table_name)
+            .key_condition_expression("#pk = :pk AND begins_with(#sk, :prefix)")
+            .expression_attribute_names("#pk", PK)
+            .expression_attribute_names("#sk", SK)
+            .expression_attribute_values(":pk", AttributeValue::S(pk.to_string()))
+            .expression_attribute_values(":prefix", AttributeValue::S(sk_prefix.to_string()))
+            .consistent_read(true)
+            .into_paginator()
+            .items()
             .send()
-            .await
-            .unwrap();
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .await?;
+        Ok(items)
+    }
+
+    async fn query_group(&self, document_id: &str, group_id: &str) -> Result<Vec<Item>> {
+        let group_sk = group_key(group_id);
+        let items = self
+            .query_partition(&document_key(document_id), &group_sk)
+            .await?
+            .into_iter()
+            .filter(|item| get_s(item, SK).is_ok_and(|sk| is_in_group(&sk, &group_sk)))
+            .collect();
+        Ok(items)
+    }
 
-        let items = req.items().unwrap();
-        items.to_vec()
+    /// The position after the document's last group.
+    async fn next_group_position(&self, document_id: &str) -> Result<i64> {
+        let key = document_key(document_id);
+        let groups = self.query_partition(&key, &group_key("")).await?;
+        next_position(&groups, &key)
     }
 
-    pub(crate) async fn fetch_by_id(&self, id: &str) -> Vec<HashMap<String, AttributeValue>> {
-        let mut sk = String::from("DOCUMENT#");
-        let mut notes_sk = String::from("GROUP#");
-        sk.push_str(id);
-        notes_sk.push_str(id);
-        let mut keys = HashMap::new();
-        keys.insert("PK".to_string(), AttributeValue::S("document".to_string()));
-        keys.insert("SK".to_string(), AttributeValue::S(sk.clone()));
+    async fn get(&self, pk: String, sk: String) -> Result<Option<Item>> {
         let response = self
             .client
             .get_item()
             .table_name(&self.table_name)
-            .set_key(Some(keys))
+            .key(PK, AttributeValue::S(pk))
+            .key(SK, AttributeValue::S(sk))
             .consistent_read(true)
             .send()
-            .await
-            .unwrap();
+            .await?;
+        Ok(response.item().cloned())
+    }
 
-        let groups = self
+    /// Returns one page of a `GSI1` partition, continuing after `start_key`.
+    async fn query_documents_index(
+        &self,
+        partition: String,
+        limit: usize,
+        start_key: Option<Item>,
+    ) -> Result<Page> {
+        let response = self
             .client
             .query()
             .table_name(&self.table_name)
-            .key_condition_expression("#pk = :pk")
-            .filter_expression("contains(#parent, :parentId)")
-            .expression_attribute_names("#pk", "PK")
-            .expression_attribute_values(":pk", AttributeValue::S("group".to_string()))
-            .expression_attribute_values(":parentId", AttributeValue::S(sk))
-            .expression_attribute_names("#parent", "parent")
+            .index_name(DOCUMENTS_INDEX)
+            .key_condition_expression("#gsi1pk = :gsi1pk")
+            .expression_attribute_names("#gsi1pk", GSI1PK)
+            .expression_attribute_values(":gsi1pk", AttributeValue::S(partition))
+            .limit(limit as i32)
+            .set_exclusive_start_key(start_key)
             .send()
-            .await
-            .unwrap();
+            .await?;
 
-        let notes = self
-            .client
-            .query()
+        let items = response.items().unwrap_or_default().to_vec();
+        let next = response
+            .last_evaluated_key()
+            .map(|key| get_s(key, GSI1SK))
+            .transpose()?
+            .map(|sk| id_of(&sk).to_string());
+        Ok(Page { items, next })
+    }
+
+    async fn put_new(&self, item: Item) -> Result<Item> {
+        self.client
+            .put_item()
             .table_name(&self.table_name)
-            .key_condition_expression("#pk = :pk")
-            .filter_expression("contains(#parent, :parentId)")
-            .expression_attribute_names("#pk", "PK")
-            .expression_attribute_values(":pk", AttributeValue::S("note".to_string()))
-            .expression_attribute_values(":parentId", AttributeValue::S(notes_sk))
-            .expression_attribute_names("#parent", "parent")
+            .set_item(Some(item.clone()))
+            .condition_expression("attribute_not_exists(#sk)")
+            .expression_attribute_names("#sk", SK)
+            .send()
+            .await?;
+        Ok(item)
+    }
+
+    /// Stores new items in one transaction, each only while nothing is
+    /// stored under its key yet.
+    async fn put_new_all(&self, items: &[Item]) -> Result<()> {
+        let transactions = items
+            .iter()
+            .map(|item| {
+                let put = Put::builder()
+                    .table_name(&self.table_name)
+                    .set_item(Some(item.clone()))
+                    .condition_expression("attribute_not_exists(#sk)")
+                    .expression_attribute_names("#sk", SK)
+                    .build();
+                TransactWriteItem::builder().put(put).build()
+            })
+            .collect();
+        self.client
+            .transact_write_items()
+            .set_transact_items(Some(transactions))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// The owner of the document, which may be in the trash.
+    async fn owner_of(&self, document_id: &str) -> Result<String> {
+        let key = document_key(document_id);
+        let document = self
+            .get(key.clone(), key)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Document", document_id))?;
+        get_s(&document, OWNER)
+    }
+
+    /// The item under `sk` followed by everything below it, trashed or not.
+    async fn subtree(&self, document_id: &str, sk: &str) -> Result<Vec<Item>> {
+        let pk = document_key(document_id);
+        if sk == pk {
+            self.query_partition(&pk, "").await
+        } else if entity_of(sk) == "Group" {
+            self.query_group(document_id, id_of(sk)).await
+        } else {
+            Ok(self.get(pk, sk.to_string()).await?.into_iter().collect())
+        }
+    }
+
+    /// Overwrites the items in one transaction with the revision of the first,
+    /// which has to still be at `expected_version`.
+    async fn put_all(
+        &self,
+        items: &[Item],
+        revision: Item,
+        entity: &str,
+        id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let transactions = items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let expected = expected_version.filter(|_| index == 0);
+                let (condition, names, values) = version_condition(expected);
+                let put = Put::builder()
+                    .table_name(&self.table_name)
+                    .set_item(Some(item.clone()))
+                    .condition_expression(condition)
+                    .set_expression_attribute_names(Some(names))
+                    .set_expression_attribute_values(values)
+                    .build();
+                TransactWriteItem::builder().put(put).build()
+            })
+            .chain([self.put_revision(revision)])
+            .collect();
+        self.client
+            .transact_write_items()
+            .set_transact_items(Some(transactions))
             .send()
             .await
-            .unwrap();
+            .map_err(|err| stale_write(err.into(), entity, id, expected_version))?;
+        Ok(())
+    }
 
-        let response = response.item().unwrap();
+    /// Brings the owner's search index from matching the `old` items to
+    /// matching the `new` ones, see `search_index::changes`.
+    async fn update_index(&self, owner: &str, old: &[Item], new: &[Item]) -> Result<()> {
+        let (puts, deletes) = search_index::changes(owner, old, new);
+        let mut requests = puts
+            .into_iter()
+            .map(|entry| {
+                WriteRequest::builder()
+                    .put_request(PutRequest::builder().set_item(Some(entry)).build())
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        for entry in deletes.iter() {
+            let key = HashMap::from([
+                (PK.to_string(), AttributeValue::S(get_s(entry, PK)?)),
+                (SK.to_string(), AttributeValue::S(get_s(entry, SK)?)),
+            ]);
+            requests.push(
+                WriteRequest::builder()
+                    .delete_request(DeleteRequest::builder().set_key(Some(key)).build())
+                    .build(),
+            );
+        }
+        self.batch_write(requests).await
+    }
 
-        let mut document = groups.items().unwrap().to_vec();
-        let mut notes = notes.items().unwrap().to_vec();
+    /// Sends the requests in batches, resending whatever DynamoDB hands back
+    /// as unprocessed until everything has gone through.
+    async fn batch_write(&self, requests: Vec<WriteRequest>) -> Result<()> {
+        for batch in requests.chunks(MAX_BATCH_ITEMS) {
+            let mut pending = batch.to_vec();
+            let mut backoff = Duration::from_millis(50);
+            while !pending.is_empty() {
+                let response = self
+                    .client
+                    .batch_write_item()
+                    .request_items(&self.table_name, pending)
+                    .send()
+                    .await?;
 
-        document.append(&mut notes);
-        document.push(response.to_owned());
+                pending = response
+                    .unprocessed_items()
+                    .and_then(|unprocessed| unprocessed.get(&self.table_name))
+                    .cloned()
+                    .unwrap_or_default();
+                if !pending.is_empty() {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(5));
+                }
+            }
+        }
+        Ok(())
+    }
 
-        document
+    /// Adds the events to their documents' activity feeds. Events are only
+    /// recorded once the write they describe went through.
+    async fn record(&self, events: Vec<Item>) -> Result<()> {
+        let requests = events
+            .into_iter()
+            .map(|event| {
+                let put = PutRequest::builder().set_item(Some(event)).build();
+                WriteRequest::builder().put_request(put).build()
+            })
+            .collect();
+        self.batch_write(requests).await
     }
 
-    pub(crate) async fn save(&self, document: &DocumentReq) -> Result<(), Error> {
-        let timestamp = Utc::now().timestamp();
-        let mut sk = String::from("DOCUMENT#");
-        sk.push_str(&timestamp.to_string());
-
-        let pk = AttributeValue::S("document".to_string());
-        let sk = AttributeValue::S(sk);
-        let title = AttributeValue::S(document.title.clone());
-        let created = AttributeValue::N(timestamp.to_string());
-        let last_updated = AttributeValue::S(timestamp.to_string());
-        let updated_by = AttributeValue::S("NITROGEN:Thomas".to_string());
-        let description = AttributeValue::S(document.description.clone());
-
-        let document_item = Put::builder()
+    /// Builds a `SET` update of the fields that bumps the version and only
+    /// applies while the item is still at `version`.
+    fn update_fields(
+        &self,
+        pk: &str,
+        sk: &str,
+        fields: Vec<Field>,
+        last_updated: i64,
+        updated_by: &str,
+        version: u64,
+    ) -> Update {
+        let mut assignments = vec![
+            String::from("#lastUpdated = :lastUpdated"),
+            String::from("#updatedBy = :updatedBy"),
+            String::from("#version = #version + :one"),
+        ];
+        let mut update = Update::builder()
+            .table_name(&self.table_name)
+            .key(PK, AttributeValue::S(pk.to_string()))
+            .key(SK, AttributeValue::S(sk.to_string()))
+            .expression_attribute_names("#sk", SK)
+            .expression_attribute_names("#trashed", TRASHED)
+            .expression_attribute_names("#version", VERSION)
+            .expression_attribute_values(":one", AttributeValue::N(String::from("1")))
+            .expression_attribute_values(":version", AttributeValue::N(version.to_string()))
+            .expression_attribute_names("#lastUpdated", LAST_UPDATED)
+            .expression_attribute_names("#updatedBy", UPDATED_BY)
+            .expression_attribute_values(
+                ":lastUpdated",
+                AttributeValue::N(last_updated.to_string()),
+            )
+            .expression_attribute_values(":updatedBy", AttributeValue::S(updated_by.to_string()));
+        for (name, value) in fields {
+            assignments.push(format!("#{0} = :{0}", name));
+            update = update
+                .expression_attribute_names(format!("#{}", name), name)
+                .expression_attribute_values(format!(":{}", name), value);
+        }
+        update
+            .condition_expression(
+                "attribute_exists(#sk) AND attribute_not_exists(#trashed) AND #version = :version",
+            )
+            .update_expression(format!("SET {}", assignments.join(", ")))
+            .build()
+    }
+
+    /// Stores a revision, which is never overwritten.
+    fn put_revision(&self, revision: Item) -> TransactWriteItem {
+        let put = Put::builder()
             .table_name(&self.table_name)
-            .item(PK, pk)
-            .item(SK, sk)
-            .item(TITLE, title)
-            .item(CREATED, created)
-            .item(LAST_UPDATED, last_updated)
-            .item(UPDATED_BY, updated_by)
-            .item(DESCRIPTION, description)
+            .set_item(Some(revision))
+            .condition_expression("attribute_not_exists(#sk)")
+            .expression_attribute_names("#sk", SK)
             .build();
-        let document_item = TransactWriteItem::builder().put(document_item).build();
+        TransactWriteItem::builder().put(put).build()
+    }
 
+    /// Applies the update together with the revision of the version it
+    /// replaces.
+    async fn transact_revised(&self, update: Update, revision: Item) -> Result<()> {
+        self.client
+            .transact_write_items()
+            .transact_items(TransactWriteItem::builder().update(update).build())
+            .transact_items(self.put_revision(revision))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Replaces the `old` items of a move with the `new` ones in one
+    /// transaction with the revision of the first.
+    async fn relocate(
+        &self,
+        old: &[Item],
+        new: &[Item],
+        revision: Item,
+        entity: &str,
+        id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
         let mut transactions = Vec::new();
-        transactions.push(document_item);
-
-        if !document.groups.is_empty() {
-            for group in document.groups.iter() {
-                let mut group_sk = String::from("GROUP#");
-                group_sk.push_str(&timestamp.to_string());
-
-                let mut parent = String::from("DOCUMENT#");
-                parent.push_str(&timestamp.to_string());
-
-                let pk = AttributeValue::S("group".to_string());
-                let sk = AttributeValue::S(group_sk.clone());
-                let title = AttributeValue::S(group.title.clone());
-                let created = AttributeValue::N(group.created.to_string());
-                let last_updated = AttributeValue::N(group.created.to_string());
-                let updated_by = AttributeValue::S("NITROGEN:Thomas".to_string());
-                let description = AttributeValue::S(group.description.clone());
-                let parent = AttributeValue::S(parent.clone());
-
-                let group_item = Put::builder()
+        for (index, (old, new)) in old.iter().zip(new).enumerate() {
+            if old == new {
+                continue;
+            }
+            let expected = expected_version.filter(|_| index == 0);
+            let (pk, sk) = (get_s(old, PK)?, get_s(old, SK)?);
+            let same_key = pk == get_s(new, PK)? && sk == get_s(new, SK)?;
+
+            let (condition, names, values) = version_condition(expected);
+            let put = Put::builder()
+                .table_name(&self.table_name)
+                .set_item(Some(new.clone()));
+            let put = if same_key {
+                put.condition_expression(condition)
+                    .set_expression_attribute_names(Some(names))
+                    .set_expression_attribute_values(values)
+            } else {
+                let delete = Delete::builder()
                     .table_name(&self.table_name)
-                    .item(PK, pk)
-                    .item(SK, sk)
-                    .item(TITLE, title)
-                    .item(CREATED, created)
-                    .item(LAST_UPDATED, last_updated)
-                    .item(UPDATED_BY, updated_by)
-                    .item(DESCRIPTION, description)
-                    .item(PARENT, parent)
+                    .key(PK, AttributeValue::S(pk))
+                    .key(SK, AttributeValue::S(sk))
+                    .condition_expression(condition)
+                    .set_expression_attribute_names(Some(names))
+                    .set_expression_attribute_values(values)
                     .build();
-
-                let transaction_item = TransactWriteItem::builder().put(group_item).build();
-                transactions.push(transaction_item);
-            }
+                transactions.push(TransactWriteItem::builder().delete(delete).build());
+                put.condition_expression("attribute_not_exists(#sk)")
+                    .expression_attribute_names("#sk", SK)
+            };
+            transactions.push(TransactWriteItem::builder().put(put.build()).build());
         }
+        transactions.push(self.put_revision(revision));
 
+        ensure_one_transaction(transactions.len(), entity, id, "moved")?;
         self.client
             .transact_write_items()
             .set_transact_items(Some(transactions))
             .send()
             .await
-            .unwrap();
-
+            .map_err(|err| stale_write(err.into(), entity, id, expected_version))?;
         Ok(())
     }
+}
+
+#[async_trait]
+impl NotesStore for DatabaseRepository {
+    async fn list_documents(&self, owner: &str, limit: usize, after: Option<&str>) -> Result<Page> {
+        // Document items carry the same key in PK, SK and GSI1SK, so the id
+        // alone is enough to rebuild the index key DynamoDB continues from.
+        let start_key = after.map(|id| {
+            let key = document_key(id);
+            index_key(key.clone(), key, owner_key(owner))
+        });
+        self.query_documents_index(owner_key(owner), limit, start_key)
+            .await
+    }
+
+    async fn fetch_by_id(&self, id: &str) -> Result<Vec<Item>> {
+        let items = without_trashed(self.query_partition(&document_key(id), "").await?);
+        if items.is_empty() {
+            return Err(ApiError::not_found("Document", id));
+        }
+        Ok(items)
+    }
+
+    async fn save(&self, document: &DocumentReq, owner: &str) -> Result<Vec<Item>> {
+        let timestamp = Utc::now().timestamp();
+        let items = document_items(document, owner, timestamp)?;
+        let document_id = id_of(&get_s(&items[0], PK)?).to_string();
+        ensure_one_transaction(items.len(), "Document", &document_id, "created")?;
+        self.put_new_all(&items).await?;
+
+        self.update_index(owner, &[], &items).await?;
+        self.record(created_events(&document_id, &items, owner, timestamp)?)
+            .await?;
+        Ok(items)
+    }
 
-    pub(crate) async fn fetch_document_by_id(
+    async fn fetch_document_by_id(&self, id: &str) -> Result<Item> {
+        let key = document_key(id);
+        self.get(key.clone(), key)
+            .await?
+            .filter(|document| !is_trashed(document))
+            .ok_or_else(|| ApiError::not_found("Document", id))
+    }
+
+    async fn fetch_group_by_id(&self, document_id: &str, group_id: &str) -> Result<Item> {
+        self.get(document_key(document_id), group_key(group_id))
+            .await?
+            .filter(|group| !is_trashed(group))
+            .ok_or_else(|| ApiError::not_found("Group", group_id))
+    }
+
+    async fn save_note(&self, document_id: &str, note: &Note) -> Result<Item> {
+        let group_id = id_of(&note.parent);
+        let notes = self.query_group(document_id, group_id).await?;
+        let position = next_position(&notes, &note.parent)?;
+        let item = self.put_new(note_item(document_id, note, position)).await?;
+        let owner = self.owner_of(document_id).await?;
+        self.update_index(&owner, &[], std::slice::from_ref(&item))
+            .await?;
+        let events = created_events(
+            document_id,
+            std::slice::from_ref(&item),
+            &note.created_by,
+            note.created,
+        )?;
+        self.record(events).await?;
+        Ok(item)
+    }
+
+    async fn update_document(
         &self,
         id: &str,
-    ) -> Vec<HashMap<String, AttributeValue>> {
-        let mut sk = String::from("DOCUMENT#");
-        sk.push_str(id);
-        let mut keys = HashMap::new();
-        keys.insert("PK".to_string(), AttributeValue::S("document".to_string()));
-        keys.insert("SK".to_string(), AttributeValue::S(sk.clone()));
+        update: &DocumentUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self.fetch_document_by_id(id).await?;
+        ensure_version(&old, expected_version, "Document", id)?;
+
+        let timestamp = Utc::now().timestamp();
+        let document_sk = document_key(id);
+        let first = self.next_group_position(id).await?;
+        let mut groups = Vec::new();
+        for (position, group) in (0..)
+            .map(|n| first + n * POSITION_STEP)
+            .zip(update.groups.iter())
+        {
+            groups.extend(group_items(
+                group,
+                &document_sk,
+                updated_by,
+                timestamp,
+                position,
+            )?);
+        }
+        ensure_one_transaction(groups.len(), "Document", id, "given these groups")?;
+
+        // Adding groups leaves the document's own fields and version alone.
+        let fields = edited_fields(update.title.as_ref(), update.description.as_ref());
+        let changed = changed_fields(&old, &fields);
+        if !changed.is_empty() {
+            let revision = revision_item(&old, &changed, updated_by, timestamp)?;
+            let document_update = self.update_fields(
+                &document_sk,
+                &document_sk,
+                fields,
+                timestamp,
+                updated_by,
+                get_n(&old, VERSION)?,
+            );
+            self.transact_revised(document_update, revision)
+                .await
+                .map_err(|err| stale_write(err, "Document", id, expected_version))?;
+        }
+
+        // The document and its revision leave no room for as many groups as
+        // a request may add, so those follow in a transaction of their own.
+        if !groups.is_empty() {
+            self.put_new_all(&groups).await?;
+        }
+
+        let mut new = vec![self.fetch_document_by_id(id).await?];
+        new.extend(groups);
+        self.update_index(&get_s(&old, OWNER)?, &[old], &new)
+            .await?;
+
+        let mut events = Vec::new();
+        if !changed.is_empty() {
+            events.push(event_item(
+                id,
+                &document_sk,
+                updated_by,
+                Action::Updated,
+                changes_summary(&changed),
+                timestamp,
+            ));
+        }
+        events.extend(created_events(id, &new[1..], updated_by, timestamp)?);
+        self.record(events).await
+    }
+
+    async fn save_group(
+        &self,
+        document_id: &str,
+        group: &GroupReq,
+        created_by: &str,
+    ) -> Result<Vec<Item>> {
+        let position = self.next_group_position(document_id).await?;
+        let timestamp = Utc::now().timestamp();
+        let items = group_items(
+            group,
+            &document_key(document_id),
+            created_by,
+            timestamp,
+            position,
+        )?;
+        let group_id = id_of(&get_s(&items[0], SK)?).to_string();
+        ensure_one_transaction(items.len(), "Group", &group_id, "created")?;
+        self.put_new_all(&items).await?;
+        let owner = self.owner_of(document_id).await?;
+        self.update_index(&owner, &[], &items).await?;
+        self.record(created_events(document_id, &items, created_by, timestamp)?)
+            .await?;
+        Ok(items)
+    }
+
+    async fn fetch_group_with_notes(&self, document_id: &str, group_id: &str) -> Result<Vec<Item>> {
+        let items = without_trashed(self.query_group(document_id, group_id).await?);
+        if items.is_empty() {
+            return Err(ApiError::not_found("Group", group_id));
+        }
+        Ok(items)
+    }
+
+    async fn update_group(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        update: &GroupUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self.fetch_group_by_id(document_id, group_id).await?;
+        ensure_version(&old, expected_version, "Group", group_id)?;
+        let timestamp = Utc::now().timestamp();
+        let fields = edited_fields(update.title.as_ref(), update.description.as_ref());
+        let changed = changed_fields(&old, &fields);
+        let revision = revision_item(&old, &changed, updated_by, timestamp)?;
+        let group_update = self.update_fields(
+            &document_key(document_id),
+            &group_key(group_id),
+            fields,
+            timestamp,
+            updated_by,
+            get_n(&old, VERSION)?,
+        );
+        self.transact_revised(group_update, revision)
+            .await
+            .map_err(|err| stale_write(err, "Group", group_id, expected_version))?;
+
+        let new = self.fetch_group_by_id(document_id, group_id).await?;
+        let owner = self.owner_of(document_id).await?;
+        self.update_index(&owner, &[old], &[new]).await?;
+        self.record(vec![event_item(
+            document_id,
+            &group_key(group_id),
+            updated_by,
+            Action::Updated,
+            changes_summary(&changed),
+            timestamp,
+        )])
+        .await
+    }
+
+    async fn fetch_note_by_id(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+    ) -> Result<Item> {
+        self.get(document_key(document_id), note_key(group_id, note_id))
+            .await?
+            .filter(|note| !is_trashed(note))
+            .ok_or_else(|| ApiError::not_found("Note", note_id))
+    }
+
+    async fn update_note(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+        update: &NoteUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self
+            .fetch_note_by_id(document_id, group_id, note_id)
+            .await?;
+        ensure_version(&old, expected_version, "Note", note_id)?;
+        let timestamp = Utc::now().timestamp();
+        let mut fields = edited_fields(update.title.as_ref(), update.description.as_ref());
+        if let Some(content) = &update.content {
+            fields.push((CONTENT, content_value(content)));
+        }
+        let changed = changed_fields(&old, &fields);
+        let revision = revision_item(&old, &changed, updated_by, timestamp)?;
+        let note_update = self.update_fields(
+            &document_key(document_id),
+            &note_key(group_id, note_id),
+            fields,
+            timestamp,
+            updated_by,
+            get_n(&old, VERSION)?,
+        );
+        self.transact_revised(note_update, revision)
+            .await
+            .map_err(|err| stale_write(err, "Note", note_id, expected_version))?;
+
+        let new = self
+            .fetch_note_by_id(document_id, group_id, note_id)
+            .await?;
+        let owner = self.owner_of(document_id).await?;
+        self.update_index(&owner, &[old], &[new]).await?;
+        self.record(vec![event_item(
+            document_id,
+            &note_key(group_id, note_id),
+            updated_by,
+            Action::Updated,
+            changes_summary(&changed),
+            timestamp,
+        )])
+        .await
+    }
+
+    async fn list_shared_documents(
+        &self,
+        user: &str,
+        limit: usize,
+        after: Option<&str>,
+    ) -> Result<Page> {
+        let start_key =
+            after.map(|id| index_key(document_key(id), share_key(user), shared_key(user)));
+        let page = self
+            .query_documents_index(shared_key(user), limit, start_key)
+            .await?;
+
+        let mut items = Vec::with_capacity(page.items.len());
+        for share in page.items.iter() {
+            let key = get_s(share, PK)?;
+            // Skips a document deleted since the share was listed.
+            if let Some(document) = self.get(key.clone(), key).await? {
+                if !is_trashed(&document) {
+                    items.push(document);
+                }
+            }
+        }
+        Ok(Page {
+            items,
+            next: page.next,
+        })
+    }
+
+    async fn fetch_share(&self, document_id: &str, user: &str) -> Result<Option<Item>> {
+        let share = self.get(document_key(document_id), share_key(user)).await?;
+        Ok(share.filter(|share| !is_trashed(share)))
+    }
+
+    async fn fetch_shares(&self, document_id: &str) -> Result<Vec<Item>> {
+        let shares = self
+            .query_partition(&document_key(document_id), &share_key(""))
+            .await?;
+        Ok(without_trashed(shares))
+    }
+
+    async fn save_share(
+        &self,
+        document_id: &str,
+        user: &str,
+        role: Role,
+        granted_by: &str,
+    ) -> Result<Item> {
+        let timestamp = Utc::now().timestamp();
+        let item = share_item(document_id, user, role, granted_by, timestamp);
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item.clone()))
+            .send()
+            .await?;
+        self.record(vec![event_item(
+            document_id,
+            &share_key(user),
+            granted_by,
+            Action::Shared,
+            activity::share_summary(user, role),
+            timestamp,
+        )])
+        .await?;
+        Ok(item)
+    }
+
+    async fn delete_share(&self, document_id: &str, user: &str, revoked_by: &str) -> Result<()> {
+        let share = self
+            .fetch_share(document_id, user)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Share", user))?;
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key(PK, AttributeValue::S(get_s(&share, PK)?))
+            .key(SK, AttributeValue::S(get_s(&share, SK)?))
+            .send()
+            .await?;
+        self.record(vec![event_item(
+            document_id,
+            &share_key(user),
+            revoked_by,
+            Action::Unshared,
+            activity::unshare_summary(user),
+            Utc::now().timestamp(),
+        )])
+        .await
+    }
+
+    async fn search(&self, owner: &str, token: &str) -> Result<Vec<Item>> {
+        self.query_partition(&search_index::search_key(owner, token), "")
+            .await
+    }
+
+    async fn update_tags(
+        &self,
+        document_id: &str,
+        sk: &str,
+        change: TagChange<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<Item> {
+        let pk = document_key(document_id);
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let old = self
+            .get(pk.clone(), sk.to_string())
+            .await?
+            .filter(|item| !is_trashed(item))
+            .ok_or_else(|| ApiError::not_found(entity, id))?;
+        ensure_version(&old, expected_version, entity, id)?;
+        if tagged_already(&old, &change) {
+            return Ok(old);
+        }
+        let timestamp = Utc::now().timestamp();
+        let revision = revision_item(&old, &[TAGS], updated_by, timestamp)?;
+
+        let (action, tag) = match change {
+            TagChange::Add(tag) => ("ADD", tag),
+            TagChange::Remove(tag) => ("DELETE", tag),
+        };
+        let update = Update::builder()
+            .table_name(&self.table_name)
+            .key(PK, AttributeValue::S(pk.clone()))
+            .key(SK, AttributeValue::S(sk.to_string()))
+            .expression_attribute_names("#sk", SK)
+            .expression_attribute_names("#trashed", TRASHED)
+            .expression_attribute_names("#version", VERSION)
+            .expression_attribute_names("#lastUpdated", LAST_UPDATED)
+            .expression_attribute_names("#updatedBy", UPDATED_BY)
+            .expression_attribute_names("#tags", TAGS)
+            .expression_attribute_values(":one", AttributeValue::N(String::from("1")))
+            .expression_attribute_values(
+                ":version",
+                AttributeValue::N(get_n::<u64>(&old, VERSION)?.to_string()),
+            )
+            .expression_attribute_values(":lastUpdated", AttributeValue::N(timestamp.to_string()))
+            .expression_attribute_values(":updatedBy", AttributeValue::S(updated_by.to_string()))
+            .expression_attribute_values(":tags", AttributeValue::Ss(vec![tag.to_string()]))
+            .condition_expression(
+                "attribute_exists(#sk) AND attribute_not_exists(#trashed) AND #version = :version",
+            )
+            .update_expression(format!(
+                "SET #lastUpdated = :lastUpdated, #updatedBy = :updatedBy, \
+                 #version = #version + :one {} #tags :tags",
+                action
+            ))
+            .build();
+        self.transact_revised(update, revision)
+            .await
+            .map_err(|err| stale_write(err, entity, id, expected_version))?;
+
+        let new = self
+            .get(pk, sk.to_string())
+            .await?
+            .ok_or_else(|| ApiError::not_found(entity, id))?;
+        let owner = self.owner_of(document_id).await?;
+        self.update_index(&owner, &[old], std::slice::from_ref(&new))
+            .await?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            updated_by,
+            Action::Tagged,
+            activity::tag_summary(&change),
+            timestamp,
+        )])
+        .await?;
+        Ok(new)
+    }
+
+    async fn tagged(
+        &self,
+        owner: &str,
+        tag: &str,
+        limit: usize,
+        after: Option<&str>,
+    ) -> Result<Page> {
+        let pk = search_index::tag_key(owner, tag);
+        let start_key = after.map(|sk| {
+            HashMap::from([
+                (PK.to_string(), AttributeValue::S(pk.clone())),
+                (SK.to_string(), AttributeValue::S(sk.to_string())),
+            ])
+        });
         let response = self
             .client
-            .get_item()
+            .query()
             .table_name(&self.table_name)
-            .set_key(Some(keys))
-            .consistent_read(true)
+            .key_condition_expression("#pk = :pk")
+            .expression_attribute_names("#pk", PK)
+            .expression_attribute_values(":pk", AttributeValue::S(pk))
+            .limit(limit as i32)
+            .set_exclusive_start_key(start_key)
             .send()
+            .await?;
+
+        let items = response.items().unwrap_or_default().to_vec();
+        let next = match (response.last_evaluated_key(), items.last()) {
+            (Some(_), Some(last)) => Some(get_s(last, SK)?),
+            _ => None,
+        };
+        Ok(Page { items, next })
+    }
+
+    async fn set_position(&self, document_id: &str, sk: &str, position: i64) -> Result<()> {
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(PK, AttributeValue::S(document_key(document_id)))
+            .key(SK, AttributeValue::S(sk.to_string()))
+            .condition_expression("attribute_exists(#sk)")
+            .update_expression("SET #position = :position")
+            .expression_attribute_names("#sk", SK)
+            .expression_attribute_names("#position", POSITION)
+            .expression_attribute_values(":position", AttributeValue::N(position.to_string()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn move_group(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        to: &Placement<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        self.fetch_group_by_id(document_id, group_id).await?;
+        let old = self.query_group(document_id, group_id).await?;
+        let owner = self.owner_of(to.document_id).await?;
+        let timestamp = Utc::now().timestamp();
+        let new = moved_group(&old, to, &owner, updated_by, timestamp)?;
+        let revision = revision_item(&old[0], &[], updated_by, timestamp)?;
+        self.relocate(&old, &new, revision, "Group", group_id, expected_version)
+            .await?;
+
+        // Index entries are keyed by the item's path, so a move replaces them
+        // even when the owner stays the same.
+        let from = self.owner_of(document_id).await?;
+        self.update_index(&from, &old, &[]).await?;
+        self.update_index(&owner, &[], &new).await?;
+        self.record(moved_events(&old[0], &new[0], updated_by, timestamp)?)
             .await
-            .unwrap();
+    }
 
-        let mut documents = Vec::new();
-        documents.push(response.item().unwrap().to_owned());
-        documents
+    async fn move_note(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+        to: &Placement<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self
+            .fetch_note_by_id(document_id, group_id, note_id)
+            .await?;
+        let timestamp = Utc::now().timestamp();
+        let new = moved_note(&old, to, updated_by, timestamp)?;
+        self.relocate(
+            std::slice::from_ref(&old),
+            std::slice::from_ref(&new),
+            revision_item(&old, &[], updated_by, timestamp)?,
+            "Note",
+            note_id,
+            expected_version,
+        )
+        .await?;
+
+        let events = moved_events(&old, &new, updated_by, timestamp)?;
+        let from = self.owner_of(document_id).await?;
+        self.update_index(&from, &[old], &[]).await?;
+        let owner = self.owner_of(to.document_id).await?;
+        self.update_index(&owner, &[], &[new]).await?;
+        self.record(events).await
     }
 
-    pub(crate) async fn fetch_group_by_id(
+    async fn trash(
         &self,
-        group_id: i32,
-    ) -> Vec<HashMap<String, AttributeValue>> {
-        let group_id = group_id.to_string();
-        let mut sk = String::from("GROUP#");
-        sk.push_str(&group_id);
+        document_id: &str,
+        sk: &str,
+        trashed_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let items = without_trashed(self.subtree(document_id, sk).await?);
+        if !items
+            .first()
+            .is_some_and(|item| get_s(item, SK).is_ok_and(|item_sk| item_sk == sk))
+        {
+            return Err(ApiError::not_found(entity, id));
+        }
+
+        let owner = self.owner_of(document_id).await?;
+        let timestamp = Utc::now().timestamp();
+        let expires_at = timestamp + self.trash_retention.as_secs() as i64;
+        let trashed = trashed_items(&items, &owner, trashed_by, timestamp, expires_at)?;
+        let revision = revision_item(&items[0], &[], trashed_by, timestamp)?;
+        ensure_one_transaction(trashed.len() + 1, entity, id, "moved to the trash")?;
+        self.put_all(&trashed, revision, entity, id, expected_version)
+            .await?;
+        self.update_index(&owner, &items, &[]).await?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            trashed_by,
+            Action::Trashed,
+            activity::trash_summary(Action::Trashed, items.len()),
+            timestamp,
+        )])
+        .await
+    }
+
+    async fn fetch_trashed(&self, document_id: &str, sk: &str) -> Result<Item> {
+        self.get(document_key(document_id), sk.to_string())
+            .await?
+            .filter(|item| get_s(item, TRASH_ROOT).is_ok_and(|root| root == sk))
+            .ok_or_else(|| not_in_trash(sk))
+    }
 
-        let mut keys = HashMap::new();
-        keys.insert("PK".to_string(), AttributeValue::S("group".to_string()));
-        keys.insert("SK".to_string(), AttributeValue::S(sk));
+    async fn restore(&self, document_id: &str, sk: &str, restored_by: &str) -> Result<()> {
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let items = self
+            .subtree(document_id, sk)
+            .await?
+            .into_iter()
+            .filter(|item| get_s(item, TRASH_ROOT).is_ok_and(|root| root == sk))
+            .collect::<Vec<_>>();
+        if items.is_empty() {
+            return Err(not_in_trash(sk));
+        }
+
+        let owner = self.owner_of(document_id).await?;
+        let timestamp = Utc::now().timestamp();
+        let restored = restored_items(&items, &owner, restored_by, timestamp)?;
+        let revision = revision_item(&items[0], &[], restored_by, timestamp)?;
+        ensure_one_transaction(restored.len() + 1, entity, id, "restored")?;
+        self.put_all(&restored, revision, entity, id, None).await?;
+        self.update_index(&owner, &[], &restored).await?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            restored_by,
+            Action::Restored,
+            activity::trash_summary(Action::Restored, restored.len()),
+            timestamp,
+        )])
+        .await
+    }
 
+    async fn list_trash(&self, owner: &str) -> Result<Vec<Item>> {
+        let items = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .index_name(DOCUMENTS_INDEX)
+            .key_condition_expression("#gsi1pk = :gsi1pk")
+            .expression_attribute_names("#gsi1pk", GSI1PK)
+            .expression_attribute_values(":gsi1pk", AttributeValue::S(trash_key(owner)))
+            .into_paginator()
+            .items()
+            .send()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .await?;
+        Ok(items)
+    }
+
+    async fn list_revisions(
+        &self,
+        entity_id: &str,
+        limit: usize,
+        before: Option<u64>,
+    ) -> Result<Page> {
+        let pk = revision_key(entity_id);
+        let start_key = before.map(|version| {
+            HashMap::from([
+                (PK.to_string(), AttributeValue::S(pk.clone())),
+                (SK.to_string(), AttributeValue::S(version_key(version))),
+            ])
+        });
         let response = self
             .client
-            .get_item()
+            .query()
             .table_name(&self.table_name)
-            .set_key(Some(keys))
-            .consistent_read(true)
+            .key_condition_expression("#pk = :pk")
+            .expression_attribute_names("#pk", PK)
+            .expression_attribute_values(":pk", AttributeValue::S(pk))
+            .scan_index_forward(false)
+            .limit(limit as i32)
+            .set_exclusive_start_key(start_key)
             .send()
-            .await
-            .unwrap();
-        let mut groups = Vec::new();
-        groups.push(response.item().unwrap().to_owned());
-        groups
+            .await?;
+
+        let items = response.items().unwrap_or_default().to_vec();
+        let next = match (response.last_evaluated_key(), items.last()) {
+            (Some(_), Some(last)) => Some(get_n::<u64>(last, VERSION)?.to_string()),
+            _ => None,
+        };
+        Ok(Page { items, next })
+    }
+
+    async fn fetch_revision(&self, entity_id: &str, version: u64) -> Result<Item> {
+        self.get(revision_key(entity_id), version_key(version))
+            .await?
+            .ok_or_else(|| ApiError::not_found("Revision", &version.to_string()))
     }
 
-    pub(crate) async fn save_note(&self, note: &Note) -> Result<(), Error> {
-        let mut note_sk = String::from("NOTE#");
-        note_sk.push_str(&note.created.to_string());
+    async fn revert(
+        &self,
+        document_id: &str,
+        sk: &str,
+        version: u64,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let old = self
+            .get(document_key(document_id), sk.to_string())
+            .await?
+            .filter(|item| !is_trashed(item))
+            .ok_or_else(|| ApiError::not_found(entity, id))?;
+        ensure_version(&old, expected_version, entity, id)?;
+        let revision = self.fetch_revision(id, version).await?;
 
-        let pk = AttributeValue::S("note".to_string());
-        let sk = AttributeValue::S(note_sk.clone());
-        let title = AttributeValue::S(note.title.clone());
-        let created = AttributeValue::N(note.created.to_string());
-        let last_updated = AttributeValue::N(note.created.to_string());
-        let updated_by = AttributeValue::S(note.updated_by.clone());
-        let description = AttributeValue::S(note.description.clone());
-        let parent = AttributeValue::S(note.parent.clone());
+        let timestamp = Utc::now().timestamp();
+        let (item, changed) = reverted_item(&old, &revision, updated_by, timestamp)?;
+        let put = Put::builder()
+            .table_name(&self.table_name)
+            .set_item(Some(item.clone()))
+            .condition_expression(
+                "attribute_exists(#sk) AND attribute_not_exists(#trashed) AND #version = :version",
+            )
+            .expression_attribute_names("#sk", SK)
+            .expression_attribute_names("#trashed", TRASHED)
+            .expression_attribute_names("#version", VERSION)
+            .expression_attribute_values(
+                ":version",
+                AttributeValue::N(get_n::<u64>(&old, VERSION)?.to_string()),
+            )
+            .build();
+        self.client
+            .transact_write_items()
+            .transact_items(TransactWriteItem::builder().put(put).build())
+            .transact_items(
+                self.put_revision(revision_item(&old, &changed, updated_by, timestamp)?),
+            )
+            .send()
+            .await
+            .map_err(|err| stale_write(err.into(), entity, id, expected_version))?;
+
+        let owner = self.owner_of(document_id).await?;
+        self.update_index(&owner, &[old], &[item]).await?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            updated_by,
+            Action::Reverted,
+            activity::revert_summary(version, &changed),
+            timestamp,
+        )])
+        .await
+    }
 
-        let request = self
+    async fn list_activity(
+        &self,
+        document_id: &str,
+        limit: usize,
+        before: Option<&str>,
+    ) -> Result<Page> {
+        let pk = activity_key(document_id);
+        let start_key = before.map(|event_id| {
+            HashMap::from([
+                (PK.to_string(), AttributeValue::S(pk.clone())),
+                (SK.to_string(), AttributeValue::S(event_key(event_id))),
+            ])
+        });
+        let response = self
             .client
-            .put_item()
+            .query()
             .table_name(&self.table_name)
-            .item(PK, pk)
-            .item(SK, sk)
-            .item(TITLE, title)
-            .item(CREATED, created)
-            .item(LAST_UPDATED, last_updated)
-            .item(UPDATED_BY, updated_by)
-            .item(DESCRIPTION, description)
-            .item(PARENT, parent);
+            .key_condition_expression("#pk = :pk")
+            .expression_attribute_names("#pk", PK)
+            .expression_attribute_values(":pk", AttributeValue::S(pk))
+            .scan_index_forward(false)
+            .limit(limit as i32)
+            .set_exclusive_start_key(start_key)
+            .send()
+            .await?;
 
-        request.send().await;
+        let items = response.items().unwrap_or_default().to_vec();
+        let next = match (response.last_evaluated_key(), items.last()) {
+            (Some(_), Some(last)) => Some(id_of(&get_s(last, SK)?).to_string()),
+            _ => None,
+        };
+        Ok(Page { items, next })
+    }
+}
 
-        Ok(())
+/// A conditional write that was turned down while the caller expected the
+/// item at a given version means someone else changed it first.
+fn stale_write(err: ApiError, entity: &str, id: &str, expected_version: Option<u64>) -> ApiError {
+    match (err, expected_version) {
+        (ApiError::Conflict(_), Some(expected)) => ApiError::stale_version(entity, id, expected),
+        (err, _) => err,
     }
 }
+
+/// The condition on a write to an item that has to exist, and at
+/// `expected_version` when one is given, with the names and values it uses.
+fn version_condition(
+    expected_version: Option<u64>,
+) -> (
+    String,
+    HashMap<String, String>,
+    Option<HashMap<String, AttributeValue>>,
+) {
+    let mut names = HashMap::from([(String::from("#sk"), SK.to_string())]);
+    match expected_version {
+        Some(expected) => {
+            names.insert(String::from("#version"), VERSION.to_string());
+            let values = HashMap::from([(
+                String::from(":expectedVersion"),
+                AttributeValue::N(expected.to_string()),
+            )]);
+            (
+                String::from("attribute_exists(#sk) AND #version = :expectedVersion"),
+                names,
+                Some(values),
+            )
+        }
+        None => (String::from("attribute_exists(#sk)"), names, None),
+    }
+}
+
+/// The key of an item in `GSI1`, as DynamoDB expects it back to continue a
+/// query after that item.
+fn index_key(pk: String, sk: String, gsi1pk: String) -> Item {
+    HashMap::from([
+        (GSI1SK.to_string(), AttributeValue::S(pk.clone())),
+        (PK.to_string(), AttributeValue::S(pk)),
+        (SK.to_string(), AttributeValue::S(sk)),
+        (GSI1PK.to_string(), AttributeValue::S(gsi1pk)),
+    ])
+}
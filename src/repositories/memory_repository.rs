@@ -0,0 +1,990 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use chrono::Utc;
+
+use crate::{
+    controllers::{DocumentReq, DocumentUpdateReq, GroupReq, GroupUpdateReq, NoteUpdateReq},
+    error::{ApiError, Result},
+    services::{
+        ensure_version, get_n, get_s, get_ss, id_of, notes_service::Note, Role, CONTENT,
+        EXPIRES_AT, GSI1PK, GSI1SK, OWNER, PK, POSITION, SK, TAGS, TRASH_ROOT, VERSION,
+    },
+};
+
+use super::{
+    activity::{
+        self, activity_key, changes_summary, created_events, event_item, event_key, moved_events,
+        Action,
+    },
+    content_value, document_items, document_key, edited_fields, ensure_one_transaction, entity_of,
+    group_items, group_key, is_in_group, is_trashed, moved_group, moved_note, next_position,
+    not_in_trash, note_item, note_key, owner_key, restored_items,
+    revisions::{changed_fields, reverted_item, revision_item, revision_key, version_key},
+    search_index, set_fields, share_item, share_key, shared_key, tagged_already, trash_key,
+    trashed_items, with_tags, without_trashed, Item, NotesStore, Page, Placement, TagChange,
+    POSITION_STEP,
+};
+
+type Key = (String, String);
+
+/// A `NotesStore` kept in process memory, for offline runs.
+pub struct MemoryRepository {
+    items: Mutex<BTreeMap<Key, Item>>,
+    trash_retention: Duration,
+}
+
+impl MemoryRepository {
+    pub fn new(trash_retention: Duration) -> Self {
+        Self {
+            items: Mutex::default(),
+            trash_retention,
+        }
+    }
+
+    /// Locks the items, first dropping those whose `expiresAt` has passed
+    /// the way the table's TTL would.
+    fn items(&self) -> Result<MutexGuard<'_, BTreeMap<Key, Item>>> {
+        let mut items = self
+            .items
+            .lock()
+            .map_err(|_| ApiError::Storage(String::from("in-memory store lock was poisoned")))?;
+        let now = Utc::now().timestamp();
+        items.retain(|_, item| get_n::<i64>(item, EXPIRES_AT).map_or(true, |at| at > now));
+        Ok(items)
+    }
+
+    fn get(&self, pk: &str, sk: String) -> Result<Option<Item>> {
+        Ok(self.items()?.get(&(pk.to_string(), sk)).cloned())
+    }
+
+    fn put(&self, item: Item) -> Result<()> {
+        let key = key_of(&item)?;
+        self.items()?.insert(key, item);
+        Ok(())
+    }
+
+    /// Stores the item unless one with the same key already exists, like a
+    /// conditional put on `attribute_not_exists(SK)`.
+    fn insert_new(&self, item: Item) -> Result<Item> {
+        insert_absent(&mut *self.items()?, item.clone())?;
+        Ok(item)
+    }
+
+    /// The owner of the document, which may be in the trash.
+    fn owner_of(&self, document_id: &str) -> Result<String> {
+        let key = document_key(document_id);
+        let document = self
+            .get(&key, key.clone())?
+            .ok_or_else(|| ApiError::not_found("Document", document_id))?;
+        get_s(&document, OWNER)
+    }
+
+    /// Brings the owner's search index from matching the `old` items to
+    /// matching the `new` ones, see `search_index::changes`.
+    fn update_index(&self, owner: &str, old: &[Item], new: &[Item]) -> Result<()> {
+        let (puts, deletes) = search_index::changes(owner, old, new);
+        for entry in puts {
+            self.put(entry)?;
+        }
+        let keys = deletes.iter().map(key_of).collect::<Result<Vec<_>>>()?;
+        self.remove_all(keys)
+    }
+
+    fn remove_all(&self, keys: Vec<Key>) -> Result<()> {
+        let mut items = self.items()?;
+        for key in keys {
+            items.remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Returns the items of a partition whose sort key starts with
+    /// `sk_prefix`, in sort key order like a DynamoDB range query.
+    fn query_partition(&self, pk: &str, sk_prefix: &str) -> Result<Vec<Item>> {
+        let items = self.items()?;
+        let partition = items
+            .range((pk.to_string(), sk_prefix.to_string())..)
+            .take_while(|((item_pk, sk), _)| item_pk == pk && sk.starts_with(sk_prefix))
+            .map(|(_, item)| item.clone())
+            .collect();
+        Ok(partition)
+    }
+
+    /// One page of the items whose `GSI1PK` is `partition`, like a query on
+    /// `GSI1`.
+    fn index_page(&self, partition: &str, limit: usize, after: Option<&str>) -> Result<Page> {
+        let after = after.map(document_key);
+        let mut items = self
+            .items()?
+            .iter()
+            .filter(|(_, item)| get_s(item, GSI1PK).is_ok_and(|key| key == partition))
+            .filter(|((pk, _), _)| after.as_ref().is_none_or(|after| pk > after))
+            .take(limit + 1)
+            .map(|(_, item)| item.clone())
+            .collect::<Vec<_>>();
+
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            let last = items.last().map(|item| get_s(item, PK)).transpose()?;
+            last.map(|pk| id_of(&pk).to_string())
+        } else {
+            None
+        };
+        Ok(Page { items, next })
+    }
+
+    /// The position after the document's last group.
+    fn next_group_position(&self, document_id: &str) -> Result<i64> {
+        let key = document_key(document_id);
+        let groups = self.query_partition(&key, &group_key(""))?;
+        next_position(&groups, &key)
+    }
+
+    /// Replaces the `old` items of a move with the `new` ones and stores the
+    /// revision of the first.
+    fn relocate(
+        &self,
+        old: &[Item],
+        new: &[Item],
+        revision: Item,
+        entity: &str,
+        id: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let changed = old.iter().zip(new).filter(|(old, new)| old != new);
+        let writes = changed
+            .map(|(old, new)| match (key_of(old), key_of(new)) {
+                (Ok(old), Ok(new)) if old == new => 1,
+                _ => 2,
+            })
+            .sum::<usize>();
+        ensure_one_transaction(writes + 1, entity, id, "moved")?;
+        let removed = old.iter().map(key_of).collect::<Result<Vec<_>>>()?;
+        let added = new
+            .iter()
+            .map(|item| Ok((key_of(item)?, item.clone())))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut items = self.items()?;
+        if let Some(moved) = old.first() {
+            ensure_unchanged(&items, moved, expected_version)?;
+        }
+        insert_absent(&mut items, revision)?;
+        for key in removed {
+            items.remove(&key);
+        }
+        items.extend(added);
+        Ok(())
+    }
+
+    fn query_group(&self, document_id: &str, group_id: &str) -> Result<Vec<Item>> {
+        let group_sk = group_key(group_id);
+        let items = self
+            .query_partition(&document_key(document_id), &group_sk)?
+            .into_iter()
+            .filter(|item| get_s(item, SK).is_ok_and(|sk| is_in_group(&sk, &group_sk)))
+            .collect();
+        Ok(items)
+    }
+
+    /// Adds the events to their documents' activity feeds.
+    fn record(&self, events: Vec<Item>) -> Result<()> {
+        for event in events {
+            self.put(event)?;
+        }
+        Ok(())
+    }
+
+    /// The item under `sk` followed by everything below it, trashed or not.
+    fn subtree(&self, document_id: &str, sk: &str) -> Result<Vec<Item>> {
+        let pk = document_key(document_id);
+        if sk == pk {
+            self.query_partition(&pk, "")
+        } else if entity_of(sk) == "Group" {
+            self.query_group(document_id, id_of(sk))
+        } else {
+            Ok(self.get(&pk, sk.to_string())?.into_iter().collect())
+        }
+    }
+
+    /// Stores `new` and the revision of `old` under one lock, only while `old`
+    /// is unchanged.
+    fn write_revised(
+        &self,
+        old: &Item,
+        new: &[Item],
+        revision: Option<Item>,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let keyed = new
+            .iter()
+            .map(|item| Ok((key_of(item)?, item.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        let mut items = self.items()?;
+        ensure_unchanged(&items, old, expected_version)?;
+        if let Some(revision) = revision {
+            insert_absent(&mut items, revision)?;
+        }
+        items.extend(keyed);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl NotesStore for MemoryRepository {
+    async fn list_documents(&self, owner: &str, limit: usize, after: Option<&str>) -> Result<Page> {
+        self.index_page(&owner_key(owner), limit, after)
+    }
+
+    async fn fetch_by_id(&self, id: &str) -> Result<Vec<Item>> {
+        let items = without_trashed(self.query_partition(&document_key(id), "")?);
+        if items.is_empty() {
+            return Err(ApiError::not_found("Document", id));
+        }
+        Ok(items)
+    }
+
+    async fn fetch_document_by_id(&self, id: &str) -> Result<Item> {
+        let key = document_key(id);
+        self.get(&key, key.clone())?
+            .filter(|document| !is_trashed(document))
+            .ok_or_else(|| ApiError::not_found("Document", id))
+    }
+
+    async fn fetch_group_by_id(&self, document_id: &str, group_id: &str) -> Result<Item> {
+        self.get(&document_key(document_id), group_key(group_id))?
+            .filter(|group| !is_trashed(group))
+            .ok_or_else(|| ApiError::not_found("Group", group_id))
+    }
+
+    async fn save(&self, document: &DocumentReq, owner: &str) -> Result<Vec<Item>> {
+        let timestamp = Utc::now().timestamp();
+        let items = document_items(document, owner, timestamp)?;
+        let document_id = id_of(&get_s(&items[0], PK)?).to_string();
+        ensure_one_transaction(items.len(), "Document", &document_id, "created")?;
+
+        // Resolve every key up front so a malformed item leaves the store
+        // untouched, like a cancelled DynamoDB transaction.
+        let keyed = items
+            .iter()
+            .map(|item| Ok((key_of(item)?, item.clone())))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.items()?.extend(keyed);
+        self.update_index(owner, &[], &items)?;
+        self.record(created_events(&document_id, &items, owner, timestamp)?)?;
+        Ok(items)
+    }
+
+    async fn save_note(&self, document_id: &str, note: &Note) -> Result<Item> {
+        let notes = self.query_group(document_id, id_of(&note.parent))?;
+        let position = next_position(&notes, &note.parent)?;
+        let item = self.insert_new(note_item(document_id, note, position))?;
+        let owner = self.owner_of(document_id)?;
+        self.update_index(&owner, &[], std::slice::from_ref(&item))?;
+        let events = created_events(
+            document_id,
+            std::slice::from_ref(&item),
+            &note.created_by,
+            note.created,
+        )?;
+        self.record(events)?;
+        Ok(item)
+    }
+
+    async fn update_document(
+        &self,
+        id: &str,
+        update: &DocumentUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self.fetch_document_by_id(id).await?;
+        ensure_version(&old, expected_version, "Document", id)?;
+        let mut document = old.clone();
+        let timestamp = Utc::now().timestamp();
+        let document_sk = document_key(id);
+
+        let first = self.next_group_position(id)?;
+        let mut groups = Vec::new();
+        for (position, group) in (0..)
+            .map(|n| first + n * POSITION_STEP)
+            .zip(update.groups.iter())
+        {
+            groups.extend(group_items(
+                group,
+                &document_sk,
+                updated_by,
+                timestamp,
+                position,
+            )?);
+        }
+        ensure_one_transaction(groups.len(), "Document", id, "given these groups")?;
+
+        // Adding groups leaves the document's own fields and version alone.
+        let fields = edited_fields(update.title.as_ref(), update.description.as_ref());
+        let changed = changed_fields(&old, &fields);
+        let mut revision = None;
+        if !changed.is_empty() {
+            revision = Some(revision_item(&old, &changed, updated_by, timestamp)?);
+            set_fields(&mut document, fields, timestamp, updated_by)?;
+        }
+
+        let mut new = vec![document];
+        new.extend(groups);
+        self.write_revised(&old, &new, revision, expected_version)?;
+        self.update_index(&get_s(&old, OWNER)?, &[old], &new)?;
+
+        let mut events = Vec::new();
+        if !changed.is_empty() {
+            events.push(event_item(
+                id,
+                &document_sk,
+                updated_by,
+                Action::Updated,
+                changes_summary(&changed),
+                timestamp,
+            ));
+        }
+        events.extend(created_events(id, &new[1..], updated_by, timestamp)?);
+        self.record(events)
+    }
+
+    async fn save_group(
+        &self,
+        document_id: &str,
+        group: &GroupReq,
+        created_by: &str,
+    ) -> Result<Vec<Item>> {
+        let position = self.next_group_position(document_id)?;
+        let timestamp = Utc::now().timestamp();
+        let items = group_items(
+            group,
+            &document_key(document_id),
+            created_by,
+            timestamp,
+            position,
+        )?;
+        let group_id = id_of(&get_s(&items[0], SK)?).to_string();
+        ensure_one_transaction(items.len(), "Group", &group_id, "created")?;
+        for item in items.iter() {
+            self.insert_new(item.clone())?;
+        }
+        let owner = self.owner_of(document_id)?;
+        self.update_index(&owner, &[], &items)?;
+        self.record(created_events(document_id, &items, created_by, timestamp)?)?;
+        Ok(items)
+    }
+
+    async fn fetch_group_with_notes(&self, document_id: &str, group_id: &str) -> Result<Vec<Item>> {
+        let items = without_trashed(self.query_group(document_id, group_id)?);
+        if items.is_empty() {
+            return Err(ApiError::not_found("Group", group_id));
+        }
+        Ok(items)
+    }
+
+    async fn update_group(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        update: &GroupUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self.fetch_group_by_id(document_id, group_id).await?;
+        ensure_version(&old, expected_version, "Group", group_id)?;
+        let mut group = old.clone();
+        let timestamp = Utc::now().timestamp();
+
+        let fields = edited_fields(update.title.as_ref(), update.description.as_ref());
+        let changed = changed_fields(&old, &fields);
+        let revision = revision_item(&old, &changed, updated_by, timestamp)?;
+        set_fields(&mut group, fields, timestamp, updated_by)?;
+        self.write_revised(
+            &old,
+            std::slice::from_ref(&group),
+            Some(revision),
+            expected_version,
+        )?;
+        let owner = self.owner_of(document_id)?;
+        self.update_index(&owner, &[old], &[group])?;
+        self.record(vec![event_item(
+            document_id,
+            &group_key(group_id),
+            updated_by,
+            Action::Updated,
+            changes_summary(&changed),
+            timestamp,
+        )])
+    }
+
+    async fn fetch_note_by_id(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+    ) -> Result<Item> {
+        self.get(&document_key(document_id), note_key(group_id, note_id))?
+            .filter(|note| !is_trashed(note))
+            .ok_or_else(|| ApiError::not_found("Note", note_id))
+    }
+
+    async fn update_note(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+        update: &NoteUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self
+            .fetch_note_by_id(document_id, group_id, note_id)
+            .await?;
+        ensure_version(&old, expected_version, "Note", note_id)?;
+        let mut note = old.clone();
+        let timestamp = Utc::now().timestamp();
+
+        let mut fields = edited_fields(update.title.as_ref(), update.description.as_ref());
+        if let Some(content) = &update.content {
+            fields.push((CONTENT, content_value(content)));
+        }
+        let changed = changed_fields(&old, &fields);
+        let revision = revision_item(&old, &changed, updated_by, timestamp)?;
+        set_fields(&mut note, fields, timestamp, updated_by)?;
+        self.write_revised(
+            &old,
+            std::slice::from_ref(&note),
+            Some(revision),
+            expected_version,
+        )?;
+        let owner = self.owner_of(document_id)?;
+        self.update_index(&owner, &[old], &[note])?;
+        self.record(vec![event_item(
+            document_id,
+            &note_key(group_id, note_id),
+            updated_by,
+            Action::Updated,
+            changes_summary(&changed),
+            timestamp,
+        )])
+    }
+
+    async fn list_shared_documents(
+        &self,
+        user: &str,
+        limit: usize,
+        after: Option<&str>,
+    ) -> Result<Page> {
+        let page = self.index_page(&shared_key(user), limit, after)?;
+
+        let mut items = Vec::with_capacity(page.items.len());
+        for share in page.items.iter() {
+            let key = get_s(share, PK)?;
+            if let Some(document) = self.get(&key, key.clone())? {
+                if !is_trashed(&document) {
+                    items.push(document);
+                }
+            }
+        }
+        Ok(Page {
+            items,
+            next: page.next,
+        })
+    }
+
+    async fn fetch_share(&self, document_id: &str, user: &str) -> Result<Option<Item>> {
+        let share = self.get(&document_key(document_id), share_key(user))?;
+        Ok(share.filter(|share| !is_trashed(share)))
+    }
+
+    async fn fetch_shares(&self, document_id: &str) -> Result<Vec<Item>> {
+        let shares = self.query_partition(&document_key(document_id), &share_key(""))?;
+        Ok(without_trashed(shares))
+    }
+
+    async fn save_share(
+        &self,
+        document_id: &str,
+        user: &str,
+        role: Role,
+        granted_by: &str,
+    ) -> Result<Item> {
+        let timestamp = Utc::now().timestamp();
+        let item = share_item(document_id, user, role, granted_by, timestamp);
+        self.put(item.clone())?;
+        self.record(vec![event_item(
+            document_id,
+            &share_key(user),
+            granted_by,
+            Action::Shared,
+            activity::share_summary(user, role),
+            timestamp,
+        )])?;
+        Ok(item)
+    }
+
+    async fn delete_share(&self, document_id: &str, user: &str, revoked_by: &str) -> Result<()> {
+        let share = self
+            .fetch_share(document_id, user)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Share", user))?;
+        self.remove_all(vec![key_of(&share)?])?;
+        self.record(vec![event_item(
+            document_id,
+            &share_key(user),
+            revoked_by,
+            Action::Unshared,
+            activity::unshare_summary(user),
+            Utc::now().timestamp(),
+        )])
+    }
+
+    async fn search(&self, owner: &str, token: &str) -> Result<Vec<Item>> {
+        self.query_partition(&search_index::search_key(owner, token), "")
+    }
+
+    async fn update_tags(
+        &self,
+        document_id: &str,
+        sk: &str,
+        change: TagChange<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<Item> {
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let old = self
+            .get(&document_key(document_id), sk.to_string())?
+            .filter(|item| !is_trashed(item))
+            .ok_or_else(|| ApiError::not_found(entity, id))?;
+        ensure_version(&old, expected_version, entity, id)?;
+        if tagged_already(&old, &change) {
+            return Ok(old);
+        }
+
+        let mut tags = get_ss(&old, TAGS);
+        match change {
+            TagChange::Add(tag) => tags.push(tag.to_string()),
+            TagChange::Remove(tag) => tags.retain(|t| t != tag),
+        }
+        let timestamp = Utc::now().timestamp();
+        let revision = revision_item(&old, &[TAGS], updated_by, timestamp)?;
+        let mut item = with_tags(old.clone(), &tags);
+        set_fields(&mut item, Vec::new(), timestamp, updated_by)?;
+        self.write_revised(
+            &old,
+            std::slice::from_ref(&item),
+            Some(revision),
+            expected_version,
+        )?;
+        let owner = self.owner_of(document_id)?;
+        self.update_index(&owner, &[old], std::slice::from_ref(&item))?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            updated_by,
+            Action::Tagged,
+            activity::tag_summary(&change),
+            timestamp,
+        )])?;
+        Ok(item)
+    }
+
+    async fn tagged(
+        &self,
+        owner: &str,
+        tag: &str,
+        limit: usize,
+        after: Option<&str>,
+    ) -> Result<Page> {
+        let mut items = self
+            .query_partition(&search_index::tag_key(owner, tag), "")?
+            .into_iter()
+            .filter(|item| {
+                after.is_none_or(|after| get_s(item, SK).is_ok_and(|sk| sk.as_str() > after))
+            })
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|item| get_s(item, SK)).transpose()?
+        } else {
+            None
+        };
+        Ok(Page { items, next })
+    }
+
+    async fn set_position(&self, document_id: &str, sk: &str, position: i64) -> Result<()> {
+        let mut items = self.items()?;
+        let item = items
+            .get_mut(&(document_key(document_id), sk.to_string()))
+            .ok_or_else(|| ApiError::not_found(entity_of(sk), id_of(sk)))?;
+        item.insert(
+            POSITION.to_string(),
+            AttributeValue::N(position.to_string()),
+        );
+        Ok(())
+    }
+
+    async fn move_group(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        to: &Placement<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        self.fetch_group_by_id(document_id, group_id).await?;
+        let old = self.query_group(document_id, group_id)?;
+        let owner = self.owner_of(to.document_id)?;
+        let timestamp = Utc::now().timestamp();
+        let new = moved_group(&old, to, &owner, updated_by, timestamp)?;
+        let revision = revision_item(&old[0], &[], updated_by, timestamp)?;
+        self.relocate(&old, &new, revision, "Group", group_id, expected_version)?;
+
+        let from = self.owner_of(document_id)?;
+        self.update_index(&from, &old, &[])?;
+        self.update_index(&owner, &[], &new)?;
+        self.record(moved_events(&old[0], &new[0], updated_by, timestamp)?)
+    }
+
+    async fn move_note(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+        to: &Placement<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let old = self
+            .fetch_note_by_id(document_id, group_id, note_id)
+            .await?;
+        let timestamp = Utc::now().timestamp();
+        let new = moved_note(&old, to, updated_by, timestamp)?;
+        self.relocate(
+            std::slice::from_ref(&old),
+            std::slice::from_ref(&new),
+            revision_item(&old, &[], updated_by, timestamp)?,
+            "Note",
+            note_id,
+            expected_version,
+        )?;
+
+        let events = moved_events(&old, &new, updated_by, timestamp)?;
+        let from = self.owner_of(document_id)?;
+        self.update_index(&from, &[old], &[])?;
+        let owner = self.owner_of(to.document_id)?;
+        self.update_index(&owner, &[], &[new])?;
+        self.record(events)
+    }
+
+    async fn trash(
+        &self,
+        document_id: &str,
+        sk: &str,
+        trashed_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let items = without_trashed(self.subtree(document_id, sk)?);
+        if !items
+            .first()
+            .is_some_and(|item| get_s(item, SK).is_ok_and(|item_sk| item_sk == sk))
+        {
+            return Err(ApiError::not_found(entity, id));
+        }
+
+        let owner = self.owner_of(document_id)?;
+        let timestamp = Utc::now().timestamp();
+        let expires_at = timestamp + self.trash_retention.as_secs() as i64;
+        let trashed = trashed_items(&items, &owner, trashed_by, timestamp, expires_at)?;
+        let revision = revision_item(&items[0], &[], trashed_by, timestamp)?;
+        ensure_one_transaction(trashed.len() + 1, entity, id, "moved to the trash")?;
+        self.write_revised(&items[0], &trashed, Some(revision), expected_version)?;
+        self.update_index(&owner, &items, &[])?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            trashed_by,
+            Action::Trashed,
+            activity::trash_summary(Action::Trashed, items.len()),
+            timestamp,
+        )])
+    }
+
+    async fn fetch_trashed(&self, document_id: &str, sk: &str) -> Result<Item> {
+        self.get(&document_key(document_id), sk.to_string())?
+            .filter(|item| get_s(item, TRASH_ROOT).is_ok_and(|root| root == sk))
+            .ok_or_else(|| not_in_trash(sk))
+    }
+
+    async fn restore(&self, document_id: &str, sk: &str, restored_by: &str) -> Result<()> {
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let items = self
+            .subtree(document_id, sk)?
+            .into_iter()
+            .filter(|item| get_s(item, TRASH_ROOT).is_ok_and(|root| root == sk))
+            .collect::<Vec<_>>();
+        if items.is_empty() {
+            return Err(not_in_trash(sk));
+        }
+
+        let owner = self.owner_of(document_id)?;
+        let timestamp = Utc::now().timestamp();
+        let restored = restored_items(&items, &owner, restored_by, timestamp)?;
+        let revision = revision_item(&items[0], &[], restored_by, timestamp)?;
+        ensure_one_transaction(restored.len() + 1, entity, id, "restored")?;
+        self.write_revised(&items[0], &restored, Some(revision), None)?;
+        self.update_index(&owner, &[], &restored)?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            restored_by,
+            Action::Restored,
+            activity::trash_summary(Action::Restored, restored.len()),
+            timestamp,
+        )])
+    }
+
+    async fn list_trash(&self, owner: &str) -> Result<Vec<Item>> {
+        let partition = trash_key(owner);
+        let mut items = self
+            .items()?
+            .values()
+            .filter(|item| get_s(item, GSI1PK).is_ok_and(|key| key == partition))
+            .cloned()
+            .collect::<Vec<_>>();
+        items.sort_by_key(|item| get_s(item, GSI1SK).unwrap_or_default());
+        Ok(items)
+    }
+
+    async fn list_revisions(
+        &self,
+        entity_id: &str,
+        limit: usize,
+        before: Option<u64>,
+    ) -> Result<Page> {
+        let before = before.map(version_key);
+        let mut items = self
+            .query_partition(&revision_key(entity_id), "")?
+            .into_iter()
+            .rev()
+            .filter(|item| {
+                before
+                    .as_ref()
+                    .is_none_or(|before| get_s(item, SK).is_ok_and(|sk| &sk < before))
+            })
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            let last = items.last().map(|item| get_n::<u64>(item, VERSION));
+            last.transpose()?.map(|version| version.to_string())
+        } else {
+            None
+        };
+        Ok(Page { items, next })
+    }
+
+    async fn fetch_revision(&self, entity_id: &str, version: u64) -> Result<Item> {
+        self.get(&revision_key(entity_id), version_key(version))?
+            .ok_or_else(|| ApiError::not_found("Revision", &version.to_string()))
+    }
+
+    async fn revert(
+        &self,
+        document_id: &str,
+        sk: &str,
+        version: u64,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()> {
+        let (entity, id) = (entity_of(sk), id_of(sk));
+        let old = self
+            .get(&document_key(document_id), sk.to_string())?
+            .filter(|item| !is_trashed(item))
+            .ok_or_else(|| ApiError::not_found(entity, id))?;
+        ensure_version(&old, expected_version, entity, id)?;
+        let revision = self.fetch_revision(id, version).await?;
+
+        let timestamp = Utc::now().timestamp();
+        let (item, changed) = reverted_item(&old, &revision, updated_by, timestamp)?;
+        let revision = revision_item(&old, &changed, updated_by, timestamp)?;
+        self.write_revised(
+            &old,
+            std::slice::from_ref(&item),
+            Some(revision),
+            expected_version,
+        )?;
+        let owner = self.owner_of(document_id)?;
+        self.update_index(&owner, &[old], &[item])?;
+        self.record(vec![event_item(
+            document_id,
+            sk,
+            updated_by,
+            Action::Reverted,
+            activity::revert_summary(version, &changed),
+            timestamp,
+        )])
+    }
+
+    async fn list_activity(
+        &self,
+        document_id: &str,
+        limit: usize,
+        before: Option<&str>,
+    ) -> Result<Page> {
+        let before = before.map(event_key);
+        let mut items = self
+            .query_partition(&activity_key(document_id), "")?
+            .into_iter()
+            .rev()
+            .filter(|item| {
+                before
+                    .as_ref()
+                    .is_none_or(|before| get_s(item, SK).is_ok_and(|sk| &sk < before))
+            })
+            .take(limit + 1)
+            .collect::<Vec<_>>();
+
+        let next = if items.len() > limit {
+            items.truncate(limit);
+            let last = items.last().map(|item| get_s(item, SK));
+            last.transpose()?.map(|sk| id_of(&sk).to_string())
+        } else {
+            None
+        };
+        Ok(Page { items, next })
+    }
+}
+
+/// Fails unless `old` is still stored at the version it was read at.
+fn ensure_unchanged(
+    items: &BTreeMap<Key, Item>,
+    old: &Item,
+    expected_version: Option<u64>,
+) -> Result<()> {
+    let sk = get_s(old, SK)?;
+    let (entity, id) = (entity_of(&sk), id_of(&sk));
+    let stored = items
+        .get(&key_of(old)?)
+        .ok_or_else(|| ApiError::not_found(entity, id))?;
+    ensure_version(stored, expected_version, entity, id)?;
+    if get_n::<u64>(stored, VERSION)? != get_n::<u64>(old, VERSION)? {
+        return Err(ApiError::Conflict(format!(
+            "{} '{}' was changed by another request",
+            entity, id
+        )));
+    }
+    Ok(())
+}
+
+/// `insert_new` for callers already holding the lock.
+fn insert_absent(items: &mut BTreeMap<Key, Item>, item: Item) -> Result<()> {
+    let key = key_of(&item)?;
+    if items.contains_key(&key) {
+        return Err(ApiError::Conflict(format!(
+            "Item '{}' already exists",
+            key.1
+        )));
+    }
+    items.insert(key, item);
+    Ok(())
+}
+
+fn key_of(item: &Item) -> Result<Key> {
+    match (item.get(PK), item.get(SK)) {
+        (Some(AttributeValue::S(pk)), Some(AttributeValue::S(sk))) => Ok((pk.clone(), sk.clone())),
+        _ => Err(ApiError::Storage(String::from(
+            "item is missing its PK or SK attribute",
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nanoserde::DeJson;
+
+    use super::*;
+    use crate::{controllers::NoteReq, services::TITLE};
+
+    fn document(groups: usize, notes: usize) -> DocumentReq {
+        let group = format!(
+            r#"{{"title": "Group", "notes": [{}]}}"#,
+            vec![r#"{"title": "Note"}"#; notes].join(",")
+        );
+        let document = format!(
+            r#"{{"title": "Groceries", "groups": [{}]}}"#,
+            vec![group; groups].join(",")
+        );
+        DocumentReq::deserialize_json(&document).expect("the document parses")
+    }
+
+    #[tokio::test]
+    async fn deletes_a_document_with_its_groups_and_notes() {
+        let store = MemoryRepository::new(Duration::ZERO);
+        let items = store.save(&document(3, 2), "alice").await.unwrap();
+        let key = get_s(&items[0], PK).unwrap();
+        assert_eq!(store.query_partition(&key, "").unwrap().len(), 10);
+
+        store.trash(id_of(&key), &key, "alice", None).await.unwrap();
+
+        assert!(store.query_partition(&key, "").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuses_a_write_over_a_version_it_did_not_read() {
+        let store = MemoryRepository::new(Duration::ZERO);
+        let items = store.save(&document(0, 0), "alice").await.unwrap();
+        let key = get_s(&items[0], PK).unwrap();
+        let update = DocumentUpdateReq::deserialize_json(r#"{"title": "Shopping"}"#).unwrap();
+        store
+            .update_document(id_of(&key), &update, "bob", None)
+            .await
+            .unwrap();
+
+        let error = store
+            .write_revised(&items[0], &items[..1], None, None)
+            .unwrap_err();
+        assert!(matches!(error, ApiError::Conflict(_)));
+        let error = store
+            .write_revised(&items[0], &items[..1], None, Some(1))
+            .unwrap_err();
+        assert!(matches!(error, ApiError::PreconditionFailed(_)));
+        let stored = store.fetch_document_by_id(id_of(&key)).await.unwrap();
+        assert_eq!(get_s(&stored, TITLE).unwrap(), "Shopping");
+    }
+
+    #[tokio::test]
+    async fn refuses_a_cascade_larger_than_one_transaction() {
+        let store = MemoryRepository::new(Duration::ZERO);
+        let error = store.save(&document(2, 49), "alice").await.unwrap_err();
+        assert!(matches!(error, ApiError::Validation(_)));
+
+        let items = store.save(&document(2, 48), "alice").await.unwrap();
+        let key = get_s(&items[0], PK).unwrap();
+        let group = get_s(&items[1], SK).unwrap();
+        let note = NoteReq::deserialize_json(r#"{"title": "Note"}"#).unwrap();
+        for _ in 0..2 {
+            let note = Note::new(&note, group.clone(), "alice", Utc::now().timestamp());
+            store.save_note(id_of(&key), &note).await.unwrap();
+        }
+
+        let error = store
+            .trash(id_of(&key), &key, "alice", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ApiError::Validation(_)));
+        assert_eq!(store.query_partition(&key, "").unwrap().len(), 101);
+    }
+}
@@ -1 +1,777 @@
+use std::collections::{BTreeSet, HashMap};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::model::AttributeValue;
+use ulid::Ulid;
+
+use crate::{
+    controllers::{
+        validation::normalize_tag, ChecklistItem, DocumentReq, DocumentUpdateReq, GroupReq,
+        GroupUpdateReq, NoteContent, NoteUpdateReq,
+    },
+    error::{ApiError, Result},
+    services::{
+        get_n, get_s, get_ss, id_of, notes_service::Note, Role, CONTENT, CREATED, CREATED_BY,
+        DESCRIPTION, EXPIRES_AT, GSI1PK, GSI1SK, LAST_UPDATED, OWNER, PARENT, PK, POSITION, ROLE,
+        SK, TAGS, TITLE, TRASHED, TRASHED_BY, TRASH_ROOT, UPDATED_BY, USER, VERSION,
+    },
+};
+
+pub mod activity;
 pub mod document_repository;
+pub mod memory_repository;
+pub mod revisions;
+pub mod search_index;
+
+pub type Item = HashMap<String, AttributeValue>;
+
+/// One page of document items, see `NotesStore::list_documents`, or of
+/// revisions or activity events.
+pub struct Page {
+    pub items: Vec<Item>,
+    /// Where the next page starts, set when more may follow.
+    pub next: Option<String>,
+}
+
+/// An attribute set by an update, by name.
+pub(crate) type Field = (&'static str, AttributeValue);
+
+/// DynamoDB rejects transactions that touch more items than this.
+pub(crate) const MAX_TRANSACTION_ITEMS: usize = 100;
+
+/// The gap between the positions of new groups and notes, so one can be
+/// moved between two others.
+pub const POSITION_STEP: i64 = 1 << 16;
+
+/// Where a group or note is moved to: the document, for a note the group
+/// within it, and the `position` it takes there.
+pub struct Placement<'a> {
+    pub document_id: &'a str,
+    pub group_id: Option<&'a str>,
+    pub position: i64,
+}
+
+/// One tag to add to or remove from a document, group or note.
+#[derive(Clone, Copy)]
+pub enum TagChange<'a> {
+    Add(&'a str),
+    Remove(&'a str),
+}
+
+/// The storage operations the services depend on. Items are handed around
+/// in their DynamoDB attribute form, one partition per document.
+#[async_trait]
+pub trait NotesStore: Send + Sync {
+    /// Returns up to `limit` of the owner's document items, without their
+    /// groups and notes, in id order starting after the document `after`.
+    async fn list_documents(&self, owner: &str, limit: usize, after: Option<&str>) -> Result<Page>;
+
+    /// Returns every item in the document's partition.
+    async fn fetch_by_id(&self, id: &str) -> Result<Vec<Item>>;
+
+    async fn fetch_document_by_id(&self, id: &str) -> Result<Item>;
+
+    async fn fetch_group_by_id(&self, document_id: &str, group_id: &str) -> Result<Item>;
+
+    /// Stores a new document with its groups and returns the stored items.
+    async fn save(&self, document: &DocumentReq, owner: &str) -> Result<Vec<Item>>;
+
+    /// Stores a new note and returns the stored item.
+    async fn save_note(&self, document_id: &str, note: &Note) -> Result<Item>;
+
+    /// Applies the update and bumps the version, only while it is still
+    /// `expected_version` when one is given.
+    async fn update_document(
+        &self,
+        id: &str,
+        update: &DocumentUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()>;
+
+    /// Adds a group with the notes given with it to an existing document and
+    /// returns the stored group item followed by its notes.
+    async fn save_group(
+        &self,
+        document_id: &str,
+        group: &GroupReq,
+        created_by: &str,
+    ) -> Result<Vec<Item>>;
+
+    /// Returns the group item followed by its notes.
+    async fn fetch_group_with_notes(&self, document_id: &str, group_id: &str) -> Result<Vec<Item>>;
+
+    /// Like `update_document`, for a group.
+    async fn update_group(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        update: &GroupUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()>;
+
+    async fn fetch_note_by_id(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+    ) -> Result<Item>;
+
+    /// Like `update_document`, for a note.
+    async fn update_note(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+        update: &NoteUpdateReq,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()>;
+
+    /// Like `list_documents`, for the documents shared with `user`.
+    async fn list_shared_documents(
+        &self,
+        user: &str,
+        limit: usize,
+        after: Option<&str>,
+    ) -> Result<Page>;
+
+    async fn fetch_share(&self, document_id: &str, user: &str) -> Result<Option<Item>>;
+
+    /// Returns the document's access list, the owner aside.
+    async fn fetch_shares(&self, document_id: &str) -> Result<Vec<Item>>;
+
+    /// Gives the user a role on the document, replacing any role they had.
+    async fn save_share(
+        &self,
+        document_id: &str,
+        user: &str,
+        role: Role,
+        granted_by: &str,
+    ) -> Result<Item>;
+
+    async fn delete_share(&self, document_id: &str, user: &str, revoked_by: &str) -> Result<()>;
+
+    /// Returns the search index entries for `token` in the owner's documents,
+    /// in document order.
+    async fn search(&self, owner: &str, token: &str) -> Result<Vec<Item>>;
+
+    /// Adds or removes a tag of the item under `sk` and returns it.
+    async fn update_tags(
+        &self,
+        document_id: &str,
+        sk: &str,
+        change: TagChange<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<Item>;
+
+    /// One page of the tag index entries for `tag` in the owner's documents.
+    async fn tagged(
+        &self,
+        owner: &str,
+        tag: &str,
+        limit: usize,
+        after: Option<&str>,
+    ) -> Result<Page>;
+
+    /// Sets the position of the item under `sk` without bumping its version.
+    async fn set_position(&self, document_id: &str, sk: &str, position: i64) -> Result<()>;
+
+    /// Moves the group to `to`, taking its notes along when that is another
+    /// document. Versions are bumped and checked like in `update_document`.
+    async fn move_group(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        to: &Placement<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()>;
+
+    /// Moves the note to `to`, possibly a group of another document.
+    async fn move_note(
+        &self,
+        document_id: &str,
+        group_id: &str,
+        note_id: &str,
+        to: &Placement<'_>,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()>;
+
+    /// Moves the item under `sk` to the trash with everything below it.
+    async fn trash(
+        &self,
+        document_id: &str,
+        sk: &str,
+        trashed_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()>;
+
+    /// Returns the item under `sk` if it was moved to the trash by deleting
+    /// it, rather than along with its document or group.
+    async fn fetch_trashed(&self, document_id: &str, sk: &str) -> Result<Item>;
+
+    /// Takes the item under `sk` back out of the trash, with everything that
+    /// went there along with it, and bumps its version.
+    async fn restore(&self, document_id: &str, sk: &str, restored_by: &str) -> Result<()>;
+
+    /// Returns the deleted items in the owner's documents, in document order.
+    async fn list_trash(&self, owner: &str) -> Result<Vec<Item>>;
+
+    /// Returns up to `limit` revisions of the document, group or note with
+    /// the id, newest first and starting below version `before`.
+    async fn list_revisions(
+        &self,
+        entity_id: &str,
+        limit: usize,
+        before: Option<u64>,
+    ) -> Result<Page>;
+
+    async fn fetch_revision(&self, entity_id: &str, version: u64) -> Result<Item>;
+
+    /// Returns up to `limit` events of the document's activity feed, newest
+    /// first and starting below the event with the id `before`.
+    async fn list_activity(
+        &self,
+        document_id: &str,
+        limit: usize,
+        before: Option<&str>,
+    ) -> Result<Page>;
+
+    /// Puts the fields of the item under `sk` back the way they were at
+    /// `version`, as a new version.
+    async fn revert(
+        &self,
+        document_id: &str,
+        sk: &str,
+        version: u64,
+        updated_by: &str,
+        expected_version: Option<u64>,
+    ) -> Result<()>;
+}
+
+/// Value of `GSI1PK` on document items.
+pub(crate) fn owner_key(owner: &str) -> String {
+    format!("OWNER#{}", owner)
+}
+
+/// Value of `GSI1PK` on the access list entries of the user they are for.
+pub(crate) fn shared_key(user: &str) -> String {
+    format!("SHARED#{}", user)
+}
+
+/// Value of `GSI1PK` on the items deleted from the owner's documents.
+pub(crate) fn trash_key(owner: &str) -> String {
+    format!("TRASH#{}", owner)
+}
+
+pub(crate) fn document_key(document_id: &str) -> String {
+    format!("DOCUMENT#{}", document_id)
+}
+
+pub(crate) fn group_key(group_id: &str) -> String {
+    format!("GROUP#{}", group_id)
+}
+
+pub(crate) fn note_key(group_id: &str, note_id: &str) -> String {
+    format!("{}#NOTE#{}", group_key(group_id), note_id)
+}
+
+pub(crate) fn share_key(user: &str) -> String {
+    format!("SHARE#{}", user)
+}
+
+fn is_share(item: &Item) -> bool {
+    get_s(item, SK).is_ok_and(|sk| sk.starts_with(&share_key("")))
+}
+
+/// Refuses a write of more items than one transaction takes.
+pub(crate) fn ensure_one_transaction(
+    items: usize,
+    entity: &str,
+    id: &str,
+    action: &str,
+) -> Result<()> {
+    if items > MAX_TRANSACTION_ITEMS {
+        return Err(ApiError::Validation(format!(
+            "{} '{}' cannot be {}, that takes {} writes and at most {} go through at once",
+            entity, id, action, items, MAX_TRANSACTION_ITEMS
+        )));
+    }
+    Ok(())
+}
+
+/// Names the kind of item a document partition sort key belongs to, for
+/// error messages.
+pub(crate) fn entity_of(sk: &str) -> &'static str {
+    if sk.contains("#NOTE#") {
+        "Note"
+    } else if sk.starts_with("GROUP#") {
+        "Group"
+    } else {
+        "Document"
+    }
+}
+
+pub(crate) fn not_in_trash(sk: &str) -> ApiError {
+    ApiError::NotFound(format!(
+        "{} '{}' is not in the trash",
+        entity_of(sk),
+        id_of(sk)
+    ))
+}
+
+pub(crate) fn is_trashed(item: &Item) -> bool {
+    item.contains_key(TRASHED)
+}
+
+/// Leaves out the items in the trash.
+pub(crate) fn without_trashed(items: Vec<Item>) -> Vec<Item> {
+    items.into_iter().filter(|item| !is_trashed(item)).collect()
+}
+
+/// Where an item sits, `DOCUMENT#<id>` followed by its sort key unless it is
+/// the document itself; the trash lists items in this order.
+fn path_of(item: &Item) -> Result<String> {
+    let (pk, sk) = (get_s(item, PK)?, get_s(item, SK)?);
+    Ok(if pk == sk {
+        pk
+    } else {
+        format!("{}#{}", pk, sk)
+    })
+}
+
+/// The items, the deleted one first, as they are stored in the trash. The
+/// shares of a deleted document leave `GSI1`.
+pub(crate) fn trashed_items(
+    items: &[Item],
+    owner: &str,
+    trashed_by: &str,
+    timestamp: i64,
+    expires_at: i64,
+) -> Result<Vec<Item>> {
+    let Some(root) = items.first() else {
+        return Ok(Vec::new());
+    };
+    let root_sk = get_s(root, SK)?;
+    let mut trashed = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        let mut item = item.clone();
+        if index == 0 {
+            item.insert(GSI1PK.to_string(), AttributeValue::S(trash_key(owner)));
+            item.insert(GSI1SK.to_string(), AttributeValue::S(path_of(&item)?));
+            set_fields(&mut item, Vec::new(), timestamp, trashed_by)?;
+        } else if is_share(&item) {
+            item.remove(GSI1PK);
+            item.remove(GSI1SK);
+        }
+        item.insert(
+            TRASHED.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        );
+        item.insert(
+            TRASHED_BY.to_string(),
+            AttributeValue::S(trashed_by.to_string()),
+        );
+        item.insert(TRASH_ROOT.to_string(), AttributeValue::S(root_sk.clone()));
+        item.insert(
+            EXPIRES_AT.to_string(),
+            AttributeValue::N(expires_at.to_string()),
+        );
+        trashed.push(item);
+    }
+    Ok(trashed)
+}
+
+/// The reverse of `trashed_items`, for the items that went to the trash
+/// together, the deleted one first. Restoring it counts as a change to it.
+pub(crate) fn restored_items(
+    items: &[Item],
+    owner: &str,
+    restored_by: &str,
+    timestamp: i64,
+) -> Result<Vec<Item>> {
+    let mut restored = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        let mut item = item.clone();
+        for name in [TRASHED, TRASHED_BY, TRASH_ROOT, EXPIRES_AT] {
+            item.remove(name);
+        }
+        if index == 0 {
+            if get_s(&item, PK)? == get_s(&item, SK)? {
+                item.insert(GSI1PK.to_string(), AttributeValue::S(owner_key(owner)));
+            } else {
+                item.remove(GSI1PK);
+                item.remove(GSI1SK);
+            }
+            set_fields(&mut item, Vec::new(), timestamp, restored_by)?;
+        } else if is_share(&item) {
+            let user = get_s(&item, USER)?;
+            item.insert(GSI1PK.to_string(), AttributeValue::S(shared_key(&user)));
+            item.insert(GSI1SK.to_string(), AttributeValue::S(get_s(&item, PK)?));
+        }
+        restored.push(item);
+    }
+    Ok(restored)
+}
+
+/// Whether the sort key is the group itself or one of the group's notes.
+/// Compares whole key segments so `GROUP#1` does not claim `GROUP#12`.
+pub(crate) fn is_in_group(sk: &str, group_sk: &str) -> bool {
+    sk == group_sk
+        || sk
+            .strip_prefix(group_sk)
+            .is_some_and(|rest| rest.starts_with('#'))
+}
+
+/// Returns a fresh entity id. ULIDs sort by creation time, so items keyed on
+/// them keep the order they were written in.
+pub(crate) fn new_id() -> String {
+    Ulid::new().to_string()
+}
+
+fn document_item(document: &DocumentReq, owner: &str, timestamp: i64) -> Item {
+    let key = document_key(&new_id());
+
+    let item = HashMap::from([
+        (PK.to_string(), AttributeValue::S(key.clone())),
+        (SK.to_string(), AttributeValue::S(key.clone())),
+        (GSI1PK.to_string(), AttributeValue::S(owner_key(owner))),
+        (GSI1SK.to_string(), AttributeValue::S(key)),
+        (TITLE.to_string(), AttributeValue::S(document.title.clone())),
+        (
+            CREATED.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        ),
+        (
+            LAST_UPDATED.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        ),
+        (VERSION.to_string(), AttributeValue::N(String::from("1"))),
+        (OWNER.to_string(), AttributeValue::S(owner.to_string())),
+        (CREATED_BY.to_string(), AttributeValue::S(owner.to_string())),
+        (UPDATED_BY.to_string(), AttributeValue::S(owner.to_string())),
+        (
+            DESCRIPTION.to_string(),
+            AttributeValue::S(document.description.clone()),
+        ),
+    ]);
+    with_tags(item, &document.tags)
+}
+
+/// Builds the document item followed by one item per group, every group
+/// pointing back at the document's freshly generated key.
+pub(crate) fn document_items(
+    document: &DocumentReq,
+    owner: &str,
+    timestamp: i64,
+) -> Result<Vec<Item>> {
+    let document_item = document_item(document, owner, timestamp);
+    let parent = get_s(&document_item, SK)?;
+
+    let mut items = vec![document_item];
+    for (position, group) in (1..).map(|n| n * POSITION_STEP).zip(document.groups.iter()) {
+        items.extend(group_items(group, &parent, owner, timestamp, position)?);
+    }
+    Ok(items)
+}
+
+/// A new group under the document keyed `parent`, followed by the notes
+/// given with it in the order they were given.
+pub(crate) fn group_items(
+    group: &GroupReq,
+    parent: &str,
+    created_by: &str,
+    timestamp: i64,
+    position: i64,
+) -> Result<Vec<Item>> {
+    let group_item = group_item(group, parent, created_by, timestamp, position);
+    let group_sk = get_s(&group_item, SK)?;
+    let document_id = id_of(parent);
+
+    let mut items = vec![group_item];
+    for (position, note) in (1..).map(|n| n * POSITION_STEP).zip(group.notes.iter()) {
+        let note = Note::new(note, group_sk.clone(), created_by, timestamp);
+        items.push(note_item(document_id, &note, position));
+    }
+    Ok(items)
+}
+
+/// The position after the last of the items hanging off `parent`, for an
+/// item added at the end.
+pub(crate) fn next_position(items: &[Item], parent: &str) -> Result<i64> {
+    let mut last = 0;
+    for item in items {
+        if get_s(item, PARENT).is_ok_and(|p| p == parent) {
+            last = last.max(get_n(item, POSITION)?);
+        }
+    }
+    Ok(last + POSITION_STEP)
+}
+
+fn group_item(
+    group: &GroupReq,
+    parent: &str,
+    created_by: &str,
+    timestamp: i64,
+    position: i64,
+) -> Item {
+    let item = HashMap::from([
+        (PK.to_string(), AttributeValue::S(parent.to_string())),
+        (SK.to_string(), AttributeValue::S(group_key(&new_id()))),
+        (TITLE.to_string(), AttributeValue::S(group.title.clone())),
+        (
+            CREATED.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        ),
+        (
+            LAST_UPDATED.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        ),
+        (VERSION.to_string(), AttributeValue::N(String::from("1"))),
+        (
+            CREATED_BY.to_string(),
+            AttributeValue::S(created_by.to_string()),
+        ),
+        (
+            UPDATED_BY.to_string(),
+            AttributeValue::S(created_by.to_string()),
+        ),
+        (
+            DESCRIPTION.to_string(),
+            AttributeValue::S(group.description.clone()),
+        ),
+        (PARENT.to_string(), AttributeValue::S(parent.to_string())),
+        (
+            POSITION.to_string(),
+            AttributeValue::N(position.to_string()),
+        ),
+    ]);
+    with_tags(item, &group.tags)
+}
+
+pub(crate) fn note_item(document_id: &str, note: &Note, position: i64) -> Item {
+    let note_sk = format!("{}#NOTE#{}", note.parent, note.id);
+
+    let mut item = HashMap::from([
+        (PK.to_string(), AttributeValue::S(document_key(document_id))),
+        (SK.to_string(), AttributeValue::S(note_sk)),
+        (TITLE.to_string(), AttributeValue::S(note.title.clone())),
+        (
+            CREATED.to_string(),
+            AttributeValue::N(note.created.to_string()),
+        ),
+        (
+            LAST_UPDATED.to_string(),
+            AttributeValue::N(note.created.to_string()),
+        ),
+        (VERSION.to_string(), AttributeValue::N(String::from("1"))),
+        (
+            CREATED_BY.to_string(),
+            AttributeValue::S(note.created_by.clone()),
+        ),
+        (
+            UPDATED_BY.to_string(),
+            AttributeValue::S(note.updated_by.clone()),
+        ),
+        (
+            DESCRIPTION.to_string(),
+            AttributeValue::S(note.description.clone()),
+        ),
+        (PARENT.to_string(), AttributeValue::S(note.parent.clone())),
+        (
+            POSITION.to_string(),
+            AttributeValue::N(position.to_string()),
+        ),
+    ]);
+    if let Some(content) = &note.content {
+        item.insert(CONTENT.to_string(), content_value(content));
+    }
+    with_tags(item, &note.tags)
+}
+
+/// The title and description of a partial update, leaving out the ones
+/// that were not given so they keep their stored value.
+pub(crate) fn edited_fields(title: Option<&String>, description: Option<&String>) -> Vec<Field> {
+    let mut fields = Vec::new();
+    if let Some(title) = title {
+        fields.push((TITLE, AttributeValue::S(title.clone())));
+    }
+    if let Some(description) = description {
+        fields.push((DESCRIPTION, AttributeValue::S(description.clone())));
+    }
+    fields
+}
+
+/// Sets the given fields, see `edited_fields`, and bumps the item's version.
+pub(crate) fn set_fields(
+    item: &mut Item,
+    fields: Vec<Field>,
+    last_updated: i64,
+    updated_by: &str,
+) -> Result<()> {
+    let version: u64 = get_n(item, VERSION)?;
+    for (name, value) in fields {
+        item.insert(name.to_string(), value);
+    }
+    item.insert(
+        LAST_UPDATED.to_string(),
+        AttributeValue::N(last_updated.to_string()),
+    );
+    item.insert(
+        UPDATED_BY.to_string(),
+        AttributeValue::S(updated_by.to_string()),
+    );
+    item.insert(
+        VERSION.to_string(),
+        AttributeValue::N((version + 1).to_string()),
+    );
+    Ok(())
+}
+
+/// The items of a group, the group first, as they are stored once moved to
+/// `to`, a document of `owner`.
+pub(crate) fn moved_group(
+    items: &[Item],
+    to: &Placement<'_>,
+    owner: &str,
+    updated_by: &str,
+    timestamp: i64,
+) -> Result<Vec<Item>> {
+    let pk = document_key(to.document_id);
+    let mut moved = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        let mut item = item.clone();
+        item.insert(PK.to_string(), AttributeValue::S(pk.clone()));
+        // Notes deleted on their own are listed in the trash by their path.
+        if item.contains_key(GSI1SK) {
+            item.insert(GSI1PK.to_string(), AttributeValue::S(trash_key(owner)));
+            item.insert(GSI1SK.to_string(), AttributeValue::S(path_of(&item)?));
+        }
+        if index == 0 {
+            item.insert(PARENT.to_string(), AttributeValue::S(pk.clone()));
+            let position = AttributeValue::N(to.position.to_string());
+            set_fields(&mut item, vec![(POSITION, position)], timestamp, updated_by)?;
+        }
+        moved.push(item);
+    }
+    Ok(moved)
+}
+
+/// The note item as it is stored once the note is moved to `to`.
+pub(crate) fn moved_note(
+    note: &Item,
+    to: &Placement<'_>,
+    updated_by: &str,
+    timestamp: i64,
+) -> Result<Item> {
+    let group_id = to
+        .group_id
+        .ok_or_else(|| ApiError::Storage(String::from("a note can only be moved into a group")))?;
+    let sk = get_s(note, SK)?;
+    let mut note = note.clone();
+    note.insert(
+        PK.to_string(),
+        AttributeValue::S(document_key(to.document_id)),
+    );
+    note.insert(
+        SK.to_string(),
+        AttributeValue::S(note_key(group_id, id_of(&sk))),
+    );
+    note.insert(PARENT.to_string(), AttributeValue::S(group_key(group_id)));
+    let position = AttributeValue::N(to.position.to_string());
+    set_fields(&mut note, vec![(POSITION, position)], timestamp, updated_by)?;
+    Ok(note)
+}
+
+/// Stores a note body as a map holding only the fields its type uses.
+pub(crate) fn content_value(content: &NoteContent) -> AttributeValue {
+    let mut value = HashMap::from([(
+        String::from("type"),
+        AttributeValue::S(content.kind.clone()),
+    )]);
+    let strings = [
+        ("text", &content.text),
+        ("language", &content.language),
+        ("code", &content.code),
+        ("url", &content.url),
+        ("title", &content.title),
+    ];
+    for (name, field) in strings {
+        if let Some(field) = field {
+            value.insert(name.to_string(), AttributeValue::S(field.clone()));
+        }
+    }
+    if let Some(items) = &content.items {
+        let items = items.iter().map(checklist_item_value).collect();
+        value.insert(String::from("items"), AttributeValue::L(items));
+    }
+    AttributeValue::M(value)
+}
+
+fn checklist_item_value(item: &ChecklistItem) -> AttributeValue {
+    let id = match item.id.as_str() {
+        "" => new_id(),
+        id => id.to_string(),
+    };
+    AttributeValue::M(HashMap::from([
+        (String::from("id"), AttributeValue::S(id)),
+        (String::from("text"), AttributeValue::S(item.text.clone())),
+        (String::from("done"), AttributeValue::Bool(item.done)),
+    ]))
+}
+
+/// Stores tags as a string set, left out while empty since DynamoDB has no
+/// empty sets.
+pub(crate) fn with_tags(mut item: Item, tags: &[String]) -> Item {
+    let tags = tags
+        .iter()
+        .filter_map(|tag| normalize_tag(tag))
+        .collect::<BTreeSet<_>>();
+    if tags.is_empty() {
+        item.remove(TAGS);
+    } else {
+        item.insert(
+            TAGS.to_string(),
+            AttributeValue::Ss(tags.into_iter().collect()),
+        );
+    }
+    item
+}
+
+/// Whether the change adds a tag the item carries already.
+pub(crate) fn tagged_already(item: &Item, change: &TagChange<'_>) -> bool {
+    matches!(change, TagChange::Add(tag) if get_ss(item, TAGS).iter().any(|t| t == tag))
+}
+
+/// Access list entries carry the document key in `GSI1SK`, so shared
+/// documents list in the same order, and page the same way, as owned ones.
+pub(crate) fn share_item(
+    document_id: &str,
+    user: &str,
+    role: Role,
+    granted_by: &str,
+    timestamp: i64,
+) -> Item {
+    let key = document_key(document_id);
+
+    HashMap::from([
+        (PK.to_string(), AttributeValue::S(key.clone())),
+        (SK.to_string(), AttributeValue::S(share_key(user))),
+        (GSI1PK.to_string(), AttributeValue::S(shared_key(user))),
+        (GSI1SK.to_string(), AttributeValue::S(key)),
+        (USER.to_string(), AttributeValue::S(user.to_string())),
+        (ROLE.to_string(), AttributeValue::S(role.to_string())),
+        (
+            CREATED.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        ),
+        (
+            CREATED_BY.to_string(),
+            AttributeValue::S(granted_by.to_string()),
+        ),
+    ])
+}
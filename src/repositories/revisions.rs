@@ -0,0 +1,116 @@
+//! The revision history of documents, groups and notes, in a
+//! `REVISION#<id>` partition per entity sorted by version.
+
+use std::collections::{BTreeSet, HashMap};
+
+use aws_sdk_dynamodb::model::AttributeValue;
+
+use crate::{
+    error::Result,
+    services::{
+        get_n, get_s, id_of, CONTENT, DESCRIPTION, LAST_UPDATED, PK, SK, TAGS, TITLE, UPDATED_BY,
+        VERSION,
+    },
+};
+
+use super::{set_fields, Field, Item};
+
+pub const REVISED: &str = "revised";
+pub const REVISED_BY: &str = "revisedBy";
+pub const CHANGED: &str = "changed";
+
+/// The fields a revision keeps and a revert brings back.
+const REVISED_FIELDS: [&str; 4] = [TITLE, DESCRIPTION, CONTENT, TAGS];
+
+pub(crate) fn revision_key(entity_id: &str) -> String {
+    format!("REVISION#{}", entity_id)
+}
+
+/// Zero-padded so revisions sort by version.
+pub(crate) fn version_key(version: u64) -> String {
+    format!("VERSION#{:010}", version)
+}
+
+/// The names of the fields an edit sets to something other than what the
+/// item holds.
+pub(crate) fn changed_fields(item: &Item, fields: &[Field]) -> Vec<&'static str> {
+    fields
+        .iter()
+        .filter(|(name, value)| !same_value(item.get(*name), Some(value)))
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// Compares two attributes, string sets regardless of the order DynamoDB
+/// happened to return them in.
+fn same_value(a: Option<&AttributeValue>, b: Option<&AttributeValue>) -> bool {
+    match (a, b) {
+        (Some(AttributeValue::Ss(a)), Some(AttributeValue::Ss(b))) => {
+            a.iter().collect::<BTreeSet<_>>() == b.iter().collect::<BTreeSet<_>>()
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// The revision recording `item` as it was before `revised_by` changed the
+/// `changed` fields at `timestamp`.
+pub(crate) fn revision_item(
+    item: &Item,
+    changed: &[&str],
+    revised_by: &str,
+    timestamp: i64,
+) -> Result<Item> {
+    let sk = get_s(item, SK)?;
+    let version: u64 = get_n(item, VERSION)?;
+    let mut revision = HashMap::from([
+        (PK.to_string(), AttributeValue::S(revision_key(id_of(&sk)))),
+        (SK.to_string(), AttributeValue::S(version_key(version))),
+        (VERSION.to_string(), AttributeValue::N(version.to_string())),
+        (
+            REVISED_BY.to_string(),
+            AttributeValue::S(revised_by.to_string()),
+        ),
+        (
+            REVISED.to_string(),
+            AttributeValue::N(timestamp.to_string()),
+        ),
+    ]);
+    for name in REVISED_FIELDS.into_iter().chain([LAST_UPDATED, UPDATED_BY]) {
+        if let Some(value) = item.get(name) {
+            revision.insert(name.to_string(), value.clone());
+        }
+    }
+    // String sets cannot be empty, so a change of nothing but the version
+    // leaves the attribute out.
+    if !changed.is_empty() {
+        revision.insert(
+            CHANGED.to_string(),
+            AttributeValue::Ss(changed.iter().map(|name| name.to_string()).collect()),
+        );
+    }
+    Ok(revision)
+}
+
+/// The item with the fields of the revision put back, as a new version,
+/// together with the names of the fields that differ from what it holds.
+pub(crate) fn reverted_item(
+    item: &Item,
+    revision: &Item,
+    updated_by: &str,
+    timestamp: i64,
+) -> Result<(Item, Vec<&'static str>)> {
+    let mut reverted = item.clone();
+    let mut changed = Vec::new();
+    for name in REVISED_FIELDS {
+        if same_value(item.get(name), revision.get(name)) {
+            continue;
+        }
+        changed.push(name);
+        match revision.get(name) {
+            Some(value) => reverted.insert(name.to_string(), value.clone()),
+            None => reverted.remove(name),
+        };
+    }
+    set_fields(&mut reverted, Vec::new(), timestamp, updated_by)?;
+    Ok((reverted, changed))
+}
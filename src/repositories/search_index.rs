@@ -0,0 +1,215 @@
+//! The inverted indexes behind search and tags: `SEARCH#<owner>#<token>` and
+//! `TAG#<owner>#<tag>` partitions sorted by item path.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use aws_sdk_dynamodb::model::AttributeValue;
+
+use crate::services::{get_s, get_ss, DESCRIPTION, PK, SK, TAGS, TITLE};
+
+use super::{is_trashed, Item};
+
+pub const KIND: &str = "kind";
+pub const DOCUMENT_ID: &str = "documentId";
+pub const GROUP_ID: &str = "groupId";
+pub const NOTE_ID: &str = "noteId";
+
+/// Tokens shorter than this are too common to be worth an entry.
+const MIN_TOKEN_LEN: usize = 2;
+const MAX_TOKEN_LEN: usize = 64;
+
+pub(crate) fn search_key(owner: &str, token: &str) -> String {
+    format!("SEARCH#{}#{}", escape_owner(owner), token)
+}
+
+pub(crate) fn tag_key(owner: &str, tag: &str) -> String {
+    format!("TAG#{}#{}", escape_owner(owner), tag)
+}
+
+/// The owner as it appears in index keys, with `%` and `#` percent-encoded
+/// so no two owners and tokens make the same key. Shared with `migrate`.
+pub fn escape_owner(owner: &str) -> String {
+    owner.replace('%', "%25").replace('#', "%23")
+}
+
+/// Lower-cased alphanumeric words, each once, in the order they first appear.
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| (MIN_TOKEN_LEN..=MAX_TOKEN_LEN).contains(&word.chars().count()))
+        .map(str::to_lowercase)
+        .filter(|token| seen.insert(token.clone()))
+        .collect()
+}
+
+/// The index entries of a document, group or note item under `owner`.
+pub(crate) fn entries(owner: &str, item: &Item) -> Vec<Item> {
+    if is_trashed(item) {
+        return Vec::new();
+    }
+    let (Ok(pk), Ok(sk)) = (get_s(item, PK), get_s(item, SK)) else {
+        return Vec::new();
+    };
+    let Some(path) = Path::of(&pk, &sk) else {
+        return Vec::new();
+    };
+    let title = get_s(item, TITLE).unwrap_or_default();
+    let description = get_s(item, DESCRIPTION).unwrap_or_default();
+
+    let mut tokens = tokenize(&title);
+    tokens.extend(tokenize(&description));
+    let mut keys = tokens
+        .into_iter()
+        .collect::<BTreeSet<_>>()
+        .iter()
+        .map(|token| search_key(owner, token))
+        .collect::<Vec<_>>();
+    keys.extend(get_ss(item, TAGS).iter().map(|tag| tag_key(owner, tag)));
+
+    keys.into_iter()
+        .map(|key| {
+            let mut entry = HashMap::from([
+                (PK.to_string(), AttributeValue::S(key)),
+                (SK.to_string(), AttributeValue::S(path.sort_key())),
+                (KIND.to_string(), AttributeValue::S(path.kind().to_string())),
+                (TITLE.to_string(), AttributeValue::S(title.clone())),
+                (
+                    DOCUMENT_ID.to_string(),
+                    AttributeValue::S(path.document_id.to_string()),
+                ),
+            ]);
+            if let Some(group_id) = path.group_id {
+                entry.insert(
+                    GROUP_ID.to_string(),
+                    AttributeValue::S(group_id.to_string()),
+                );
+            }
+            if let Some(note_id) = path.note_id {
+                entry.insert(NOTE_ID.to_string(), AttributeValue::S(note_id.to_string()));
+            }
+            entry
+        })
+        .collect()
+}
+
+/// The entries to write and the keys to delete so the index matches `new`
+/// instead of `old`.
+pub(crate) fn changes(owner: &str, old: &[Item], new: &[Item]) -> (Vec<Item>, Vec<Item>) {
+    let puts = new
+        .iter()
+        .flat_map(|item| entries(owner, item))
+        .collect::<Vec<_>>();
+    let kept = puts.iter().map(key_of).collect::<HashSet<_>>();
+
+    let mut deletes = Vec::new();
+    for entry in old.iter().flat_map(|item| entries(owner, item)) {
+        if !kept.contains(&key_of(&entry)) {
+            deletes.push(entry);
+        }
+    }
+    (puts, deletes)
+}
+
+fn key_of(entry: &Item) -> (String, String) {
+    (
+        get_s(entry, PK).unwrap_or_default(),
+        get_s(entry, SK).unwrap_or_default(),
+    )
+}
+
+/// Where an item sits: `DOCUMENT#d`, `GROUP#g` or `GROUP#g#NOTE#n` in the
+/// partition of document `d`.
+struct Path<'a> {
+    document_id: &'a str,
+    group_id: Option<&'a str>,
+    note_id: Option<&'a str>,
+}
+
+impl<'a> Path<'a> {
+    fn of(pk: &'a str, sk: &'a str) -> Option<Self> {
+        let document_id = pk.strip_prefix("DOCUMENT#")?;
+        if sk == pk {
+            return Some(Path {
+                document_id,
+                group_id: None,
+                note_id: None,
+            });
+        }
+
+        let rest = sk.strip_prefix("GROUP#")?;
+        let (group_id, note_id) = match rest.split_once("#NOTE#") {
+            Some((group_id, note_id)) => (group_id, Some(note_id)),
+            None => (rest, None),
+        };
+        Some(Path {
+            document_id,
+            group_id: Some(group_id),
+            note_id,
+        })
+    }
+
+    fn kind(&self) -> &'static str {
+        match (self.group_id, self.note_id) {
+            (None, _) => "document",
+            (Some(_), None) => "group",
+            (Some(_), Some(_)) => "note",
+        }
+    }
+
+    /// Sorts an item's entry after its document's and its group's, so hits
+    /// come back in the order the document reads.
+    fn sort_key(&self) -> String {
+        let mut key = format!("DOCUMENT#{}", self.document_id);
+        if let Some(group_id) = self.group_id {
+            key.push_str(&format!("#GROUP#{}", group_id));
+        }
+        if let Some(note_id) = self.note_id {
+            key.push_str(&format!("#NOTE#{}", note_id));
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_words_of_two_to_64_characters() {
+        let longest = "a".repeat(MAX_TOKEN_LEN);
+        let text = format!("a to {} {}b", longest, longest);
+        assert_eq!(tokenize(&text), ["to", longest.as_str()]);
+
+        // Lengths count characters, not bytes.
+        let accented = "é".repeat(MAX_TOKEN_LEN);
+        assert_eq!(tokenize(&accented), [accented.as_str()]);
+    }
+
+    #[test]
+    fn splits_on_anything_but_letters_and_digits() {
+        assert_eq!(
+            tokenize("docker-compose.yml, v2 (beta)"),
+            ["docker", "compose", "yml", "v2", "beta"]
+        );
+        assert_eq!(
+            tokenize("Crème brûlée à Paris"),
+            ["crème", "brûlée", "paris"]
+        );
+        assert_eq!(tokenize("東京 タワー"), ["東京", "タワー"]);
+    }
+
+    #[test]
+    fn folds_case_and_keeps_each_token_once() {
+        assert_eq!(tokenize("Docker DOCKER docker"), ["docker"]);
+        assert_eq!(tokenize("STRASSE Ärger"), ["strasse", "ärger"]);
+        assert_eq!(tokenize("ΣΟΦΙΑ σοφια"), ["σοφια"]);
+    }
+
+    #[test]
+    fn escapes_the_owner_in_index_keys() {
+        assert_eq!(search_key("alice", "docker"), "SEARCH#alice#docker");
+        assert_eq!(search_key("team#1", "docker"), "SEARCH#team%231#docker");
+        assert_eq!(tag_key("50%#off", "sale"), "TAG#50%25%23off#sale");
+        assert_ne!(search_key("a#b", "c"), search_key("a%23b", "c"));
+    }
+}
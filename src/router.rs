@@ -1,28 +1,87 @@
-use lambda_http::{http::Method, Body, Request, Response};
+use std::sync::Arc;
+
+use lambda_http::aws_lambda_events::query_map::QueryMap;
+use lambda_http::{
+    http::{
+        header::{ETAG, IF_MATCH},
+        HeaderMap, HeaderValue, Method,
+    },
+    Body, Context, Request, RequestExt, Response,
+};
 use matchit::{Match, Router};
-use nanoserde::{DeJson, SerJson};
+use nanoserde::SerJson;
 
+use crate::auth::{Authenticator, Caller};
 use crate::controllers::document_controller::DocumentController;
-use crate::controllers::{DocumentReq, NoteReq};
-use crate::repositories::document_repository::DatabaseRepository;
-use crate::services::notes_service::NotesService;
+use crate::controllers::{
+    tag_param, validation, ActivityListReq, ChecklistItemReq, ChecklistItemUpdateReq,
+    ChecklistOrderReq, DocumentListReq, DocumentReq, DocumentUpdateReq, GroupMoveReq, GroupReq,
+    GroupUpdateReq, NoteListReq, NoteMoveReq, NoteReq, NoteUpdateReq, RevisionListReq, SearchReq,
+    ShareReq, TrashListReq,
+};
+use crate::error::{ApiError, Result};
+use crate::repositories::{NotesStore, TagChange};
+use crate::services::activity_service::ActivityService;
+use crate::services::checklist_service::ChecklistService;
+use crate::services::groups_service::GroupsService;
+use crate::services::notes_service::{NotePath, NotesService};
+use crate::services::revisions_service::{Revised, RevisionsService};
+use crate::services::search_service::SearchService;
+use crate::services::shares_service::SharesService;
+use crate::services::trash_service::TrashService;
 
 pub enum HttpRoute {
     Documents,
     Document,
+    Groups,
     Group,
+    Note,
+    Notes,
+    Shares,
+    Share,
+    Search,
+    DocumentTag,
+    GroupTag,
+    NoteTag,
+    ChecklistItems,
+    ChecklistItem,
+    GroupMove,
+    NoteMove,
+    Trash,
+    DocumentRestore,
+    GroupRestore,
+    NoteRestore,
+    Revisions,
+    Revision,
+    RevisionRevert,
+    Activity,
 }
 
-pub struct RouterDelegate<'a> {
+pub struct RouterDelegate {
     router: Router<HttpRoute>,
-    document_controller: DocumentController<'a>,
-    notes_service: NotesService<'a>,
+    authenticator: Authenticator,
+    document_controller: DocumentController,
+    groups_service: GroupsService,
+    notes_service: NotesService,
+    shares_service: SharesService,
+    search_service: SearchService,
+    checklist_service: ChecklistService,
+    trash_service: TrashService,
+    revisions_service: RevisionsService,
+    activity_service: ActivityService,
 }
 
-impl<'a> RouterDelegate<'a> {
-    pub(crate) fn new(database: &'a DatabaseRepository) -> Self {
-        let document_service = DocumentController::new(&database);
-        let notes_service = NotesService::new(&database);
+impl RouterDelegate {
+    pub fn new(database: Arc<dyn NotesStore>, authenticator: Authenticator) -> Self {
+        let document_service = DocumentController::new(database.clone());
+        let groups_service = GroupsService::new(database.clone());
+        let notes_service = NotesService::new(database.clone());
+        let shares_service = SharesService::new(database.clone());
+        let search_service = SearchService::new(database.clone());
+        let checklist_service = ChecklistService::new(database.clone());
+        let trash_service = TrashService::new(database.clone());
+        let revisions_service = RevisionsService::new(database.clone());
+        let activity_service = ActivityService::new(database);
         let mut router = Router::new();
         router
             .insert("/api/notes/documents", HttpRoute::Documents)
@@ -30,84 +89,1613 @@ impl<'a> RouterDelegate<'a> {
         router
             .insert("/api/notes/documents/:id", HttpRoute::Document)
             .unwrap();
+        router
+            .insert("/api/notes/documents/:id/groups", HttpRoute::Groups)
+            .unwrap();
         router
             .insert("/api/notes/documents/:id/groups/:groupId", HttpRoute::Group)
             .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId",
+                HttpRoute::Note,
+            )
+            .unwrap();
+        router
+            .insert("/api/notes/documents/:id/shares", HttpRoute::Shares)
+            .unwrap();
+        router
+            .insert("/api/notes/documents/:id/shares/:userId", HttpRoute::Share)
+            .unwrap();
+        router
+            .insert("/api/notes/search", HttpRoute::Search)
+            .unwrap();
+        router.insert("/api/notes/notes", HttpRoute::Notes).unwrap();
+        router
+            .insert("/api/notes/documents/:id/tags/:tag", HttpRoute::DocumentTag)
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/tags/:tag",
+                HttpRoute::GroupTag,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/tags/:tag",
+                HttpRoute::NoteTag,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/items",
+                HttpRoute::ChecklistItems,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/items/:itemId",
+                HttpRoute::ChecklistItem,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/move",
+                HttpRoute::GroupMove,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/move",
+                HttpRoute::NoteMove,
+            )
+            .unwrap();
+        router.insert("/api/notes/trash", HttpRoute::Trash).unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/restore",
+                HttpRoute::DocumentRestore,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/restore",
+                HttpRoute::GroupRestore,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/restore",
+                HttpRoute::NoteRestore,
+            )
+            .unwrap();
+        router
+            .insert("/api/notes/documents/:id/revisions", HttpRoute::Revisions)
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/revisions/:version",
+                HttpRoute::Revision,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/revisions/:version/revert",
+                HttpRoute::RevisionRevert,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/revisions",
+                HttpRoute::Revisions,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/revisions/:version",
+                HttpRoute::Revision,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/revisions/:version/revert",
+                HttpRoute::RevisionRevert,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/revisions",
+                HttpRoute::Revisions,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/revisions/:version",
+                HttpRoute::Revision,
+            )
+            .unwrap();
+        router
+            .insert(
+                "/api/notes/documents/:id/groups/:groupId/notes/:noteId/revisions/:version/revert",
+                HttpRoute::RevisionRevert,
+            )
+            .unwrap();
+        router
+            .insert("/api/notes/documents/:id/activity", HttpRoute::Activity)
+            .unwrap();
         Self {
             router,
+            authenticator,
             document_controller: document_service,
+            groups_service,
             notes_service,
+            shares_service,
+            search_service,
+            checklist_service,
+            trash_service,
+            revisions_service,
+            activity_service,
         }
     }
 
-    pub(crate) async fn handle(&self, event: Request) -> Response<Body> {
+    pub async fn handle(&self, event: Request) -> Response<Body> {
+        let query = event.query_string_parameters();
+        let caller = self.authenticator.caller(&event);
         let (head, body) = event.into_parts();
-        let match_res = self.router.at(head.uri.path());
-        dbg!(body.clone());
-        match match_res {
-            Ok(m) => self.resolve(m, &head.method, body).await,
-            Err(_) => Response::builder().status(404).body(Body::Empty).unwrap(),
-        }
+        let request_id = head
+            .extensions
+            .get::<Context>()
+            .map(|context| context.request_id.clone())
+            .unwrap_or_default();
+        let response = match self.router.at(head.uri.path()) {
+            Ok(m) => match caller {
+                Ok(caller) => {
+                    self.resolve(m, &caller, &head.method, &head.headers, &query, body)
+                        .await
+                }
+                Err(err) => Err(err),
+            },
+            Err(_) => Err(ApiError::NotFound(format!(
+                "No resource found at '{}'",
+                head.uri.path()
+            ))),
+        };
+        response.unwrap_or_else(|err| err.into_response(&request_id))
     }
 
     async fn resolve(
         &self,
-        m: Match<'a, 'a, &HttpRoute>,
+        m: Match<'_, '_, &HttpRoute>,
+        caller: &Caller,
         method: &Method,
+        headers: &HeaderMap,
+        query: &QueryMap,
         body: Body,
-    ) -> Response<Body> {
+    ) -> Result<Response<Body>> {
         let value = m.value;
-        let response = match value {
+        match value {
             HttpRoute::Documents => match *method {
                 Method::GET => {
-                    let documents = self.document_controller.list_all().await;
-                    let body = SerJson::serialize_json(&documents);
-                    Response::builder()
-                        .status(200)
-                        .header("content-type", "application/json")
-                        .body(body.into())
-                        .unwrap()
-                }
-                Method::POST => match body {
-                    Body::Text(body) => {
-                        let document: DocumentReq = DeJson::deserialize_json(&body).unwrap();
-                        self.document_controller.save(&document).await.unwrap();
-                        return Response::builder().status(200).body(Body::Empty).unwrap();
-                    }
-                    _ => Response::builder().status(400).body(Body::Empty).unwrap(),
-                },
-                _ => Response::builder().status(405).body(Body::Empty).unwrap(),
-            },
-            HttpRoute::Document => match method {
-                &Method::GET => {
-                    let id = m.params.get("id").unwrap();
-                    let documents = self.document_controller.fetch_by_id(id).await;
-                    let document = documents.first().unwrap();
-                    let body = SerJson::serialize_json(document);
-                    Response::builder()
-                        .status(200)
-                        .header("content-type", "application/json")
-                        .body(body.into())
-                        .unwrap()
-                }
-                _ => Response::builder().status(405).body(Body::Empty).unwrap(),
+                    let list_req = DocumentListReq::from_query(query)?;
+                    let page = self.document_controller.list(caller, &list_req).await?;
+                    Ok(json_response(200, &page))
+                }
+                Method::POST => {
+                    let document: DocumentReq = validation::parse(text_body(&body)?)?;
+                    let documents = self.document_controller.save(caller, &document).await?;
+                    let document = documents.first().ok_or_else(|| {
+                        ApiError::Storage(String::from("saved document was not returned"))
+                    })?;
+                    Ok(with_etag(json_response(201, document), document.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Document => match *method {
+                Method::GET => {
+                    let id = param(&m, "id")?;
+                    let documents = self.document_controller.fetch_by_id(caller, id).await?;
+                    let document = documents
+                        .first()
+                        .ok_or_else(|| ApiError::not_found("Document", id))?;
+                    Ok(with_etag(json_response(200, document), document.version))
+                }
+                Method::PUT | Method::PATCH => {
+                    let id = param(&m, "id")?;
+                    let update: DocumentUpdateReq = validation::parse(text_body(&body)?)?;
+                    let documents = self
+                        .document_controller
+                        .update(caller, id, &update, if_match(headers)?)
+                        .await?;
+                    let document = documents
+                        .first()
+                        .ok_or_else(|| ApiError::not_found("Document", id))?;
+                    Ok(with_etag(json_response(200, document), document.version))
+                }
+                Method::DELETE => {
+                    let id = param(&m, "id")?;
+                    self.document_controller
+                        .delete(caller, id, if_match(headers)?)
+                        .await?;
+                    Ok(empty_response(204))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Groups => match *method {
+                Method::POST => {
+                    let doc_id = param(&m, "id")?;
+                    let group_req: GroupReq = validation::parse(text_body(&body)?)?;
+                    let group = self.groups_service.save(caller, doc_id, &group_req).await?;
+                    Ok(with_etag(json_response(201, &group), group.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
             },
             HttpRoute::Group => match *method {
-                Method::POST => match body {
-                    Body::Text(body) => {
-                        let doc_id: i32 = m.params.get("id").unwrap().parse().unwrap();
-                        let group_id: i32 = m.params.get("groupId").unwrap().parse().unwrap();
-                        let note_req: NoteReq = DeJson::deserialize_json(&body).unwrap();
+                Method::GET => {
+                    let doc_id = param(&m, "id")?;
+                    let group_id = param(&m, "groupId")?;
+                    let group = self
+                        .groups_service
+                        .fetch_by_id(caller, doc_id, group_id)
+                        .await?;
+                    Ok(with_etag(json_response(200, &group), group.version))
+                }
+                Method::PATCH => {
+                    let doc_id = param(&m, "id")?;
+                    let group_id = param(&m, "groupId")?;
+                    let update: GroupUpdateReq = validation::parse(text_body(&body)?)?;
+                    let group = self
+                        .groups_service
+                        .update(caller, doc_id, group_id, &update, if_match(headers)?)
+                        .await?;
+                    Ok(with_etag(json_response(200, &group), group.version))
+                }
+                Method::DELETE => {
+                    let doc_id = param(&m, "id")?;
+                    let group_id = param(&m, "groupId")?;
+                    self.groups_service
+                        .delete(caller, doc_id, group_id, if_match(headers)?)
+                        .await?;
+                    Ok(empty_response(204))
+                }
+                Method::POST => {
+                    let doc_id = param(&m, "id")?;
+                    let group_id = param(&m, "groupId")?;
+                    let note_req: NoteReq = validation::parse(text_body(&body)?)?;
+                    let note = self
+                        .notes_service
+                        .save(caller, doc_id, group_id, &note_req)
+                        .await?;
+                    Ok(with_etag(json_response(201, &note), note.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Note => {
+                let doc_id = param(&m, "id")?;
+                let group_id = param(&m, "groupId")?;
+                let note_id = param(&m, "noteId")?;
+                match *method {
+                    Method::GET => {
+                        let note = self
+                            .notes_service
+                            .fetch_by_id(caller, doc_id, group_id, note_id)
+                            .await?;
+                        Ok(with_etag(json_response(200, &note), note.version))
+                    }
+                    Method::PATCH => {
+                        let update: NoteUpdateReq = validation::parse(text_body(&body)?)?;
+                        let note = self
+                            .notes_service
+                            .update(
+                                caller,
+                                doc_id,
+                                group_id,
+                                note_id,
+                                &update,
+                                if_match(headers)?,
+                            )
+                            .await?;
+                        Ok(with_etag(json_response(200, &note), note.version))
+                    }
+                    Method::DELETE => {
                         self.notes_service
-                            .save(doc_id, group_id, &note_req)
-                            .await
-                            .unwrap()
+                            .delete(caller, doc_id, group_id, note_id, if_match(headers)?)
+                            .await?;
+                        Ok(empty_response(204))
                     }
-                    _ => Response::builder().status(400).body(Body::Empty).unwrap(),
-                },
-                _ => Response::builder().status(405).body(Body::Empty).unwrap(),
+                    _ => Err(ApiError::MethodNotAllowed),
+                }
+            }
+            HttpRoute::Shares => match *method {
+                Method::GET => {
+                    let doc_id = param(&m, "id")?;
+                    let shares = self.shares_service.list(caller, doc_id).await?;
+                    Ok(json_response(200, &shares))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Share => {
+                let doc_id = param(&m, "id")?;
+                let user_id = param(&m, "userId")?;
+                match *method {
+                    Method::PUT => {
+                        let share_req: ShareReq = validation::parse(text_body(&body)?)?;
+                        let share = self
+                            .shares_service
+                            .grant(caller, doc_id, user_id, &share_req)
+                            .await?;
+                        Ok(json_response(200, &share))
+                    }
+                    Method::DELETE => {
+                        self.shares_service.revoke(caller, doc_id, user_id).await?;
+                        Ok(empty_response(204))
+                    }
+                    _ => Err(ApiError::MethodNotAllowed),
+                }
+            }
+            HttpRoute::Search => match *method {
+                Method::GET => {
+                    let search_req = SearchReq::from_query(query)?;
+                    let results = self.search_service.search(caller, &search_req).await?;
+                    Ok(json_response(200, &results))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Notes => match *method {
+                Method::GET => {
+                    let list_req = NoteListReq::from_query(query)?;
+                    let page = self.notes_service.list_tagged(caller, &list_req).await?;
+                    Ok(json_response(200, &page))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::DocumentTag => {
+                let doc_id = param(&m, "id")?;
+                let tag = tag_param(param(&m, "tag")?)?;
+                let change = tag_change(method, &tag)?;
+                let tags = self
+                    .document_controller
+                    .change_tags(caller, doc_id, change, if_match(headers)?)
+                    .await?;
+                Ok(with_etag(json_response(200, &tags), tags.version))
+            }
+            HttpRoute::GroupTag => {
+                let doc_id = param(&m, "id")?;
+                let group_id = param(&m, "groupId")?;
+                let tag = tag_param(param(&m, "tag")?)?;
+                let change = tag_change(method, &tag)?;
+                let tags = self
+                    .groups_service
+                    .change_tags(caller, doc_id, group_id, change, if_match(headers)?)
+                    .await?;
+                Ok(with_etag(json_response(200, &tags), tags.version))
+            }
+            HttpRoute::NoteTag => {
+                let doc_id = param(&m, "id")?;
+                let group_id = param(&m, "groupId")?;
+                let note_id = param(&m, "noteId")?;
+                let tag = tag_param(param(&m, "tag")?)?;
+                let change = tag_change(method, &tag)?;
+                let tags = self
+                    .notes_service
+                    .change_tags(
+                        caller,
+                        doc_id,
+                        group_id,
+                        note_id,
+                        change,
+                        if_match(headers)?,
+                    )
+                    .await?;
+                Ok(with_etag(json_response(200, &tags), tags.version))
+            }
+            HttpRoute::ChecklistItems => {
+                let path = note_path(&m)?;
+                match *method {
+                    Method::POST => {
+                        let item_req: ChecklistItemReq = validation::parse(text_body(&body)?)?;
+                        let note = self
+                            .checklist_service
+                            .add(caller, &path, &item_req, if_match(headers)?)
+                            .await?;
+                        Ok(with_etag(json_response(201, &note), note.version))
+                    }
+                    Method::PUT => {
+                        let order_req: ChecklistOrderReq = validation::parse(text_body(&body)?)?;
+                        let note = self
+                            .checklist_service
+                            .reorder(caller, &path, &order_req, if_match(headers)?)
+                            .await?;
+                        Ok(with_etag(json_response(200, &note), note.version))
+                    }
+                    _ => Err(ApiError::MethodNotAllowed),
+                }
+            }
+            HttpRoute::ChecklistItem => {
+                let path = note_path(&m)?;
+                let item_id = param(&m, "itemId")?;
+                match *method {
+                    Method::PATCH => {
+                        let update: ChecklistItemUpdateReq = validation::parse(text_body(&body)?)?;
+                        let note = self
+                            .checklist_service
+                            .update(caller, &path, item_id, &update, if_match(headers)?)
+                            .await?;
+                        Ok(with_etag(json_response(200, &note), note.version))
+                    }
+                    Method::DELETE => {
+                        let note = self
+                            .checklist_service
+                            .delete(caller, &path, item_id, if_match(headers)?)
+                            .await?;
+                        Ok(with_etag(json_response(200, &note), note.version))
+                    }
+                    _ => Err(ApiError::MethodNotAllowed),
+                }
+            }
+            HttpRoute::GroupMove => match *method {
+                Method::POST => {
+                    let doc_id = param(&m, "id")?;
+                    let group_id = param(&m, "groupId")?;
+                    let move_req: GroupMoveReq = validation::parse(text_body(&body)?)?;
+                    let group = self
+                        .groups_service
+                        .move_to(caller, doc_id, group_id, &move_req, if_match(headers)?)
+                        .await?;
+                    Ok(with_etag(json_response(200, &group), group.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::NoteMove => match *method {
+                Method::POST => {
+                    let path = note_path(&m)?;
+                    let move_req: NoteMoveReq = validation::parse(text_body(&body)?)?;
+                    let note = self
+                        .notes_service
+                        .move_to(caller, &path, &move_req, if_match(headers)?)
+                        .await?;
+                    Ok(with_etag(json_response(200, &note), note.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Trash => match *method {
+                Method::GET => {
+                    let list_req = TrashListReq::from_query(query)?;
+                    let page = self.trash_service.list(caller, &list_req).await?;
+                    Ok(json_response(200, &page))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::DocumentRestore => match *method {
+                Method::POST => {
+                    let id = param(&m, "id")?;
+                    let documents = self.document_controller.restore(caller, id).await?;
+                    let document = documents
+                        .first()
+                        .ok_or_else(|| ApiError::not_found("Document", id))?;
+                    Ok(with_etag(json_response(200, document), document.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::GroupRestore => match *method {
+                Method::POST => {
+                    let doc_id = param(&m, "id")?;
+                    let group_id = param(&m, "groupId")?;
+                    let group = self
+                        .groups_service
+                        .restore(caller, doc_id, group_id)
+                        .await?;
+                    Ok(with_etag(json_response(200, &group), group.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::NoteRestore => match *method {
+                Method::POST => {
+                    let path = note_path(&m)?;
+                    let note = self.notes_service.restore(caller, &path).await?;
+                    Ok(with_etag(json_response(200, &note), note.version))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Revisions => match *method {
+                Method::GET => {
+                    let list_req = RevisionListReq::from_query(query)?;
+                    let page = self
+                        .revisions_service
+                        .list(caller, revised(&m)?, &list_req)
+                        .await?;
+                    Ok(json_response(200, &page))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Revision => match *method {
+                Method::GET => {
+                    let revision = self
+                        .revisions_service
+                        .fetch(caller, revised(&m)?, version_param(&m)?)
+                        .await?;
+                    Ok(json_response(200, &revision))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
             },
+            HttpRoute::RevisionRevert => match *method {
+                Method::POST => {
+                    let revised = revised(&m)?;
+                    self.revisions_service
+                        .revert(caller, revised, version_param(&m)?, if_match(headers)?)
+                        .await?;
+                    self.reverted(caller, revised).await
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+            HttpRoute::Activity => match *method {
+                Method::GET => {
+                    let list_req = ActivityListReq::from_query(query)?;
+                    let page = self
+                        .activity_service
+                        .list(caller, param(&m, "id")?, &list_req)
+                        .await?;
+                    Ok(json_response(200, &page))
+                }
+                _ => Err(ApiError::MethodNotAllowed),
+            },
+        }
+    }
+
+    /// Answers a revert with the document, group or note as it now is.
+    async fn reverted(&self, caller: &Caller, revised: Revised<'_>) -> Result<Response<Body>> {
+        match revised {
+            Revised::Document(id) => {
+                let documents = self.document_controller.fetch_by_id(caller, id).await?;
+                let document = documents
+                    .first()
+                    .ok_or_else(|| ApiError::not_found("Document", id))?;
+                Ok(with_etag(json_response(200, document), document.version))
+            }
+            Revised::Group(doc_id, group_id) => {
+                let group = self
+                    .groups_service
+                    .fetch_by_id(caller, doc_id, group_id)
+                    .await?;
+                Ok(with_etag(json_response(200, &group), group.version))
+            }
+            Revised::Note(path) => {
+                let note = self
+                    .notes_service
+                    .fetch_by_id(caller, path.document_id, path.group_id, path.note_id)
+                    .await?;
+                Ok(with_etag(json_response(200, &note), note.version))
+            }
+        }
+    }
+}
+
+fn json_response<T: SerJson>(status: u16, value: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(SerJson::serialize_json(value).into())
+        .expect("response is always valid")
+}
+
+/// Tags a document, group or note response with its version, which clients
+/// send back in `If-Match` to update or delete it.
+fn with_etag(mut response: Response<Body>, version: u64) -> Response<Body> {
+    let etag = HeaderValue::from_str(&format!("\"{}\"", version)).expect("etag is always valid");
+    response.headers_mut().insert(ETAG, etag);
+    response
+}
+
+/// The version a mutation expects the entity at, from an `If-Match` header
+/// holding an ETag given out by `with_etag`. `*` matches any version.
+fn if_match(headers: &HeaderMap) -> Result<Option<u64>> {
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value.to_str().map(str::trim).unwrap_or_default();
+    if value == "*" {
+        return Ok(None);
+    }
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|version| version.parse().ok())
+        .map(Some)
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "If-Match must be an ETag returned by the API, got '{}'",
+                value
+            ))
+        })
+}
+
+/// `PUT` adds the tag in the path, `DELETE` removes it.
+fn tag_change<'t>(method: &Method, tag: &'t str) -> Result<TagChange<'t>> {
+    match *method {
+        Method::PUT => Ok(TagChange::Add(tag)),
+        Method::DELETE => Ok(TagChange::Remove(tag)),
+        _ => Err(ApiError::MethodNotAllowed),
+    }
+}
+
+fn empty_response(status: u16) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::Empty)
+        .expect("response is always valid")
+}
+
+fn text_body(body: &Body) -> Result<&str> {
+    match body {
+        Body::Text(body) => Ok(body),
+        Body::Empty => Err(ApiError::BadRequest(String::from(
+            "A JSON request body is required",
+        ))),
+        Body::Binary(_) => Err(ApiError::BadRequest(String::from(
+            "The request body must be JSON text",
+        ))),
+    }
+}
+
+fn note_path<'m>(m: &'m Match<'_, '_, &HttpRoute>) -> Result<NotePath<'m>> {
+    Ok(NotePath {
+        document_id: param(m, "id")?,
+        group_id: param(m, "groupId")?,
+        note_id: param(m, "noteId")?,
+    })
+}
+
+/// The document, group or note a revisions route is about, the most
+/// specific one its path names.
+fn revised<'m>(m: &'m Match<'_, '_, &HttpRoute>) -> Result<Revised<'m>> {
+    if m.params.get("noteId").is_some() {
+        Ok(Revised::Note(note_path(m)?))
+    } else if let Some(group_id) = m.params.get("groupId") {
+        Ok(Revised::Group(param(m, "id")?, group_id))
+    } else {
+        Ok(Revised::Document(param(m, "id")?))
+    }
+}
+
+fn version_param(m: &Match<'_, '_, &HttpRoute>) -> Result<u64> {
+    let version = param(m, "version")?;
+    version
+        .parse()
+        .map_err(|_| ApiError::BadRequest(format!("Version '{}' is not a number", version)))
+}
+
+fn param<'m>(m: &'m Match<'_, '_, &HttpRoute>, name: &str) -> Result<&'m str> {
+    m.params
+        .get(name)
+        .ok_or_else(|| ApiError::BadRequest(format!("Missing path parameter '{}'", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+
+    use lambda_http::{
+        aws_lambda_events::apigw::ApiGatewayProxyRequestContext, http::header::CONTENT_TYPE,
+        request::RequestContext, RequestExt,
+    };
+
+    use super::*;
+    use crate::{
+        controllers::{DocumentReq, DocumentUpdateReq, GroupReq, GroupUpdateReq},
+        repositories::{
+            memory_repository::MemoryRepository, Item, Page, Placement, MAX_TRANSACTION_ITEMS,
+        },
+        services::{notes_service::Note, Role},
+    };
+
+    const CALLER: &str = "alice";
+
+    fn router() -> RouterDelegate {
+        let store = Arc::new(MemoryRepository::new(Duration::from_secs(60)));
+        RouterDelegate::new(store, Authenticator::new(None))
+    }
+
+    /// Sends a request as API Gateway would for `CALLER` and returns the
+    /// status and the parsed body.
+    async fn call(
+        router: &RouterDelegate,
+        method: Method,
+        path: &str,
+        body: Option<&str>,
+        if_match: Option<&str>,
+    ) -> (u16, serde_json::Value) {
+        call_as(router, CALLER, method, path, body, if_match).await
+    }
+
+    async fn call_as(
+        router: &RouterDelegate,
+        caller: &str,
+        method: Method,
+        path: &str,
+        body: Option<&str>,
+        if_match: Option<&str>,
+    ) -> (u16, serde_json::Value) {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let query = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        let mut request = lambda_http::http::Request::builder()
+            .method(method)
+            .uri(path)
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(version) = if_match {
+            request = request.header(IF_MATCH, version);
+        }
+        let body = body.map_or(Body::Empty, |body| Body::Text(body.to_string()));
+        let mut context = ApiGatewayProxyRequestContext::default();
+        context
+            .authorizer
+            .insert(String::from("principalId"), caller.into());
+        let request = request
+            .body(body)
+            .expect("request is valid")
+            .with_query_string_parameters(query)
+            .with_request_context(RequestContext::ApiGatewayV1(context));
+
+        let response = router.handle(request).await;
+        let status = response.status().as_u16();
+        let body = match response.body() {
+            Body::Text(text) => serde_json::from_str(text).expect("body is JSON"),
+            _ => serde_json::Value::Null,
         };
-        response
+        (status, body)
+    }
+
+    /// Creates a document with one group and returns its id.
+    async fn create_document(router: &RouterDelegate) -> String {
+        let (status, document) = call(
+            router,
+            Method::POST,
+            "/api/notes/documents",
+            Some(r#"{"title": "Groceries", "groups": [{"title": "Fruit"}]}"#),
+            None,
+        )
+        .await;
+        assert_eq!(status, 201);
+        document["id"]
+            .as_str()
+            .expect("document has an id")
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn creates_lists_and_gets_a_document() {
+        let router = router();
+        let id = create_document(&router).await;
+
+        let (status, page) = call(&router, Method::GET, "/api/notes/documents", None, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(page["documents"][0]["id"], id.as_str());
+        assert_eq!(page["documents"][0]["title"], "Groceries");
+
+        let path = format!("/api/notes/documents/{}", id);
+        let (status, document) = call(&router, Method::GET, &path, None, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(document["title"], "Groceries");
+        assert_eq!(document["owner"], CALLER);
+        assert_eq!(document["groups"][0]["title"], "Fruit");
+    }
+
+    #[tokio::test]
+    async fn answers_404_for_a_missing_document() {
+        let router = router();
+
+        let (status, _) = call(&router, Method::GET, "/api/notes/documents/1", None, None).await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn updates_a_document() {
+        let router = router();
+        let id = create_document(&router).await;
+        let path = format!("/api/notes/documents/{}", id);
+
+        let (status, document) = call(
+            &router,
+            Method::PATCH,
+            &path,
+            Some(r#"{"title": "Shopping"}"#),
+            None,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(document["title"], "Shopping");
+        let (_, document) = call(&router, Method::GET, &path, None, None).await;
+        assert_eq!(document["title"], "Shopping");
+    }
+
+    #[tokio::test]
+    async fn honours_if_match_on_a_patch() {
+        let router = router();
+        let id = create_document(&router).await;
+        let path = format!("/api/notes/documents/{}", id);
+        let patch = |title: &str| format!(r#"{{"title": "{}"}}"#, title);
+
+        let current = Some("\"1\"");
+        let (status, document) = call(
+            &router,
+            Method::PATCH,
+            &path,
+            Some(&patch("Shopping")),
+            current,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(document["version"], 2);
+        let (status, _) = call(&router, Method::PATCH, &path, Some(&patch("Food")), current).await;
+        assert_eq!(status, 412);
+        let (status, document) = call(
+            &router,
+            Method::PATCH,
+            &path,
+            Some(&patch("Food")),
+            Some("*"),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(document["version"], 3);
+
+        // Neither adding groups nor setting the same title is a new version.
+        let current = Some("\"3\"");
+        let groups = Some(r#"{"groups": [{"title": "Dairy"}]}"#);
+        let (status, document) = call(&router, Method::PATCH, &path, groups, current).await;
+        assert_eq!(status, 200);
+        assert_eq!(document["version"], 3);
+        let (status, document) =
+            call(&router, Method::PATCH, &path, Some(&patch("Food")), current).await;
+        assert_eq!(status, 200);
+        assert_eq!(document["version"], 3);
+        assert_eq!(document["groups"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[tokio::test]
+    async fn adds_as_many_groups_as_a_patch_allows() {
+        let router = router();
+        let id = create_document(&router).await;
+        let path = format!("/api/notes/documents/{}", id);
+        let groups = |count: usize, group: &str| {
+            let groups = vec![group; count].join(",");
+            format!(r#"{{"groups": [{}]}}"#, groups)
+        };
+
+        let patch = groups(validation::GROUPS_MAX_LEN, r#"{"title": "Group"}"#);
+        let (status, document) = call(&router, Method::PATCH, &path, Some(&patch), None).await;
+        assert_eq!(status, 200);
+        let added = document["groups"].as_array().expect("groups are listed");
+        assert_eq!(added.len(), validation::GROUPS_MAX_LEN + 1);
+
+        // Each of these groups takes two writes, one for its note.
+        let group = r#"{"title": "Group", "notes": [{"title": "Note"}]}"#;
+        let patch = groups(MAX_TRANSACTION_ITEMS / 2 + 1, group);
+        let (status, _) = call(&router, Method::PATCH, &path, Some(&patch), None).await;
+        assert_eq!(status, 422);
+        let (_, document) = call(&router, Method::GET, &path, None, None).await;
+        assert_eq!(document["version"], 1);
+        let groups = document["groups"].as_array().expect("groups are listed");
+        assert_eq!(groups.len(), validation::GROUPS_MAX_LEN + 1);
+    }
+
+    #[tokio::test]
+    async fn restores_a_note_only_once_its_group_is_back() {
+        let router = router();
+        let (_, document) = call(
+            &router,
+            Method::POST,
+            "/api/notes/documents",
+            Some(r#"{"title": "Groceries", "groups": [{"title": "Fruit", "notes": [{"title": "Apples"}]}]}"#),
+            None,
+        )
+        .await;
+        let group = &document["groups"][0];
+        let group_path = format!(
+            "/api/notes/documents/{}/groups/{}",
+            document["id"].as_str().expect("document has an id"),
+            group["id"].as_str().expect("group has an id")
+        );
+        let note_path = format!(
+            "{}/notes/{}",
+            group_path,
+            group["notes"][0]["id"].as_str().expect("note has an id")
+        );
+
+        let (status, _) = call(&router, Method::DELETE, &note_path, None, None).await;
+        assert_eq!(status, 204);
+        let (status, _) = call(&router, Method::DELETE, &group_path, None, None).await;
+        assert_eq!(status, 204);
+
+        let missing = format!("{}/notes/01M56TT71ES6WPKVYX167G9GX5/restore", group_path);
+        let (status, _) = call(&router, Method::POST, &missing, None, None).await;
+        assert_eq!(status, 404);
+        let restore = format!("{}/restore", note_path);
+        let (status, _) = call(&router, Method::POST, &restore, None, None).await;
+        assert_eq!(status, 409);
+
+        let (status, _) = call(
+            &router,
+            Method::POST,
+            &format!("{}/restore", group_path),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, 200);
+        let (status, note) = call(&router, Method::POST, &restore, None, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(note["title"], "Apples");
+    }
+
+    #[tokio::test]
+    async fn revises_moves_deletes_and_restores() {
+        let router = router();
+        let (_, document) = call(
+            &router,
+            Method::POST,
+            "/api/notes/documents",
+            Some(r#"{"title": "Groceries", "groups": [{"title": "Fruit", "notes": [{"title": "Apples"}, {"title": "Pears"}]}]}"#),
+            None,
+        )
+        .await;
+        let note_path = format!(
+            "/api/notes/documents/{}/groups/{}/notes/{}",
+            document["id"].as_str().expect("document has an id"),
+            document["groups"][0]["id"]
+                .as_str()
+                .expect("group has an id"),
+            document["groups"][0]["notes"][0]["id"]
+                .as_str()
+                .expect("note has an id")
+        );
+
+        let body = Some(r#"{"position": 1}"#);
+        let (status, _) = call(
+            &router,
+            Method::POST,
+            &format!("{}/move", note_path),
+            body,
+            None,
+        )
+        .await;
+        assert_eq!(status, 200);
+        let (status, _) = call(&router, Method::DELETE, &note_path, None, None).await;
+        assert_eq!(status, 204);
+        let restore = format!("{}/restore", note_path);
+        let (status, note) = call(&router, Method::POST, &restore, None, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(note["version"], 4);
+
+        let tag = format!("{}/tags/fruit", note_path);
+        let (_, tags) = call(&router, Method::PUT, &tag, None, None).await;
+        assert_eq!(tags["version"], 5);
+        let (status, tags) = call(&router, Method::PUT, &tag, None, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(tags["version"], 5);
+
+        let revisions = format!("{}/revisions", note_path);
+        let (_, page) = call(&router, Method::GET, &revisions, None, None).await;
+        let versions = page["revisions"]
+            .as_array()
+            .expect("revisions are listed")
+            .iter()
+            .map(|revision| {
+                revision["version"]
+                    .as_u64()
+                    .expect("revision has a version")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(versions, [4, 3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn lists_documents_and_notes_by_tag() {
+        let router = router();
+        let mut notes = Vec::new();
+        for _ in 0..3 {
+            let (_, document) = call(
+                &router,
+                Method::POST,
+                "/api/notes/documents",
+                Some(r#"{"title": "Groceries", "groups": [{"title": "Fruit", "notes": [{"title": "Apples"}]}]}"#),
+                None,
+            )
+            .await;
+            let path = format!(
+                "/api/notes/documents/{}",
+                document["id"].as_str().expect("document has an id")
+            );
+            let (status, tags) = call(
+                &router,
+                Method::PUT,
+                &format!("{}/tags/Food", path),
+                None,
+                None,
+            )
+            .await;
+            assert_eq!(status, 200);
+            assert_eq!(tags["tags"], serde_json::json!(["food"]));
+            notes.push(format!(
+                "{}/groups/{}/notes/{}",
+                path,
+                document["groups"][0]["id"]
+                    .as_str()
+                    .expect("group has an id"),
+                document["groups"][0]["notes"][0]["id"]
+                    .as_str()
+                    .expect("note has an id")
+            ));
+        }
+        for note in &notes[..2] {
+            let (status, _) = call(
+                &router,
+                Method::PUT,
+                &format!("{}/tags/fruit", note),
+                None,
+                None,
+            )
+            .await;
+            assert_eq!(status, 200);
+        }
+
+        let list = |path: &str, key: &str, page: &serde_json::Value| {
+            let listed = page[key].as_array().map_or(0, Vec::len);
+            let next = page["next"]
+                .as_str()
+                .map(|next| format!("{}&cursor={}", path, next));
+            (listed, next)
+        };
+        let path = "/api/notes/documents?tag=food&limit=2";
+        let (_, page) = call(&router, Method::GET, path, None, None).await;
+        let (listed, next) = list(path, "documents", &page);
+        assert_eq!(listed, 2);
+        let (_, page) = call(
+            &router,
+            Method::GET,
+            &next.expect("more follow"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(list(path, "documents", &page), (1, None));
+
+        let path = "/api/notes/notes?tag=fruit&limit=1";
+        let (_, page) = call(&router, Method::GET, path, None, None).await;
+        let (listed, next) = list(path, "notes", &page);
+        assert_eq!(listed, 1);
+        assert_eq!(page["notes"][0]["note"]["title"], "Apples");
+        let (_, page) = call(
+            &router,
+            Method::GET,
+            &next.expect("more follow"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(list(path, "notes", &page), (1, None));
+
+        let untag = format!("{}/tags/fruit", notes[0]);
+        let (status, _) = call(&router, Method::DELETE, &untag, None, None).await;
+        assert_eq!(status, 200);
+        let (status, _) = call(&router, Method::DELETE, &untag, None, None).await;
+        assert_eq!(status, 404);
+        let (_, page) = call(
+            &router,
+            Method::GET,
+            "/api/notes/notes?tag=fruit",
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(page["notes"].as_array().map(Vec::len), Some(1));
+    }
+
+    #[tokio::test]
+    async fn hides_a_shared_document_while_it_is_in_the_trash() {
+        let router = router();
+        let id = create_document(&router).await;
+        let path = format!("/api/notes/documents/{}", id);
+        let share = Some(r#"{"role": "viewer"}"#);
+        let (status, _) = call(
+            &router,
+            Method::PUT,
+            &format!("{}/shares/bob", path),
+            share,
+            None,
+        )
+        .await;
+        assert_eq!(status, 200);
+
+        let shared = "/api/notes/documents?shared=true";
+        let listed = |page: serde_json::Value| page["documents"].as_array().map_or(0, Vec::len);
+        let (_, page) = call_as(&router, "bob", Method::GET, shared, None, None).await;
+        assert_eq!(listed(page), 1);
+
+        let (status, _) = call(&router, Method::DELETE, &path, None, None).await;
+        assert_eq!(status, 204);
+        let (_, page) = call_as(&router, "bob", Method::GET, shared, None, None).await;
+        assert_eq!(listed(page), 0);
+        let (status, _) = call_as(&router, "bob", Method::GET, &path, None, None).await;
+        assert_eq!(status, 404);
+
+        let restore = format!("{}/restore", path);
+        let (status, _) = call(&router, Method::POST, &restore, None, None).await;
+        assert_eq!(status, 200);
+        let (_, page) = call_as(&router, "bob", Method::GET, shared, None, None).await;
+        assert_eq!(listed(page), 1);
+    }
+
+    #[tokio::test]
+    async fn records_moves_but_not_reordering() {
+        let router = router();
+        let (_, document) = call(
+            &router,
+            Method::POST,
+            "/api/notes/documents",
+            Some(r#"{"title": "Groceries", "groups": [{"title": "Fruit"}, {"title": "Dairy"}]}"#),
+            None,
+        )
+        .await;
+        let id = document["id"].as_str().expect("document has an id");
+        let other = create_document(&router).await;
+        let group = |n: usize| {
+            let group_id = document["groups"][n]["id"]
+                .as_str()
+                .expect("group has an id");
+            format!("/api/notes/documents/{}/groups/{}/move", id, group_id)
+        };
+
+        let (status, _) = call(
+            &router,
+            Method::POST,
+            &group(1),
+            Some(r#"{"position": 0}"#),
+            None,
+        )
+        .await;
+        assert_eq!(status, 200);
+        let body = format!(r#"{{"documentId": "{}"}}"#, other);
+        let (status, _) = call(&router, Method::POST, &group(0), Some(&body), None).await;
+        assert_eq!(status, 200);
+
+        for document_id in [id, other.as_str()] {
+            let path = format!("/api/notes/documents/{}/activity", document_id);
+            let (status, page) = call(&router, Method::GET, &path, None, None).await;
+            assert_eq!(status, 200);
+            let moves = page["events"]
+                .as_array()
+                .expect("events are listed")
+                .iter()
+                .filter(|event| event["action"] == "moved")
+                .count();
+            assert_eq!(moves, 1);
+        }
+    }
+
+    /// A store on which another writer edits a note right before each of
+    /// the first `races` writes to it, so those fail as stale.
+    struct RacingStore {
+        store: MemoryRepository,
+        races: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl NotesStore for RacingStore {
+        async fn list_documents(
+            &self,
+            owner: &str,
+            limit: usize,
+            after: Option<&str>,
+        ) -> Result<Page> {
+            self.store.list_documents(owner, limit, after).await
+        }
+
+        async fn fetch_by_id(&self, id: &str) -> Result<Vec<Item>> {
+            self.store.fetch_by_id(id).await
+        }
+
+        async fn fetch_document_by_id(&self, id: &str) -> Result<Item> {
+            self.store.fetch_document_by_id(id).await
+        }
+
+        async fn fetch_group_by_id(&self, document_id: &str, group_id: &str) -> Result<Item> {
+            self.store.fetch_group_by_id(document_id, group_id).await
+        }
+
+        async fn save(&self, document: &DocumentReq, owner: &str) -> Result<Vec<Item>> {
+            self.store.save(document, owner).await
+        }
+
+        async fn save_note(&self, document_id: &str, note: &Note) -> Result<Item> {
+            self.store.save_note(document_id, note).await
+        }
+
+        async fn update_document(
+            &self,
+            id: &str,
+            update: &DocumentUpdateReq,
+            updated_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<()> {
+            self.store
+                .update_document(id, update, updated_by, expected_version)
+                .await
+        }
+
+        async fn save_group(
+            &self,
+            document_id: &str,
+            group: &GroupReq,
+            created_by: &str,
+        ) -> Result<Vec<Item>> {
+            self.store.save_group(document_id, group, created_by).await
+        }
+
+        async fn fetch_group_with_notes(
+            &self,
+            document_id: &str,
+            group_id: &str,
+        ) -> Result<Vec<Item>> {
+            self.store
+                .fetch_group_with_notes(document_id, group_id)
+                .await
+        }
+
+        async fn update_group(
+            &self,
+            document_id: &str,
+            group_id: &str,
+            update: &GroupUpdateReq,
+            updated_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<()> {
+            self.store
+                .update_group(document_id, group_id, update, updated_by, expected_version)
+                .await
+        }
+
+        async fn fetch_note_by_id(
+            &self,
+            document_id: &str,
+            group_id: &str,
+            note_id: &str,
+        ) -> Result<Item> {
+            self.store
+                .fetch_note_by_id(document_id, group_id, note_id)
+                .await
+        }
+
+        async fn list_shared_documents(
+            &self,
+            user: &str,
+            limit: usize,
+            after: Option<&str>,
+        ) -> Result<Page> {
+            self.store.list_shared_documents(user, limit, after).await
+        }
+
+        async fn fetch_share(&self, document_id: &str, user: &str) -> Result<Option<Item>> {
+            self.store.fetch_share(document_id, user).await
+        }
+
+        async fn fetch_shares(&self, document_id: &str) -> Result<Vec<Item>> {
+            self.store.fetch_shares(document_id).await
+        }
+
+        async fn save_share(
+            &self,
+            document_id: &str,
+            user: &str,
+            role: Role,
+            granted_by: &str,
+        ) -> Result<Item> {
+            self.store
+                .save_share(document_id, user, role, granted_by)
+                .await
+        }
+
+        async fn delete_share(
+            &self,
+            document_id: &str,
+            user: &str,
+            revoked_by: &str,
+        ) -> Result<()> {
+            self.store.delete_share(document_id, user, revoked_by).await
+        }
+
+        async fn search(&self, owner: &str, token: &str) -> Result<Vec<Item>> {
+            self.store.search(owner, token).await
+        }
+
+        async fn update_tags(
+            &self,
+            document_id: &str,
+            sk: &str,
+            change: TagChange<'_>,
+            updated_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<Item> {
+            self.store
+                .update_tags(document_id, sk, change, updated_by, expected_version)
+                .await
+        }
+
+        async fn tagged(
+            &self,
+            owner: &str,
+            tag: &str,
+            limit: usize,
+            after: Option<&str>,
+        ) -> Result<Page> {
+            self.store.tagged(owner, tag, limit, after).await
+        }
+
+        async fn set_position(&self, document_id: &str, sk: &str, position: i64) -> Result<()> {
+            self.store.set_position(document_id, sk, position).await
+        }
+
+        async fn move_group(
+            &self,
+            document_id: &str,
+            group_id: &str,
+            to: &Placement<'_>,
+            updated_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<()> {
+            self.store
+                .move_group(document_id, group_id, to, updated_by, expected_version)
+                .await
+        }
+
+        async fn move_note(
+            &self,
+            document_id: &str,
+            group_id: &str,
+            note_id: &str,
+            to: &Placement<'_>,
+            updated_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<()> {
+            self.store
+                .move_note(
+                    document_id,
+                    group_id,
+                    note_id,
+                    to,
+                    updated_by,
+                    expected_version,
+                )
+                .await
+        }
+
+        async fn trash(
+            &self,
+            document_id: &str,
+            sk: &str,
+            trashed_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<()> {
+            self.store
+                .trash(document_id, sk, trashed_by, expected_version)
+                .await
+        }
+
+        async fn fetch_trashed(&self, document_id: &str, sk: &str) -> Result<Item> {
+            self.store.fetch_trashed(document_id, sk).await
+        }
+
+        async fn restore(&self, document_id: &str, sk: &str, restored_by: &str) -> Result<()> {
+            self.store.restore(document_id, sk, restored_by).await
+        }
+
+        async fn list_trash(&self, owner: &str) -> Result<Vec<Item>> {
+            self.store.list_trash(owner).await
+        }
+
+        async fn list_revisions(
+            &self,
+            entity_id: &str,
+            limit: usize,
+            before: Option<u64>,
+        ) -> Result<Page> {
+            self.store.list_revisions(entity_id, limit, before).await
+        }
+
+        async fn fetch_revision(&self, entity_id: &str, version: u64) -> Result<Item> {
+            self.store.fetch_revision(entity_id, version).await
+        }
+
+        async fn list_activity(
+            &self,
+            document_id: &str,
+            limit: usize,
+            before: Option<&str>,
+        ) -> Result<Page> {
+            self.store.list_activity(document_id, limit, before).await
+        }
+
+        async fn revert(
+            &self,
+            document_id: &str,
+            sk: &str,
+            version: u64,
+            updated_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<()> {
+            self.store
+                .revert(document_id, sk, version, updated_by, expected_version)
+                .await
+        }
+
+        async fn update_note(
+            &self,
+            document_id: &str,
+            group_id: &str,
+            note_id: &str,
+            update: &NoteUpdateReq,
+            updated_by: &str,
+            expected_version: Option<u64>,
+        ) -> Result<()> {
+            let raced = self
+                .races
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |races| {
+                    races.checked_sub(1)
+                })
+                .is_ok();
+            if raced {
+                let edit = NoteUpdateReq {
+                    title: Some("Edited elsewhere".to_string()),
+                    description: None,
+                    content: None,
+                };
+                self.store
+                    .update_note(document_id, group_id, note_id, &edit, "bob", None)
+                    .await?;
+            }
+            self.store
+                .update_note(
+                    document_id,
+                    group_id,
+                    note_id,
+                    update,
+                    updated_by,
+                    expected_version,
+                )
+                .await
+        }
+    }
+
+    /// Creates a document holding one checklist note with `Milk` on it and
+    /// returns the path of its items.
+    async fn create_checklist(router: &RouterDelegate) -> String {
+        let (status, document) = call(
+            router,
+            Method::POST,
+            "/api/notes/documents",
+            Some(
+                r#"{"title": "Groceries", "groups": [{"title": "Fruit", "notes": [
+                    {"title": "Shopping", "content": {"type": "checklist", "items": [{"text": "Milk"}]}}
+                ]}]}"#,
+            ),
+            None,
+        )
+        .await;
+        assert_eq!(status, 201);
+        let group = &document["groups"][0];
+        format!(
+            "/api/notes/documents/{}/groups/{}/notes/{}/items",
+            document["id"].as_str().expect("document has an id"),
+            group["id"].as_str().expect("group has an id"),
+            group["notes"][0]["id"].as_str().expect("note has an id"),
+        )
+    }
+
+    fn item_texts(note: &serde_json::Value) -> Vec<&str> {
+        note["content"]["items"]
+            .as_array()
+            .expect("checklist has items")
+            .iter()
+            .map(|item| item["text"].as_str().expect("item has a text"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn edits_checklist_items() {
+        let router = router();
+        let items = create_checklist(&router).await;
+
+        let (status, note) = call(
+            &router,
+            Method::POST,
+            &items,
+            Some(r#"{"text": "Eggs", "done": false}"#),
+            None,
+        )
+        .await;
+        assert_eq!(status, 201);
+        assert_eq!(item_texts(&note), ["Milk", "Eggs"]);
+        let ids: Vec<String> = note["content"]["items"]
+            .as_array()
+            .expect("checklist has items")
+            .iter()
+            .map(|item| item["id"].as_str().expect("item has an id").to_string())
+            .collect();
+
+        let path = format!("{}/{}", items, ids[0]);
+        let (status, note) = call(
+            &router,
+            Method::PATCH,
+            &path,
+            Some(r#"{"done": true}"#),
+            None,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(note["content"]["items"][0]["done"], true);
+
+        let order = format!(r#"{{"items": ["{}", "{}"]}}"#, ids[1], ids[0]);
+        let (status, note) = call(&router, Method::PUT, &items, Some(&order), None).await;
+        assert_eq!(status, 200);
+        assert_eq!(item_texts(&note), ["Eggs", "Milk"]);
+
+        let (status, note) = call(&router, Method::DELETE, &path, None, None).await;
+        assert_eq!(status, 200);
+        assert_eq!(item_texts(&note), ["Eggs"]);
+        let (status, _) = call(&router, Method::DELETE, &path, None, None).await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn retries_checklist_edits_that_lost_a_race() {
+        let store = Arc::new(RacingStore {
+            store: MemoryRepository::new(Duration::from_secs(60)),
+            races: AtomicUsize::new(0),
+        });
+        let router = RouterDelegate::new(store.clone(), Authenticator::new(None));
+        let items = create_checklist(&router).await;
+        let eggs = Some(r#"{"text": "Eggs", "done": false}"#);
+
+        store.races.store(2, Ordering::SeqCst);
+        let (status, note) = call(&router, Method::POST, &items, eggs, None).await;
+        assert_eq!(status, 201);
+        assert_eq!(note["title"], "Edited elsewhere");
+        assert_eq!(item_texts(&note), ["Milk", "Eggs"]);
+
+        store.races.store(3, Ordering::SeqCst);
+        let (status, _) = call(&router, Method::POST, &items, eggs, None).await;
+        assert_eq!(status, 412);
+
+        let version = note["version"].as_u64().expect("note has a version") + 3;
+        store.races.store(1, Ordering::SeqCst);
+        let (status, _) = call(
+            &router,
+            Method::POST,
+            &items,
+            eggs,
+            Some(&format!("\"{}\"", version)),
+        )
+        .await;
+        assert_eq!(status, 412);
+    }
+
+    #[tokio::test]
+    async fn deletes_a_document() {
+        let router = router();
+        let id = create_document(&router).await;
+        let path = format!("/api/notes/documents/{}", id);
+
+        let (status, _) = call(&router, Method::DELETE, &path, None, None).await;
+        assert_eq!(status, 204);
+        let (status, _) = call(&router, Method::GET, &path, None, None).await;
+        assert_eq!(status, 404);
     }
 }
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use crate::{
+    auth::Caller,
+    controllers::{decode_cursor, encode_cursor, ActivityListReq},
+    error::Result,
+    repositories::NotesStore,
+};
+
+use super::{fetch_accessible_document, ActivityEvent, ActivityPage, Role};
+
+pub struct ActivityService {
+    database_repository: Arc<dyn NotesStore>,
+}
+
+impl ActivityService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
+        Self {
+            database_repository,
+        }
+    }
+
+    /// Lists what was done to the document and everything in it, newest
+    /// first, to anyone who can see the document.
+    pub(crate) async fn list(
+        &self,
+        caller: &Caller,
+        document_id: &str,
+        req: &ActivityListReq,
+    ) -> Result<ActivityPage> {
+        let store = self.database_repository.as_ref();
+        fetch_accessible_document(store, caller, document_id, Role::Viewer).await?;
+        let before = req.cursor.as_deref().map(decode_cursor).transpose()?;
+
+        let page = store
+            .list_activity(document_id, req.limit, before.as_deref())
+            .await?;
+        let events = page
+            .items
+            .iter()
+            .map(ActivityEvent::try_from)
+            .collect::<Result<_>>()?;
+        let next = page.next.as_deref().map(encode_cursor);
+        Ok(ActivityPage::new(events, next))
+    }
+}
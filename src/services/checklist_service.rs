@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use crate::{
+    auth::Caller,
+    controllers::{
+        validation::CHECKLIST_MAX_LEN, ChecklistItem, ChecklistItemReq, ChecklistItemUpdateReq,
+        ChecklistOrderReq, NoteUpdateReq,
+    },
+    error::{ApiError, Result},
+    repositories::{new_id, NotesStore},
+};
+
+use super::{
+    ensure_version, get_content, get_n,
+    notes_service::{fetch_note_of_group, NotePath},
+    Note, Role, VERSION,
+};
+
+/// A write that lost a race with another one is tried this many times
+/// before the caller gets the `412`.
+const MAX_ATTEMPTS: usize = 3;
+
+pub struct ChecklistService {
+    database_repository: Arc<dyn NotesStore>,
+}
+
+impl ChecklistService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
+        Self {
+            database_repository,
+        }
+    }
+
+    pub(crate) async fn add(
+        &self,
+        caller: &Caller,
+        path: &NotePath<'_>,
+        req: &ChecklistItemReq,
+        if_match: Option<u64>,
+    ) -> Result<Note> {
+        self.edit(caller, path, if_match, |items| {
+            if items.len() >= CHECKLIST_MAX_LEN {
+                return Err(ApiError::Validation(format!(
+                    "Checklist '{}' already holds the maximum of {} items",
+                    path.note_id, CHECKLIST_MAX_LEN
+                )));
+            }
+            let position = req.position.unwrap_or(items.len()).min(items.len());
+            items.insert(
+                position,
+                ChecklistItem {
+                    id: new_id(),
+                    text: req.text.clone(),
+                    done: req.done,
+                },
+            );
+            Ok(())
+        })
+        .await
+    }
+
+    pub(crate) async fn update(
+        &self,
+        caller: &Caller,
+        path: &NotePath<'_>,
+        item_id: &str,
+        req: &ChecklistItemUpdateReq,
+        if_match: Option<u64>,
+    ) -> Result<Note> {
+        self.edit(caller, path, if_match, |items| {
+            let item = items
+                .iter_mut()
+                .find(|item| item.id == item_id)
+                .ok_or_else(|| ApiError::not_found("Checklist item", item_id))?;
+            if let Some(text) = &req.text {
+                item.text = text.clone();
+            }
+            if let Some(done) = req.done {
+                item.done = done;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub(crate) async fn delete(
+        &self,
+        caller: &Caller,
+        path: &NotePath<'_>,
+        item_id: &str,
+        if_match: Option<u64>,
+    ) -> Result<Note> {
+        self.edit(caller, path, if_match, |items| {
+            let position = items
+                .iter()
+                .position(|item| item.id == item_id)
+                .ok_or_else(|| ApiError::not_found("Checklist item", item_id))?;
+            items.remove(position);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Puts the items in the order of `req`, which has to name every item
+    /// exactly once.
+    pub(crate) async fn reorder(
+        &self,
+        caller: &Caller,
+        path: &NotePath<'_>,
+        req: &ChecklistOrderReq,
+        if_match: Option<u64>,
+    ) -> Result<Note> {
+        self.edit(caller, path, if_match, |items| {
+            let mut reordered = Vec::with_capacity(items.len());
+            for id in req.items.iter() {
+                let position = items
+                    .iter()
+                    .position(|item| &item.id == id)
+                    .ok_or_else(|| {
+                        ApiError::Validation(format!(
+                            "Item '{}' is not in checklist '{}' or is listed twice",
+                            id, path.note_id
+                        ))
+                    })?;
+                reordered.push(items.remove(position));
+            }
+            if !items.is_empty() {
+                return Err(ApiError::Validation(format!(
+                    "The new order leaves out {} of the checklist's items",
+                    items.len()
+                )));
+            }
+            *items = reordered;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Applies `edit` to the checklist items and stores them, replaying it on
+    /// a newer note when there is no `If-Match`.
+    async fn edit<F>(
+        &self,
+        caller: &Caller,
+        path: &NotePath<'_>,
+        if_match: Option<u64>,
+        edit: F,
+    ) -> Result<Note>
+    where
+        F: Fn(&mut Vec<ChecklistItem>) -> Result<()>,
+    {
+        let store = self.database_repository.as_ref();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let note = fetch_note_of_group(
+                store,
+                caller,
+                path.document_id,
+                path.group_id,
+                path.note_id,
+                Role::Editor,
+            )
+            .await?;
+            ensure_version(&note, if_match, "Note", path.note_id)?;
+            let mut content = get_content(&note)?
+                .filter(|content| content.kind == "checklist")
+                .ok_or_else(|| {
+                    ApiError::Validation(format!("Note '{}' is not a checklist", path.note_id))
+                })?;
+
+            let mut items = content.items.take().unwrap_or_default();
+            edit(&mut items)?;
+            content.items = Some(items);
+
+            let update = NoteUpdateReq {
+                title: None,
+                description: None,
+                content: Some(content),
+            };
+            let expected_version = if_match.unwrap_or(get_n(&note, VERSION)?);
+            let written = store
+                .update_note(
+                    path.document_id,
+                    path.group_id,
+                    path.note_id,
+                    &update,
+                    &caller.id,
+                    Some(expected_version),
+                )
+                .await;
+            match written {
+                Ok(()) => break,
+                Err(ApiError::PreconditionFailed(_))
+                    if if_match.is_none() && attempts < MAX_ATTEMPTS =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let note = store
+            .fetch_note_by_id(path.document_id, path.group_id, path.note_id)
+            .await?;
+        Note::try_from(&note)
+    }
+}
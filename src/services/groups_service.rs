@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use crate::{
+    auth::Caller,
+    controllers::{GroupMoveReq, GroupReq, GroupUpdateReq},
+    error::{ApiError, Result},
+    repositories::{document_key, group_key, Item, NotesStore, Placement, TagChange},
+};
+
+use super::{
+    change_tags, ensure_version, fetch_accessible_document, get_s, position_among, Group, Note,
+    Role, Tags, PARENT,
+};
+
+pub struct GroupsService {
+    database_repository: Arc<dyn NotesStore>,
+}
+
+impl GroupsService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
+        Self {
+            database_repository,
+        }
+    }
+
+    pub(crate) async fn save(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_req: &GroupReq,
+    ) -> Result<Group> {
+        fetch_accessible_document(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            Role::Editor,
+        )
+        .await?;
+        let items = self
+            .database_repository
+            .save_group(doc_id, group_req, &caller.id)
+            .await?;
+        let (group, notes) = items
+            .split_first()
+            .ok_or_else(|| ApiError::Storage(String::from("group was not stored")))?;
+        let mut group = Group::try_from(group)?;
+        let notes = notes.iter().map(Note::try_from).collect::<Result<_>>()?;
+        group.set_notes(notes);
+        Ok(group)
+    }
+
+    pub(crate) async fn fetch_by_id(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+    ) -> Result<Group> {
+        fetch_accessible_document(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            Role::Viewer,
+        )
+        .await?;
+        let items = self
+            .database_repository
+            .fetch_group_with_notes(doc_id, group_id)
+            .await?;
+
+        let (group, notes) = items
+            .split_first()
+            .ok_or_else(|| ApiError::not_found("Group", group_id))?;
+        let mut group = Group::try_from(group)?;
+        let notes = notes.iter().map(Note::try_from).collect::<Result<_>>()?;
+        group.set_notes(notes);
+        Ok(group)
+    }
+
+    pub(crate) async fn update(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        update: &GroupUpdateReq,
+        if_match: Option<u64>,
+    ) -> Result<Group> {
+        fetch_group_of_document(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            group_id,
+            Role::Editor,
+        )
+        .await?;
+        self.database_repository
+            .update_group(doc_id, group_id, update, &caller.id, if_match)
+            .await?;
+        self.fetch_by_id(caller, doc_id, group_id).await
+    }
+
+    /// Moves the group to `position` among the groups of its document, or of
+    /// another one the caller can edit as well.
+    pub(crate) async fn move_to(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        move_req: &GroupMoveReq,
+        if_match: Option<u64>,
+    ) -> Result<Group> {
+        let store = self.database_repository.as_ref();
+        let group = fetch_group_of_document(store, caller, doc_id, group_id, Role::Editor).await?;
+        ensure_version(&group, if_match, "Group", group_id)?;
+
+        let to_document = move_req.document_id.as_deref().unwrap_or(doc_id);
+        if to_document != doc_id {
+            fetch_accessible_document(store, caller, to_document, Role::Editor).await?;
+        }
+        let items = store.fetch_by_id(to_document).await?;
+        let position = position_among(
+            store,
+            to_document,
+            &items,
+            &document_key(to_document),
+            &group_key(group_id),
+            move_req.position,
+        )
+        .await?;
+
+        let to = Placement {
+            document_id: to_document,
+            group_id: None,
+            position,
+        };
+        store
+            .move_group(doc_id, group_id, &to, &caller.id, if_match)
+            .await?;
+        self.fetch_by_id(caller, to_document, group_id).await
+    }
+
+    pub(crate) async fn change_tags(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        change: TagChange<'_>,
+        if_match: Option<u64>,
+    ) -> Result<Tags> {
+        let store = self.database_repository.as_ref();
+        let group = fetch_group_of_document(store, caller, doc_id, group_id, Role::Editor).await?;
+        change_tags(store, &group, change, caller, if_match).await
+    }
+
+    pub(crate) async fn delete(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        if_match: Option<u64>,
+    ) -> Result<()> {
+        let group = fetch_group_of_document(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            group_id,
+            Role::Editor,
+        )
+        .await?;
+        ensure_version(&group, if_match, "Group", group_id)?;
+        self.database_repository
+            .trash(doc_id, &group_key(group_id), &caller.id, if_match)
+            .await
+    }
+
+    /// Takes a deleted group back out of the trash, with the notes that went
+    /// there along with it.
+    pub(crate) async fn restore(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+    ) -> Result<Group> {
+        let store = self.database_repository.as_ref();
+        fetch_accessible_document(store, caller, doc_id, Role::Editor).await?;
+        store
+            .restore(doc_id, &group_key(group_id), &caller.id)
+            .await?;
+        self.fetch_by_id(caller, doc_id, group_id).await
+    }
+}
+
+/// Fetches the group, making sure it actually hangs off the given document
+/// and that the caller holds at least the `required` role on the document.
+pub(crate) async fn fetch_group_of_document(
+    database_repository: &dyn NotesStore,
+    caller: &Caller,
+    doc_id: &str,
+    group_id: &str,
+    required: Role,
+) -> Result<Item> {
+    fetch_accessible_document(database_repository, caller, doc_id, required).await?;
+    let group = database_repository
+        .fetch_group_by_id(doc_id, group_id)
+        .await?;
+
+    if get_s(&group, PARENT)? != document_key(doc_id) {
+        return Err(ApiError::Validation(format!(
+            "Group '{}' does not belong to document '{}'",
+            group_id, doc_id
+        )));
+    }
+    Ok(group)
+}
@@ -1,6 +1,8 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
     ops::{Deref, DerefMut},
+    str::FromStr,
 };
 
 use aws_sdk_dynamodb::model::AttributeValue;
@@ -8,126 +10,661 @@ use aws_sdk_dynamodb::model::AttributeValue;
 use multimap::MultiMap;
 use nanoserde::{DeJson, SerJson};
 
+use crate::{
+    auth::Caller,
+    controllers::{validation::TAGS_MAX_LEN, ChecklistItem, NoteContent},
+    error::{ApiError, Result},
+    repositories::{
+        activity, entity_of, revisions, search_index, share_key, Item, NotesStore, TagChange,
+        POSITION_STEP,
+    },
+};
+
 pub const PK: &str = "PK";
 pub const SK: &str = "SK";
+pub const GSI1PK: &str = "GSI1PK";
+pub const GSI1SK: &str = "GSI1SK";
 pub const TITLE: &str = "title";
 pub const CREATED: &str = "created";
 pub const PARENT: &str = "parent";
+pub const OWNER: &str = "owner";
+pub const CREATED_BY: &str = "createdBy";
 pub const UPDATED_BY: &str = "updatedBy";
 pub const LAST_UPDATED: &str = "lastUpdated";
 pub const DESCRIPTION: &str = "description";
+pub const USER: &str = "user";
+pub const ROLE: &str = "role";
+pub const VERSION: &str = "version";
+pub const TAGS: &str = "tags";
+pub const CONTENT: &str = "content";
+pub const POSITION: &str = "position";
+pub const TRASHED: &str = "trashed";
+pub const TRASHED_BY: &str = "trashedBy";
+pub const TRASH_ROOT: &str = "trashRoot";
+/// When DynamoDB's TTL purges an item in the trash, in epoch seconds.
+pub const EXPIRES_AT: &str = "expiresAt";
 
+pub mod activity_service;
+pub mod checklist_service;
+pub mod groups_service;
 pub mod notes_service;
+pub mod revisions_service;
+pub mod search_service;
+pub mod shares_service;
+pub mod trash_service;
+
+/// Shared documents are gathered this many at a time.
+const SHARED_PAGE_SIZE: usize = 100;
+
+pub(crate) fn get_s(item: &HashMap<String, AttributeValue>, key: &str) -> Result<String> {
+    item.get(key)
+        .and_then(|value| value.as_s().ok())
+        .cloned()
+        .ok_or_else(|| ApiError::Storage(format!("item is missing string attribute '{}'", key)))
+}
+
+/// Strips the entity prefixes from a sort key, `GROUP#7#NOTE#42` becomes `42`.
+pub(crate) fn id_of(sk: &str) -> &str {
+    sk.rsplit_once('#').map_or(sk, |(_, id)| id)
+}
+
+/// Reads a string set, which DynamoDB leaves out entirely while it is empty.
+pub(crate) fn get_ss(item: &HashMap<String, AttributeValue>, key: &str) -> Vec<String> {
+    item.get(key)
+        .and_then(|value| value.as_ss().ok())
+        .map(|values| values.iter().cloned().collect::<BTreeSet<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .collect()
+}
+
+/// Reads a note body stored by `content_value`; notes from before typed
+/// bodies have none.
+pub(crate) fn get_content(item: &HashMap<String, AttributeValue>) -> Result<Option<NoteContent>> {
+    let Some(value) = item.get(CONTENT) else {
+        return Ok(None);
+    };
+    let content = value
+        .as_m()
+        .map_err(|_| ApiError::Storage(format!("attribute '{}' is not a map", CONTENT)))?;
+    let string = |name: &str| get_s(content, name).ok();
+    let items = match content.get("items") {
+        Some(items) => {
+            let items = items
+                .as_l()
+                .map_err(|_| ApiError::Storage(String::from("checklist items are not a list")))?;
+            let items = items
+                .iter()
+                .map(|item| {
+                    let item = item.as_m().map_err(|_| {
+                        ApiError::Storage(String::from("checklist item is not a map"))
+                    })?;
+                    Ok(ChecklistItem {
+                        id: get_s(item, "id").unwrap_or_default(),
+                        text: get_s(item, "text")?,
+                        done: matches!(item.get("done"), Some(AttributeValue::Bool(true))),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            Some(items)
+        }
+        None => None,
+    };
+
+    Ok(Some(NoteContent {
+        kind: get_s(content, "type")?,
+        text: string("text"),
+        language: string("language"),
+        code: string("code"),
+        url: string("url"),
+        title: string("title"),
+        items,
+    }))
+}
+
+pub(crate) fn get_n<T: FromStr>(item: &HashMap<String, AttributeValue>, key: &str) -> Result<T> {
+    item.get(key)
+        .and_then(|value| value.as_n().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| ApiError::Storage(format!("item is missing number attribute '{}'", key)))
+}
+
+/// What a caller may do with a document, each role allowing what the ones
+/// before it do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = ApiError;
+
+    fn from_str(role: &str) -> Result<Self> {
+        match role {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(ApiError::Validation(format!(
+                "Role '{}' is not one of owner, editor or viewer",
+                role
+            ))),
+        }
+    }
+}
+
+/// Fetches the document item after checking the caller holds at least the
+/// `required` role on it.
+pub(crate) async fn fetch_accessible_document(
+    database_repository: &dyn NotesStore,
+    caller: &Caller,
+    id: &str,
+    required: Role,
+) -> Result<Item> {
+    let document = database_repository.fetch_document_by_id(id).await?;
+    let share = if get_s(&document, OWNER)? == caller.id {
+        None
+    } else {
+        database_repository.fetch_share(id, &caller.id).await?
+    };
+    ensure_role(&document, share.as_ref(), caller, id, required)?;
+    Ok(document)
+}
+
+/// Checks the caller's role on the document. Callers without access get a
+/// `404`.
+pub(crate) fn ensure_role(
+    document: &Item,
+    share: Option<&Item>,
+    caller: &Caller,
+    id: &str,
+    required: Role,
+) -> Result<Role> {
+    let role = if get_s(document, OWNER)? == caller.id {
+        Role::Owner
+    } else if let Some(share) = share {
+        get_s(share, ROLE)?.parse()?
+    } else {
+        return Err(ApiError::not_found("Document", id));
+    };
+
+    if role < required {
+        return Err(ApiError::Forbidden(format!(
+            "Document '{}' needs the {} role, the caller's role is {}",
+            id, required, role
+        )));
+    }
+    Ok(role)
+}
+
+/// The caller's entry in an access list, if the partition holds one.
+pub(crate) fn share_of<'a>(items: &'a [Item], caller: &Caller) -> Option<&'a Item> {
+    let share_sk = share_key(&caller.id);
+    items
+        .iter()
+        .find(|item| get_s(item, SK).is_ok_and(|sk| sk == share_sk))
+}
+
+/// A part of the per-owner indexes the caller may read: all of the caller's
+/// own documents, or the documents another owner shared with the caller.
+pub(crate) struct Scope {
+    pub owner: String,
+    documents: Option<HashSet<String>>,
+}
+
+impl Scope {
+    pub(crate) fn own(caller: &Caller) -> Self {
+        Scope {
+            owner: caller.id.clone(),
+            documents: None,
+        }
+    }
+
+    /// Whether an index entry belongs to a document the scope covers.
+    pub(crate) fn contains(&self, entry: &Item) -> Result<bool> {
+        Ok(self.contains_document(&get_s(entry, search_index::DOCUMENT_ID)?))
+    }
+
+    pub(crate) fn contains_document(&self, document_id: &str) -> bool {
+        self.documents
+            .as_ref()
+            .is_none_or(|documents| documents.contains(document_id))
+    }
+}
+
+/// One scope per owner who shared documents with the caller.
+pub(crate) async fn shared_scopes(
+    database_repository: &dyn NotesStore,
+    caller: &Caller,
+) -> Result<Vec<Scope>> {
+    let mut shared = BTreeMap::<String, HashSet<String>>::new();
+    let mut after = None;
+    loop {
+        let page = database_repository
+            .list_shared_documents(&caller.id, SHARED_PAGE_SIZE, after.as_deref())
+            .await?;
+        for document in page.items.iter() {
+            let id = id_of(&get_s(document, SK)?).to_string();
+            shared
+                .entry(get_s(document, OWNER)?)
+                .or_default()
+                .insert(id);
+        }
+        after = page.next;
+        if after.is_none() {
+            break;
+        }
+    }
+
+    Ok(shared
+        .into_iter()
+        .map(|(owner, documents)| Scope {
+            owner,
+            documents: Some(documents),
+        })
+        .collect())
+}
+
+/// The caller's own scope followed by the shared ones.
+pub(crate) async fn visible_scopes(
+    database_repository: &dyn NotesStore,
+    caller: &Caller,
+) -> Result<Vec<Scope>> {
+    let mut scopes = vec![Scope::own(caller)];
+    scopes.extend(shared_scopes(database_repository, caller).await?);
+    Ok(scopes)
+}
+
+/// The first `limit` tag index entries of one `kind` in the scopes.
+pub(crate) async fn tagged_entries(
+    database_repository: &dyn NotesStore,
+    scopes: &[Scope],
+    tag: &str,
+    kind: &str,
+    after: Option<&str>,
+    limit: usize,
+) -> Result<Vec<Item>> {
+    let mut entries = BTreeMap::new();
+    for scope in scopes {
+        let mut found = 0;
+        let mut start = after.map(String::from);
+        loop {
+            let page = database_repository
+                .tagged(&scope.owner, tag, limit, start.as_deref())
+                .await?;
+            for entry in page.items {
+                if get_s(&entry, search_index::KIND)? == kind && scope.contains(&entry)? {
+                    entries.insert(get_s(&entry, SK)?, entry);
+                    found += 1;
+                }
+            }
+            match page.next {
+                Some(next) if found < limit => start = Some(next),
+                _ => break,
+            }
+        }
+    }
+    Ok(entries.into_values().take(limit).collect())
+}
+
+/// Adds or removes a tag of an item the caller may edit.
+pub(crate) async fn change_tags(
+    database_repository: &dyn NotesStore,
+    item: &Item,
+    change: TagChange<'_>,
+    caller: &Caller,
+    if_match: Option<u64>,
+) -> Result<Tags> {
+    let pk = get_s(item, PK)?;
+    let sk = get_s(item, SK)?;
+    let entity = entity_of(&sk);
+    ensure_version(item, if_match, entity, id_of(&sk))?;
+
+    let tags = get_ss(item, TAGS);
+    match change {
+        TagChange::Add(tag) if !tags.iter().any(|t| t == tag) && tags.len() >= TAGS_MAX_LEN => {
+            return Err(ApiError::Validation(format!(
+                "{} '{}' already has the maximum of {} tags",
+                entity,
+                id_of(&sk),
+                TAGS_MAX_LEN
+            )));
+        }
+        TagChange::Remove(tag) if !tags.iter().any(|t| t == tag) => {
+            return Err(ApiError::not_found("Tag", tag));
+        }
+        _ => {}
+    }
+
+    let item = database_repository
+        .update_tags(id_of(&pk), &sk, change, &caller.id, if_match)
+        .await?;
+    Ok(Tags {
+        tags: get_ss(&item, TAGS),
+        version: get_n(&item, VERSION)?,
+    })
+}
+
+/// The position that puts an item moved into `parent` at `index`, spreading
+/// the others out when there is no room.
+pub(crate) async fn position_among(
+    database_repository: &dyn NotesStore,
+    document_id: &str,
+    items: &[Item],
+    parent: &str,
+    moved_sk: &str,
+    index: Option<usize>,
+) -> Result<i64> {
+    let mut siblings = Vec::new();
+    for item in items {
+        let sk = get_s(item, SK)?;
+        if get_s(item, PARENT).is_ok_and(|p| p == parent) && sk != moved_sk {
+            siblings.push((get_n::<i64>(item, POSITION)?, sk));
+        }
+    }
+    siblings.sort();
+
+    let index = index.unwrap_or(siblings.len()).min(siblings.len());
+    let before = index.checked_sub(1).map(|i| siblings[i].0);
+    let after = siblings.get(index).map(|(position, _)| *position);
+    match (before, after) {
+        (None, None) => return Ok(POSITION_STEP),
+        (Some(before), None) => return Ok(before + POSITION_STEP),
+        (None, Some(after)) => return Ok(after - POSITION_STEP),
+        (Some(before), Some(after)) if after - before > 1 => {
+            return Ok(before + (after - before) / 2)
+        }
+        _ => {}
+    }
+
+    for (i, (position, sk)) in siblings.iter().enumerate() {
+        let slot = if i < index { i + 1 } else { i + 2 };
+        let spread = slot as i64 * POSITION_STEP;
+        if *position != spread {
+            database_repository
+                .set_position(document_id, sk, spread)
+                .await?;
+        }
+    }
+    Ok((index as i64 + 1) * POSITION_STEP)
+}
+
+/// Checks an `If-Match` version against the stored item, so a client
+/// writing on top of a copy it read earlier learns that it is stale.
+pub(crate) fn ensure_version(
+    item: &Item,
+    expected: Option<u64>,
+    entity: &str,
+    id: &str,
+) -> Result<()> {
+    match expected {
+        Some(expected) if get_n::<u64>(item, VERSION)? != expected => {
+            Err(ApiError::stale_version(entity, id, expected))
+        }
+        _ => Ok(()),
+    }
+}
 
 #[derive(Clone, SerJson, DeJson)]
 pub struct Document {
+    pub id: String,
     pub pk: String,
     pub sk: String,
     pub title: String,
     description: String,
     created: i64,
+    pub version: u64,
+    owner: String,
     #[nserde(rename = "updatedBy")]
     updated_by: String,
+    tags: Vec<String>,
     groups: Vec<Group>,
 }
 
 impl Document {
-    fn new(
-        pk: String,
-        sk: String,
-        title: String,
-        description: String,
-        created: i64,
-        updated_by: String,
-    ) -> Self {
-        let groups = Vec::default();
-        Document {
-            pk,
-            sk,
-            title,
-            description,
-            created,
-            updated_by,
-            groups,
-        }
-    }
-
-    fn add_group(&mut self, group: Group) {
-        self.groups.push(group);
+    fn set_groups(&mut self, mut groups: Vec<Group>) {
+        groups.sort_by(|a, b| (a.position, &a.id).cmp(&(b.position, &b.id)));
+        self.groups = groups;
     }
 }
 
-impl From<HashMap<String, AttributeValue>> for Document {
-    fn from(group_entity: HashMap<String, AttributeValue>) -> Self {
-        let pk = group_entity[PK].as_s().unwrap().to_owned();
-        let sk = group_entity[SK].as_s().unwrap().to_owned();
-        let title = group_entity[TITLE].as_s().unwrap().to_owned();
-        let updated_by = group_entity[UPDATED_BY].as_s().unwrap().to_owned();
-        let description = group_entity[DESCRIPTION].as_s().unwrap().to_owned();
-        let created: i64 = group_entity[CREATED]
-            .as_n()
-            .unwrap()
-            .clone()
-            .parse()
-            .unwrap();
-        Document::new(pk, sk, title, description, created, updated_by)
+impl TryFrom<HashMap<String, AttributeValue>> for Document {
+    type Error = ApiError;
+
+    fn try_from(document_entity: HashMap<String, AttributeValue>) -> Result<Self> {
+        let sk = get_s(&document_entity, SK)?;
+        Ok(Document {
+            id: id_of(&sk).to_string(),
+            pk: get_s(&document_entity, PK)?,
+            sk,
+            title: get_s(&document_entity, TITLE)?,
+            description: get_s(&document_entity, DESCRIPTION)?,
+            created: get_n(&document_entity, CREATED)?,
+            version: get_n(&document_entity, VERSION)?,
+            owner: get_s(&document_entity, OWNER)?,
+            updated_by: get_s(&document_entity, UPDATED_BY)?,
+            tags: get_ss(&document_entity, TAGS),
+            groups: Vec::default(),
+        })
     }
 }
 
 #[derive(SerJson, DeJson, Clone)]
 pub struct Group {
+    pub id: String,
     pub sk: String,
     title: String,
+    description: String,
     created: u32,
+    pub version: u64,
+    position: i64,
+    tags: Vec<String>,
+    /// Left out unless one of the notes is a checklist.
+    #[nserde(default_with = "Option::default")]
+    checklist: Option<Progress>,
     notes: Vec<Note>,
 }
 
+/// How many items the checklists of a group hold and how many of them are
+/// done.
+#[derive(SerJson, DeJson, Clone, Default)]
+pub struct Progress {
+    done: u32,
+    total: u32,
+}
+
 impl Group {
-    fn new(sk: impl Into<String>, title: String, created: u32) -> Group {
+    fn new(
+        sk: impl Into<String>,
+        title: String,
+        description: String,
+        created: u32,
+        version: u64,
+        position: i64,
+        tags: Vec<String>,
+    ) -> Group {
         let sk = sk.into();
         let notes = Vec::default();
         Group {
+            id: id_of(&sk).to_string(),
             sk,
             title,
+            description,
             created,
+            version,
+            position,
+            tags,
+            checklist: None,
             notes,
         }
     }
 
-    fn set_notes(&mut self, notes: Vec<Note>) {
+    fn set_notes(&mut self, mut notes: Vec<Note>) {
+        notes.sort_by(|a, b| (a.position, &a.id).cmp(&(b.position, &b.id)));
+        let mut checklist = None;
+        for content in notes.iter().filter_map(|note| note.content.as_ref()) {
+            if content.kind != "checklist" {
+                continue;
+            }
+            let progress = checklist.get_or_insert_with(Progress::default);
+            for item in content.items.iter().flatten() {
+                progress.total += 1;
+                progress.done += u32::from(item.done);
+            }
+        }
+        self.checklist = checklist;
         self.notes = notes;
     }
 }
 
-impl From<&HashMap<String, AttributeValue>> for Group {
-    fn from(group_entity: &HashMap<String, AttributeValue>) -> Self {
-        let sk = group_entity[SK].as_s().unwrap();
-        let title = group_entity[TITLE].as_s().unwrap().clone();
-        let created: u32 = group_entity[CREATED]
-            .as_n()
-            .unwrap()
-            .clone()
-            .parse()
-            .unwrap();
-        Group::new(sk, title, created)
+impl TryFrom<&HashMap<String, AttributeValue>> for Group {
+    type Error = ApiError;
+
+    fn try_from(group_entity: &HashMap<String, AttributeValue>) -> Result<Self> {
+        let sk = get_s(group_entity, SK)?;
+        let title = get_s(group_entity, TITLE)?;
+        // Groups written before descriptions were stored have none.
+        let description = get_s(group_entity, DESCRIPTION).unwrap_or_default();
+        let created: u32 = get_n(group_entity, CREATED)?;
+        let version: u64 = get_n(group_entity, VERSION)?;
+        let position: i64 = get_n(group_entity, POSITION)?;
+        let tags = get_ss(group_entity, TAGS);
+        Ok(Group::new(
+            sk,
+            title,
+            description,
+            created,
+            version,
+            position,
+            tags,
+        ))
     }
 }
 
 #[derive(SerJson, DeJson, Clone)]
 pub struct Note {
+    pub id: String,
     pub title: String,
+    pub description: String,
     pub created: u32,
+    pub version: u64,
+    pub position: i64,
+    #[nserde(default_with = "Option::default")]
+    pub content: Option<NoteContent>,
+    pub tags: Vec<String>,
+}
+
+impl TryFrom<&HashMap<String, AttributeValue>> for Note {
+    type Error = ApiError;
+
+    fn try_from(note_entity: &HashMap<String, AttributeValue>) -> Result<Self> {
+        let sk = get_s(note_entity, SK)?;
+        let id = id_of(&sk).to_string();
+        let title = get_s(note_entity, TITLE)?;
+        let description = get_s(note_entity, DESCRIPTION)?;
+        let created: u32 = get_n(note_entity, CREATED)?;
+        let version: u64 = get_n(note_entity, VERSION)?;
+        let position: i64 = get_n(note_entity, POSITION)?;
+        let content = get_content(note_entity)?;
+        let tags = get_ss(note_entity, TAGS);
+        Ok(Note {
+            id,
+            title,
+            description,
+            created,
+            version,
+            position,
+            content,
+            tags,
+        })
+    }
 }
 
-impl From<&HashMap<String, AttributeValue>> for Note {
-    fn from(note_entity: &HashMap<String, AttributeValue>) -> Self {
-        let title = note_entity[TITLE].as_s().unwrap().to_owned();
-        let created: u32 = note_entity[CREATED].as_n().unwrap().parse().unwrap();
-        Note { title, created }
+/// The tags of a document, group or note after adding or removing one.
+#[derive(SerJson, Clone)]
+pub struct Tags {
+    tags: Vec<String>,
+    pub version: u64,
+}
+
+/// A note found through one of its tags, with the ids leading to it.
+#[derive(SerJson, Clone)]
+pub struct TaggedNote {
+    #[nserde(rename = "documentId")]
+    document_id: String,
+    #[nserde(rename = "groupId")]
+    group_id: String,
+    note: Note,
+}
+
+impl TaggedNote {
+    pub(crate) fn new(document_id: String, group_id: String, note: Note) -> Self {
+        Self {
+            document_id,
+            group_id,
+            note,
+        }
     }
 }
 
+/// A page of `GET /notes`; `next` is the cursor for the following page.
 #[derive(SerJson, Clone)]
+pub struct NotePage {
+    notes: Vec<TaggedNote>,
+    next: Option<String>,
+}
+
+impl NotePage {
+    pub(crate) fn new(notes: Vec<TaggedNote>, next: Option<String>) -> Self {
+        Self { notes, next }
+    }
+}
+
+/// An entry of a document's access list.
+#[derive(SerJson, Clone)]
+pub struct Share {
+    pub user: String,
+    pub role: String,
+}
+
+impl Share {
+    fn owner(document: &Item) -> Result<Self> {
+        Ok(Share {
+            user: get_s(document, OWNER)?,
+            role: Role::Owner.to_string(),
+        })
+    }
+}
+
+impl TryFrom<&HashMap<String, AttributeValue>> for Share {
+    type Error = ApiError;
+
+    fn try_from(share_entity: &HashMap<String, AttributeValue>) -> Result<Self> {
+        let user = get_s(share_entity, USER)?;
+        let role = get_s(share_entity, ROLE)?;
+        Ok(Share { user, role })
+    }
+}
+
+#[derive(SerJson, Clone)]
+#[nserde(transparent)]
 pub struct Documents(Vec<Document>);
 
 impl Documents {
@@ -150,32 +687,246 @@ impl DerefMut for Documents {
     }
 }
 
-impl From<Vec<HashMap<String, AttributeValue>>> for Documents {
-    fn from(items: Vec<HashMap<String, AttributeValue>>) -> Self {
+impl TryFrom<Vec<HashMap<String, AttributeValue>>> for Documents {
+    type Error = ApiError;
+
+    fn try_from(items: Vec<HashMap<String, AttributeValue>>) -> Result<Self> {
         let mut lookup = MultiMap::new();
         let mut documents = Documents::new();
         for item in items {
+            // Access list entries share the partition but are not part of
+            // the document itself.
+            if item.contains_key(ROLE) {
+                continue;
+            }
             if item.contains_key(PARENT) {
-                let value = item[PARENT].as_s().unwrap();
-                lookup.insert(value.clone(), item);
+                let value = get_s(&item, PARENT)?;
+                lookup.insert(value, item);
             } else {
-                let document = Document::from(item);
+                let document = Document::try_from(item)?;
                 documents.push(document);
             }
         }
 
         for document in documents.iter_mut() {
-            let groups = lookup.get_vec(&document.sk).unwrap();
+            let Some(items) = lookup.get_vec(&document.sk) else {
+                continue;
+            };
 
-            for group in groups {
-                let mut group = Group::from(group);
+            let mut groups = Vec::with_capacity(items.len());
+            for group in items {
+                let mut group = Group::try_from(group)?;
                 if let Some(notes) = lookup.get_vec(&group.sk) {
-                    let notes: Vec<Note> = notes.iter().map(Note::from).collect();
+                    let notes = notes.iter().map(Note::try_from).collect::<Result<_>>()?;
                     group.set_notes(notes);
                 }
-                document.add_group(group);
+                groups.push(group);
             }
+            document.set_groups(groups);
         }
-        documents
+        Ok(documents)
+    }
+}
+
+/// A document, group or note whose title or description matched a search,
+/// with the ids leading to it.
+#[derive(SerJson, Clone)]
+pub struct SearchHit {
+    kind: String,
+    #[nserde(rename = "documentId")]
+    document_id: String,
+    #[nserde(rename = "groupId")]
+    group_id: Option<String>,
+    #[nserde(rename = "noteId")]
+    note_id: Option<String>,
+    title: String,
+}
+
+impl TryFrom<&HashMap<String, AttributeValue>> for SearchHit {
+    type Error = ApiError;
+
+    fn try_from(entry: &HashMap<String, AttributeValue>) -> Result<Self> {
+        Ok(SearchHit {
+            kind: get_s(entry, search_index::KIND)?,
+            document_id: get_s(entry, search_index::DOCUMENT_ID)?,
+            group_id: get_s(entry, search_index::GROUP_ID).ok(),
+            note_id: get_s(entry, search_index::NOTE_ID).ok(),
+            title: get_s(entry, TITLE)?,
+        })
+    }
+}
+
+/// The response of `GET /search`.
+#[derive(SerJson, Clone)]
+pub struct SearchResults {
+    hits: Vec<SearchHit>,
+}
+
+impl SearchResults {
+    pub(crate) fn new(hits: Vec<SearchHit>) -> Self {
+        Self { hits }
+    }
+}
+
+/// A document, group or note in the trash, with the ids leading to it.
+#[derive(SerJson, Clone)]
+pub struct TrashedItem {
+    kind: String,
+    #[nserde(rename = "documentId")]
+    document_id: String,
+    #[nserde(rename = "groupId")]
+    group_id: Option<String>,
+    #[nserde(rename = "noteId")]
+    note_id: Option<String>,
+    title: String,
+    trashed: i64,
+    #[nserde(rename = "trashedBy")]
+    trashed_by: String,
+    #[nserde(rename = "expiresAt")]
+    expires_at: i64,
+}
+
+impl TryFrom<&HashMap<String, AttributeValue>> for TrashedItem {
+    type Error = ApiError;
+
+    fn try_from(item: &HashMap<String, AttributeValue>) -> Result<Self> {
+        let sk = get_s(item, SK)?;
+        let kind = entity_of(&sk).to_lowercase();
+        let (group_id, note_id) = match kind.as_str() {
+            "group" => (Some(id_of(&sk).to_string()), None),
+            "note" => (
+                Some(id_of(&get_s(item, PARENT)?).to_string()),
+                Some(id_of(&sk).to_string()),
+            ),
+            _ => (None, None),
+        };
+        Ok(TrashedItem {
+            kind,
+            document_id: id_of(&get_s(item, PK)?).to_string(),
+            group_id,
+            note_id,
+            title: get_s(item, TITLE)?,
+            trashed: get_n(item, TRASHED)?,
+            trashed_by: get_s(item, TRASHED_BY)?,
+            expires_at: get_n(item, EXPIRES_AT)?,
+        })
+    }
+}
+
+/// A past version of a document, group or note.
+#[derive(SerJson, Clone)]
+pub struct Revision {
+    pub version: u64,
+    title: String,
+    description: Option<String>,
+    content: Option<NoteContent>,
+    tags: Vec<String>,
+    #[nserde(rename = "updatedBy")]
+    updated_by: Option<String>,
+    #[nserde(rename = "lastUpdated")]
+    last_updated: Option<i64>,
+    #[nserde(rename = "revisedBy")]
+    revised_by: String,
+    revised: i64,
+    changed: Vec<String>,
+}
+
+impl TryFrom<&HashMap<String, AttributeValue>> for Revision {
+    type Error = ApiError;
+
+    fn try_from(revision: &HashMap<String, AttributeValue>) -> Result<Self> {
+        Ok(Revision {
+            version: get_n(revision, VERSION)?,
+            title: get_s(revision, TITLE)?,
+            description: get_s(revision, DESCRIPTION).ok(),
+            content: get_content(revision)?,
+            tags: get_ss(revision, TAGS),
+            updated_by: get_s(revision, UPDATED_BY).ok(),
+            last_updated: get_n(revision, LAST_UPDATED).ok(),
+            revised_by: get_s(revision, revisions::REVISED_BY)?,
+            revised: get_n(revision, revisions::REVISED)?,
+            changed: get_ss(revision, revisions::CHANGED),
+        })
+    }
+}
+
+/// A page of `GET .../revisions`, newest first; `next` is the cursor for the
+/// following page.
+#[derive(SerJson, Clone)]
+pub struct RevisionPage {
+    revisions: Vec<Revision>,
+    next: Option<String>,
+}
+
+impl RevisionPage {
+    pub(crate) fn new(revisions: Vec<Revision>, next: Option<String>) -> Self {
+        Self { revisions, next }
+    }
+}
+
+/// Something someone did to a document, one of its groups, notes or shares,
+/// addressed by the `path` of its route.
+#[derive(SerJson, Clone)]
+pub struct ActivityEvent {
+    id: String,
+    actor: String,
+    action: String,
+    path: String,
+    timestamp: i64,
+    summary: String,
+}
+
+impl TryFrom<&HashMap<String, AttributeValue>> for ActivityEvent {
+    type Error = ApiError;
+
+    fn try_from(event: &HashMap<String, AttributeValue>) -> Result<Self> {
+        Ok(ActivityEvent {
+            id: id_of(&get_s(event, SK)?).to_string(),
+            actor: get_s(event, activity::ACTOR)?,
+            action: get_s(event, activity::ACTION)?,
+            path: get_s(event, activity::PATH)?,
+            timestamp: get_n(event, activity::TIMESTAMP)?,
+            summary: get_s(event, activity::SUMMARY)?,
+        })
+    }
+}
+
+/// A page of `GET .../activity`, newest first; `next` is the cursor for the
+/// following page.
+#[derive(SerJson, Clone)]
+pub struct ActivityPage {
+    events: Vec<ActivityEvent>,
+    next: Option<String>,
+}
+
+impl ActivityPage {
+    pub(crate) fn new(events: Vec<ActivityEvent>, next: Option<String>) -> Self {
+        Self { events, next }
+    }
+}
+
+/// A page of `GET /trash`; `next` is the cursor for the following page.
+#[derive(SerJson, Clone)]
+pub struct TrashPage {
+    items: Vec<TrashedItem>,
+    next: Option<String>,
+}
+
+impl TrashPage {
+    pub(crate) fn new(items: Vec<TrashedItem>, next: Option<String>) -> Self {
+        Self { items, next }
+    }
+}
+
+/// A page of `GET /documents`; `next` is the cursor for the following page.
+#[derive(SerJson, Clone)]
+pub struct DocumentPage {
+    documents: Documents,
+    next: Option<String>,
+}
+
+impl DocumentPage {
+    pub(crate) fn new(documents: Documents, next: Option<String>) -> Self {
+        Self { documents, next }
     }
 }
@@ -1,42 +1,72 @@
+use std::sync::Arc;
+
 use chrono::Utc;
 
-use lambda_http::{http::Result, Body, Response};
 use nanoserde::{DeJson, SerJson};
 
-use crate::{controllers::NoteReq, repositories::document_repository::DatabaseRepository};
+use crate::{
+    auth::Caller,
+    controllers::{
+        decode_cursor, encode_cursor, NoteContent, NoteListReq, NoteMoveReq, NoteReq, NoteUpdateReq,
+    },
+    error::{ApiError, Result},
+    repositories::{
+        group_key, new_id, note_key,
+        search_index::{DOCUMENT_ID, GROUP_ID, NOTE_ID},
+        Item, NotesStore, Placement, TagChange,
+    },
+};
+
+use super::{
+    change_tags, ensure_version, fetch_accessible_document, get_s,
+    groups_service::fetch_group_of_document, position_among, tagged_entries, visible_scopes,
+    NotePage, Role, TaggedNote, Tags, PARENT, SK,
+};
 
-use super::SK;
+/// The ids leading to a note, as they appear in its route.
+#[derive(Clone, Copy)]
+pub struct NotePath<'a> {
+    pub document_id: &'a str,
+    pub group_id: &'a str,
+    pub note_id: &'a str,
+}
 
-pub struct NotesService<'a> {
-    database_repository: &'a DatabaseRepository,
+pub struct NotesService {
+    database_repository: Arc<dyn NotesStore>,
 }
 
 #[derive(SerJson, DeJson, Clone)]
 pub struct Note {
+    pub id: String,
     pub title: String,
     pub description: String,
     pub created: i64,
     pub created_by: String,
     pub updated_by: String,
     pub parent: String,
+    #[nserde(default_with = "Option::default")]
+    pub content: Option<NoteContent>,
+    pub tags: Vec<String>,
 }
 
-impl From<&NoteReq> for Note {
-    fn from(value: &NoteReq) -> Self {
-        let timestamp = Utc::now().timestamp();
+impl Note {
+    pub(crate) fn new(value: &NoteReq, parent: String, created_by: &str, timestamp: i64) -> Self {
         Note {
+            id: new_id(),
             title: value.title.clone(),
             description: value.description.clone(),
             created: timestamp,
-            created_by: value.created_by.clone(),
-            updated_by: value.updated_by.clone(),
-            parent: String::from(""),
+            created_by: created_by.to_string(),
+            updated_by: created_by.to_string(),
+            parent,
+            content: value.content.clone(),
+            tags: value.tags.clone(),
         }
     }
 }
 
-impl<'a> NotesService<'a> {
-    pub fn new(database_repository: &'a DatabaseRepository) -> Self {
+impl NotesService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
         Self {
             database_repository,
         }
@@ -44,41 +74,278 @@ impl<'a> NotesService<'a> {
 
     pub(crate) async fn save(
         &self,
-        doc_id: i32,
-        group_id: i32,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
         note_req: &NoteReq,
-    ) -> Result<Response<Body>> {
-        let documents = self
-            .database_repository
-            .fetch_document_by_id(&doc_id.to_string())
-            .await;
-
-        if documents.is_empty() {
-            todo!("Throw a cool error here");
+    ) -> Result<super::Note> {
+        let group = fetch_group_of_document(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            group_id,
+            Role::Editor,
+        )
+        .await?;
+
+        let note = Note::new(
+            note_req,
+            get_s(&group, SK)?,
+            &caller.id,
+            Utc::now().timestamp(),
+        );
+        let item = self.database_repository.save_note(doc_id, &note).await?;
+        super::Note::try_from(&item)
+    }
+
+    pub(crate) async fn fetch_by_id(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        note_id: &str,
+    ) -> Result<super::Note> {
+        let note = fetch_note_of_group(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            group_id,
+            note_id,
+            Role::Viewer,
+        )
+        .await?;
+        super::Note::try_from(&note)
+    }
+
+    pub(crate) async fn update(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        note_id: &str,
+        update: &NoteUpdateReq,
+        if_match: Option<u64>,
+    ) -> Result<super::Note> {
+        fetch_note_of_group(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            group_id,
+            note_id,
+            Role::Editor,
+        )
+        .await?;
+        self.database_repository
+            .update_note(doc_id, group_id, note_id, update, &caller.id, if_match)
+            .await?;
+        self.fetch_by_id(caller, doc_id, group_id, note_id).await
+    }
+
+    /// Moves the note to `position` among the notes of its group, or of
+    /// another group, which may be in another document the caller can edit.
+    pub(crate) async fn move_to(
+        &self,
+        caller: &Caller,
+        path: &NotePath<'_>,
+        move_req: &NoteMoveReq,
+        if_match: Option<u64>,
+    ) -> Result<super::Note> {
+        let store = self.database_repository.as_ref();
+        let NotePath {
+            document_id,
+            group_id,
+            note_id,
+        } = *path;
+        let note = fetch_note_of_group(store, caller, document_id, group_id, note_id, Role::Editor)
+            .await?;
+        ensure_version(&note, if_match, "Note", note_id)?;
+
+        let to_document = move_req.document_id.as_deref().unwrap_or(document_id);
+        let to_group = move_req.group_id.as_deref().unwrap_or(group_id);
+        if (to_document, to_group) != (document_id, group_id) {
+            fetch_group_of_document(store, caller, to_document, to_group, Role::Editor).await?;
+        }
+        let items = store.fetch_group_with_notes(to_document, to_group).await?;
+        let position = position_among(
+            store,
+            to_document,
+            &items,
+            &group_key(to_group),
+            &note_key(to_group, note_id),
+            move_req.position,
+        )
+        .await?;
+
+        let to = Placement {
+            document_id: to_document,
+            group_id: Some(to_group),
+            position,
+        };
+        store
+            .move_note(document_id, group_id, note_id, &to, &caller.id, if_match)
+            .await?;
+        self.fetch_by_id(caller, to_document, to_group, note_id)
+            .await
+    }
+
+    pub(crate) async fn change_tags(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        note_id: &str,
+        change: TagChange<'_>,
+        if_match: Option<u64>,
+    ) -> Result<Tags> {
+        let note = fetch_note_of_group(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            group_id,
+            note_id,
+            Role::Editor,
+        )
+        .await?;
+        change_tags(
+            self.database_repository.as_ref(),
+            &note,
+            change,
+            caller,
+            if_match,
+        )
+        .await
+    }
+
+    /// Pages through the notes carrying the tag in every document the caller
+    /// can read, in document order.
+    pub(crate) async fn list_tagged(&self, caller: &Caller, req: &NoteListReq) -> Result<NotePage> {
+        let store = self.database_repository.as_ref();
+        let mut after = req.cursor.as_deref().map(decode_cursor).transpose()?;
+        let scopes = visible_scopes(store, caller).await?;
+
+        let mut notes = Vec::new();
+        let mut last = None;
+        let mut next = None;
+        'entries: loop {
+            let entries = tagged_entries(
+                store,
+                &scopes,
+                &req.tag,
+                "note",
+                after.as_deref(),
+                req.limit + 1,
+            )
+            .await?;
+            let exhausted = entries.len() <= req.limit;
+            for entry in entries.iter() {
+                if notes.len() == req.limit {
+                    next = last.as_deref().map(encode_cursor);
+                    break 'entries;
+                }
+                after = Some(get_s(entry, SK)?);
+                let document_id = get_s(entry, DOCUMENT_ID)?;
+                let group_id = get_s(entry, GROUP_ID)?;
+                let note = match store
+                    .fetch_note_by_id(&document_id, &group_id, &get_s(entry, NOTE_ID)?)
+                    .await
+                {
+                    Ok(note) => super::Note::try_from(&note)?,
+                    // Deleted since it was listed.
+                    Err(ApiError::NotFound(_)) => continue,
+                    Err(err) => return Err(err),
+                };
+                notes.push(TaggedNote::new(document_id, group_id, note));
+                last = after.clone();
+            }
+            if exhausted {
+                break;
+            }
         }
-        let groups = self.database_repository.fetch_group_by_id(group_id).await;
-        if groups.is_empty() {
-            todo!("Throw a cool error here");
+        Ok(NotePage::new(notes, next))
+    }
+
+    pub(crate) async fn delete(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        group_id: &str,
+        note_id: &str,
+        if_match: Option<u64>,
+    ) -> Result<()> {
+        let note = fetch_note_of_group(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            group_id,
+            note_id,
+            Role::Editor,
+        )
+        .await?;
+        ensure_version(&note, if_match, "Note", note_id)?;
+        self.database_repository
+            .trash(doc_id, &note_key(group_id, note_id), &caller.id, if_match)
+            .await
+    }
+
+    /// Takes a deleted note back out of the trash. A note whose group is in
+    /// the trash as well has to wait for the group to be restored first.
+    pub(crate) async fn restore(
+        &self,
+        caller: &Caller,
+        path: &NotePath<'_>,
+    ) -> Result<super::Note> {
+        let store = self.database_repository.as_ref();
+        let NotePath {
+            document_id,
+            group_id,
+            note_id,
+        } = *path;
+        fetch_accessible_document(store, caller, document_id, Role::Editor).await?;
+        let note_sk = note_key(group_id, note_id);
+        store.fetch_trashed(document_id, &note_sk).await?;
+        match store.fetch_group_by_id(document_id, group_id).await {
+            Ok(_) => {}
+            Err(ApiError::NotFound(_))
+                if store
+                    .fetch_trashed(document_id, &group_key(group_id))
+                    .await
+                    .is_ok() =>
+            {
+                return Err(ApiError::Conflict(format!(
+                    "Group '{}' is in the trash, restore it first",
+                    group_id
+                )));
+            }
+            Err(err) => return Err(err),
         }
 
-        // Check that group and document actually belong together
-
-        let mut note = Note::from(note_req);
-        note.parent = groups
-            .first()
-            .unwrap()
-            .get(SK)
-            .unwrap()
-            .as_s()
-            .unwrap()
-            .to_string();
-        self.database_repository.save_note(&note).await.unwrap();
-
-        let response = Response::builder()
-            .status(200)
-            .header("content-type", "application/json")
-            .body(Body::Empty)
-            .unwrap();
-        Ok(response)
+        store.restore(document_id, &note_sk, &caller.id).await?;
+        self.fetch_by_id(caller, document_id, group_id, note_id)
+            .await
+    }
+}
+
+/// Fetches the note, making sure the whole document/group/note path actually
+/// hangs together and the caller holds the `required` role.
+pub(crate) async fn fetch_note_of_group(
+    database_repository: &dyn NotesStore,
+    caller: &Caller,
+    doc_id: &str,
+    group_id: &str,
+    note_id: &str,
+    required: Role,
+) -> Result<Item> {
+    let group =
+        fetch_group_of_document(database_repository, caller, doc_id, group_id, required).await?;
+    let note = database_repository
+        .fetch_note_by_id(doc_id, group_id, note_id)
+        .await?;
+
+    if get_s(&note, PARENT)? != get_s(&group, SK)? {
+        return Err(ApiError::Validation(format!(
+            "Note '{}' does not belong to group '{}'",
+            note_id, group_id
+        )));
     }
+    Ok(note)
 }
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use crate::{
+    auth::Caller,
+    controllers::{decode_cursor, encode_cursor, RevisionListReq},
+    error::{ApiError, Result},
+    repositories::{document_key, group_key, note_key, NotesStore},
+};
+
+use super::{
+    fetch_accessible_document, groups_service::fetch_group_of_document,
+    notes_service::fetch_note_of_group, notes_service::NotePath, Revision, RevisionPage, Role,
+};
+
+/// The document, group or note whose history a route is about.
+#[derive(Clone, Copy)]
+pub enum Revised<'a> {
+    Document(&'a str),
+    Group(&'a str, &'a str),
+    Note(NotePath<'a>),
+}
+
+impl Revised<'_> {
+    fn document_id(&self) -> &str {
+        match self {
+            Revised::Document(document_id) | Revised::Group(document_id, _) => document_id,
+            Revised::Note(path) => path.document_id,
+        }
+    }
+
+    /// The id its revisions are stored under.
+    fn id(&self) -> &str {
+        match self {
+            Revised::Document(document_id) => document_id,
+            Revised::Group(_, group_id) => group_id,
+            Revised::Note(path) => path.note_id,
+        }
+    }
+
+    fn sk(&self) -> String {
+        match self {
+            Revised::Document(document_id) => document_key(document_id),
+            Revised::Group(_, group_id) => group_key(group_id),
+            Revised::Note(path) => note_key(path.group_id, path.note_id),
+        }
+    }
+}
+
+pub struct RevisionsService {
+    database_repository: Arc<dyn NotesStore>,
+}
+
+impl RevisionsService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
+        Self {
+            database_repository,
+        }
+    }
+
+    /// Lists the revisions of the document, group or note, newest first.
+    pub(crate) async fn list(
+        &self,
+        caller: &Caller,
+        revised: Revised<'_>,
+        req: &RevisionListReq,
+    ) -> Result<RevisionPage> {
+        self.ensure_access(caller, revised, Role::Viewer).await?;
+        let before = req
+            .cursor
+            .as_deref()
+            .map(|cursor| {
+                decode_cursor(cursor)?
+                    .parse()
+                    .map_err(|_| ApiError::BadRequest(String::from("Invalid cursor")))
+            })
+            .transpose()?;
+
+        let page = self
+            .database_repository
+            .list_revisions(revised.id(), req.limit, before)
+            .await?;
+        let revisions = page
+            .items
+            .iter()
+            .map(Revision::try_from)
+            .collect::<Result<_>>()?;
+        let next = page.next.as_deref().map(encode_cursor);
+        Ok(RevisionPage::new(revisions, next))
+    }
+
+    pub(crate) async fn fetch(
+        &self,
+        caller: &Caller,
+        revised: Revised<'_>,
+        version: u64,
+    ) -> Result<Revision> {
+        self.ensure_access(caller, revised, Role::Viewer).await?;
+        let revision = self
+            .database_repository
+            .fetch_revision(revised.id(), version)
+            .await?;
+        Revision::try_from(&revision)
+    }
+
+    /// Puts the fields of the document, group or note back the way they
+    /// were at `version`, which callers then fetch to answer with.
+    pub(crate) async fn revert(
+        &self,
+        caller: &Caller,
+        revised: Revised<'_>,
+        version: u64,
+        if_match: Option<u64>,
+    ) -> Result<()> {
+        self.ensure_access(caller, revised, Role::Editor).await?;
+        self.database_repository
+            .revert(
+                revised.document_id(),
+                &revised.sk(),
+                version,
+                &caller.id,
+                if_match,
+            )
+            .await
+    }
+
+    /// Checks the caller holds the `required` role on the document and that
+    /// the group or note is actually part of it.
+    async fn ensure_access(
+        &self,
+        caller: &Caller,
+        revised: Revised<'_>,
+        required: Role,
+    ) -> Result<()> {
+        let store = self.database_repository.as_ref();
+        match revised {
+            Revised::Document(document_id) => {
+                fetch_accessible_document(store, caller, document_id, required).await?;
+            }
+            Revised::Group(document_id, group_id) => {
+                fetch_group_of_document(store, caller, document_id, group_id, required).await?;
+            }
+            Revised::Note(path) => {
+                fetch_note_of_group(
+                    store,
+                    caller,
+                    path.document_id,
+                    path.group_id,
+                    path.note_id,
+                    required,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
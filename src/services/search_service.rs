@@ -0,0 +1,63 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    auth::Caller,
+    controllers::SearchReq,
+    error::Result,
+    repositories::{Item, NotesStore},
+};
+
+use super::{get_s, visible_scopes, Scope, SearchHit, SearchResults, SK};
+
+pub struct SearchService {
+    database_repository: Arc<dyn NotesStore>,
+}
+
+impl SearchService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
+        Self {
+            database_repository,
+        }
+    }
+
+    /// Returns the documents, groups and notes the caller can see that hold
+    /// every token searched for, in document order.
+    pub(crate) async fn search(&self, caller: &Caller, req: &SearchReq) -> Result<SearchResults> {
+        let mut hits = Vec::new();
+        for scope in visible_scopes(self.database_repository.as_ref(), caller).await? {
+            for entry in self.search_scope(&scope, &req.tokens).await? {
+                hits.push(SearchHit::try_from(&entry)?);
+                if hits.len() == req.limit {
+                    return Ok(SearchResults::new(hits));
+                }
+            }
+        }
+        Ok(SearchResults::new(hits))
+    }
+
+    /// Intersects the index entries of every token, keeping the entries of
+    /// the first token for the items found under all of them.
+    async fn search_scope(&self, scope: &Scope, tokens: &[String]) -> Result<Vec<Item>> {
+        let mut matches: Option<BTreeMap<String, Item>> = None;
+        for token in tokens {
+            let mut found = BTreeMap::new();
+            for entry in self.database_repository.search(&scope.owner, token).await? {
+                if scope.contains(&entry)? {
+                    found.insert(get_s(&entry, SK)?, entry);
+                }
+            }
+
+            matches = Some(match matches {
+                None => found,
+                Some(mut matches) => {
+                    matches.retain(|sk, _| found.contains_key(sk));
+                    matches
+                }
+            });
+            if matches.as_ref().is_some_and(BTreeMap::is_empty) {
+                break;
+            }
+        }
+        Ok(matches.unwrap_or_default().into_values().collect())
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use crate::{
+    auth::Caller,
+    controllers::ShareReq,
+    error::{ApiError, Result},
+    repositories::NotesStore,
+};
+
+use super::{fetch_accessible_document, get_s, Role, Share, OWNER};
+
+pub struct SharesService {
+    database_repository: Arc<dyn NotesStore>,
+}
+
+impl SharesService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
+        Self {
+            database_repository,
+        }
+    }
+
+    /// Returns who has access to the document, the owner first.
+    pub(crate) async fn list(&self, caller: &Caller, doc_id: &str) -> Result<Vec<Share>> {
+        let document = fetch_accessible_document(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            Role::Viewer,
+        )
+        .await?;
+
+        let mut shares = vec![Share::owner(&document)?];
+        for item in self.database_repository.fetch_shares(doc_id).await? {
+            shares.push(Share::try_from(&item)?);
+        }
+        Ok(shares)
+    }
+
+    /// Gives `user_id` the requested role on the document.
+    pub(crate) async fn grant(
+        &self,
+        caller: &Caller,
+        doc_id: &str,
+        user_id: &str,
+        share_req: &ShareReq,
+    ) -> Result<Share> {
+        let document = fetch_accessible_document(
+            self.database_repository.as_ref(),
+            caller,
+            doc_id,
+            Role::Owner,
+        )
+        .await?;
+
+        let role: Role = share_req.role.parse()?;
+        if get_s(&document, OWNER)? == user_id {
+            return Err(ApiError::Validation(format!(
+                "User '{}' already owns document '{}'",
+                user_id, doc_id
+            )));
+        }
+
+        let item = self
+            .database_repository
+            .save_share(doc_id, user_id, role, &caller.id)
+            .await?;
+        Share::try_from(&item)
+    }
+
+    /// Takes away `user_id`'s access to the document. The owner may revoke
+    /// anyone's access, everyone else only their own.
+    pub(crate) async fn revoke(&self, caller: &Caller, doc_id: &str, user_id: &str) -> Result<()> {
+        let required = if caller.id == user_id {
+            Role::Viewer
+        } else {
+            Role::Owner
+        };
+        fetch_accessible_document(self.database_repository.as_ref(), caller, doc_id, required)
+            .await?;
+
+        self.database_repository
+            .delete_share(doc_id, user_id, &caller.id)
+            .await
+    }
+}
@@ -0,0 +1,52 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    auth::Caller,
+    controllers::{decode_cursor, encode_cursor, TrashListReq},
+    error::Result,
+    repositories::NotesStore,
+};
+
+use super::{get_s, id_of, visible_scopes, TrashPage, TrashedItem, GSI1SK, PK};
+
+pub struct TrashService {
+    database_repository: Arc<dyn NotesStore>,
+}
+
+impl TrashService {
+    pub fn new(database_repository: Arc<dyn NotesStore>) -> Self {
+        Self {
+            database_repository,
+        }
+    }
+
+    /// Lists what was deleted from the documents the caller can see.
+    pub(crate) async fn list(&self, caller: &Caller, req: &TrashListReq) -> Result<TrashPage> {
+        let store = self.database_repository.as_ref();
+        let after = req.cursor.as_deref().map(decode_cursor).transpose()?;
+
+        let mut trashed = BTreeMap::new();
+        for scope in visible_scopes(store, caller).await? {
+            for item in store.list_trash(&scope.owner).await? {
+                let path = get_s(&item, GSI1SK)?;
+                if after.as_ref().is_none_or(|after| &path > after)
+                    && scope.contains_document(id_of(&get_s(&item, PK)?))
+                {
+                    trashed.insert(path, item);
+                }
+            }
+        }
+
+        let more = trashed.len() > req.limit;
+        let page = trashed.into_iter().take(req.limit).collect::<Vec<_>>();
+        let next = page
+            .last()
+            .filter(|_| more)
+            .map(|(path, _)| encode_cursor(path));
+        let items = page
+            .iter()
+            .map(|(_, item)| TrashedItem::try_from(item))
+            .collect::<Result<_>>()?;
+        Ok(TrashPage::new(items, next))
+    }
+}
@@ -0,0 +1,42 @@
+use std::{fs, path::PathBuf};
+
+use aws_lambda_events::dynamodb::Event;
+use lambda_runtime::{run, service_fn, Error, LambdaEvent};
+use structopt::StructOpt;
+
+use noterino_lambda::changes::{processors::LogProcessor, StreamHandler};
+
+/// Reacts to changes of the notes table, running as an AWS Lambda function
+/// fed by the table's DynamoDB stream unless `--event` is given.
+#[derive(StructOpt)]
+#[structopt(name = "streams")]
+struct Opt {
+    /// Process the stream event in this file and log the response
+    #[structopt(long)]
+    event: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .without_time()
+        .init();
+
+    let opt = Opt::from_args();
+    let handler = StreamHandler::new(vec![Box::new(LogProcessor)]);
+
+    if let Some(path) = opt.event {
+        let event: Event = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let response = handler.handle(&event).await;
+        tracing::info!("{}", serde_json::to_string_pretty(&response)?);
+    } else {
+        run(service_fn(|event: LambdaEvent<Event>| {
+            let handler = &handler;
+            async move { Ok::<_, Error>(handler.handle(&event.payload).await) }
+        }))
+        .await?;
+    }
+    Ok(())
+}
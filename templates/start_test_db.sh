@@ -19,5 +19,8 @@ echo "          |  Importing data   |"
 echo "*******************************************"
 echo ""
 aws dynamodb create-table --cli-input-yaml file://$PWD/template_local.yml --endpoint-url http://localhost:8000 | bat -l json -P
+aws dynamodb update-time-to-live --table-name notes --time-to-live-specification Enabled=true,AttributeName=expiresAt --endpoint-url http://localhost:8000 | bat -l json -P
 aws dynamodb batch-write-item --cli-input-yaml file://$PWD/mock_data.yml --endpoint-url http://localhost:8000 | bat -l json -P
+# Fills in what the migrations derive from the mock data, such as the search index
+cargo run --bin migrate -- --table-name notes --endpoint-url http://localhost:8000
 aws dynamodb scan --table-name notes --endpoint-url http://localhost:8000 | bat -l json -P